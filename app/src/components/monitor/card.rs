@@ -1,5 +1,8 @@
 use crate::components::util::combine_optional_class;
-use crate::types::{get_monitor, Monitor, MonitorStatus};
+use crate::types::{
+    check_monitor_now, get_monitor, get_monitor_uptime, pause_monitor, resume_monitor, Monitor,
+    MonitorStatus,
+};
 use leptos::leptos_dom::warn;
 use leptos::prelude::*;
 use leptos_icons::Icon;
@@ -12,22 +15,65 @@ pub fn MonitorCard(
 ) -> impl IntoView {
     let monitor_id = monitor.name.clone();
     let (monitor, set_monitor) = signal(monitor);
+    let (uptime, set_uptime) = signal(None::<f64>);
 
     // Create a stable reference to set_monitor
-    let update_monitor = Action::new(move |_: &()| {
+    let update_monitor = Action::new({
         let monitor_id = monitor_id.clone();
-        async move {
-            match get_monitor(monitor_id).await {
-                Ok(new_monitor) => set_monitor.set(new_monitor),
-                Err(err) => {
-                    warn!("Failed to refresh monitor: {}", err);
+        move |_: &()| {
+            let monitor_id = monitor_id.clone();
+            async move {
+                match get_monitor(monitor_id.clone()).await {
+                    Ok(new_monitor) => set_monitor.set(new_monitor),
+                    Err(err) => {
+                        warn!("Failed to refresh monitor: {}", err);
+                    }
+                }
+                match get_monitor_uptime(monitor_id).await {
+                    Ok(new_uptime) => set_uptime.set(new_uptime),
+                    Err(err) => {
+                        warn!("Failed to refresh uptime: {}", err);
+                    }
+                }
+            }
+        }
+    });
+
+    // pausing or resuming, depending on whether the monitor is paused
+    let toggle_pause = Action::new({
+        let monitor_id = monitor_id.clone();
+        move |paused: &bool| {
+            let (monitor_id, paused) = (monitor_id.clone(), *paused);
+            async move {
+                let result = if paused {
+                    resume_monitor(monitor_id).await
+                } else {
+                    pause_monitor(monitor_id).await
+                };
+                match result {
+                    Ok(()) => {
+                        update_monitor.dispatch(());
+                    }
+                    Err(err) => warn!("Failed to pause or resume monitor: {}", err),
                 }
             }
         }
     });
 
+    let check_now = Action::new(move |_: &()| {
+        let monitor_id = monitor_id.clone();
+        async move {
+            match check_monitor_now(monitor_id).await {
+                Ok(status) => set_monitor.update(|m| m.current_status = Some(status)),
+                Err(err) => warn!("Failed to check monitor: {}", err),
+            }
+        }
+    });
+
     // Set up the interval using create_effect
     let effector = Effect::new(move |_| {
+        // the uptime isn't rendered on the server, fetch it right away
+        update_monitor.dispatch(());
         let _ = set_interval_with_handle(
             move || {
                 update_monitor.dispatch(());
@@ -43,10 +89,39 @@ pub fn MonitorCard(
     view! {
         <div class=combine_optional_class("card", class)>
             <div class="card-header grid grid-flow-col grid-cols-5 w-full">
-                <div class="col-span-4">{move || monitor.get().name}</div>
+                <div class="col-span-4">
+                    {move || monitor.get().name}
+                    <Show when=move || monitor().paused>
+                        <span class="badge-primary ml-2">"paused"</span>
+                    </Show>
+                </div>
                 <div class=""><MonitorStatusLight status_fn={move || monitor().current_status} /></div>
             </div>
             <div><MonitorDetail monitor=move || monitor().current_status /></div>
+            <div class="text-sm text-gray-500">{move || match uptime.get() {
+                Some(uptime) => format!("uptime (24h): {:.2}%", uptime * 100.0),
+                None => "uptime (24h): n/a".into(),
+            }}</div>
+            <div class="flex flex-row space-x-2 mt-4">
+                <button
+                    class="btn-outline"
+                    disabled=move || toggle_pause.pending().get()
+                    on:click=move |_| {
+                        toggle_pause.dispatch(monitor().paused);
+                    }
+                >
+                    {move || if monitor().paused { "Resume" } else { "Pause" }}
+                </button>
+                <button
+                    class="btn-outline"
+                    disabled=move || check_now.pending().get()
+                    on:click=move |_| {
+                        check_now.dispatch(());
+                    }
+                >
+                    "Check now"
+                </button>
+            </div>
         </div>
     }
 }
@@ -57,18 +132,24 @@ pub fn MonitorDetail(
 ) -> impl IntoView {
     view! {
         <div>{move || match monitor() {
-            Some(st) => {
-                match st {
-                    MonitorStatus::Up { .. } => "up".into(),
-                    MonitorStatus::Down { error_reason,.. } => format!("down: {error_reason}"),
-                    MonitorStatus::Unknown => "unknown".into(),
-                }
-            },
+            Some(st) => describe_status(&st),
             None => "unknown".into(),
         }}</div>
     }
 }
 
+fn describe_status(status: &MonitorStatus) -> String {
+    match status {
+        MonitorStatus::Up { .. } => "up".into(),
+        MonitorStatus::Degraded { error_reason, .. } => format!("degraded: {error_reason}"),
+        MonitorStatus::Down { error_reason, .. } => format!("down: {error_reason}"),
+        MonitorStatus::Maintenance { window, status, .. } => {
+            format!("maintenance ({window}), {}", describe_status(status))
+        }
+        MonitorStatus::Unknown => "unknown".into(),
+    }
+}
+
 #[component]
 pub fn MonitorStatusLight(status_fn: impl Fn() -> Option<MonitorStatus>) -> impl IntoView {
     let base_classes = "items-center flex flex-row justify-end";
@@ -82,10 +163,18 @@ pub fn MonitorStatusLight(status_fn: impl Fn() -> Option<MonitorStatus>) -> impl
                 status_icon = icondata::IoCheckmarkCircle;
                 text_color = "text-green-500";
             }
+            MonitorStatus::Degraded { .. } => {
+                status_icon = icondata::BiErrorSolid;
+                text_color = "text-yellow-500";
+            }
             MonitorStatus::Down { .. } => {
                 status_icon = icondata::BiErrorCircleSolid;
                 text_color = "text-red-500";
             }
+            MonitorStatus::Maintenance { .. } => {
+                status_icon = icondata::FaWrenchSolid;
+                text_color = "text-blue-500";
+            }
             MonitorStatus::Unknown => {}
         },
     }
@@ -12,7 +12,7 @@ pub mod types;
 use crate::pages::home::*;
 use crate::pages::login::LoginPage;
 use crate::pages::not_found::NotFoundPage;
-use crate::types::DbFactory;
+use crate::types::{DbFactory, MonitorControl};
 use leptos::prelude::*;
 use leptos_meta::{provide_meta_context, MetaTags, Stylesheet, Title};
 use leptos_router::{
@@ -22,6 +22,7 @@ use leptos_router::{
 use std::sync::Arc;
 
 pub type DbFactoryPointer = Arc<dyn DbFactory + Send + Sync>;
+pub type MonitorControlPointer = Arc<dyn MonitorControl + Send + Sync>;
 
 pub fn shell(options: LeptosOptions) -> impl IntoView {
     view! {
@@ -1,6 +1,7 @@
 #[cfg(feature = "ssr")]
 use crate::state::ServerState;
 use leptos::prelude::*;
+use std::collections::BTreeMap;
 use std::time::Duration;
 
 #[api_model]
@@ -9,23 +10,94 @@ pub struct Monitor {
     pub current_status: Option<MonitorStatus>,
     pub api_version: String,
     pub kind: String,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
     pub configuration: Option<MonitorConfiguration>,
     pub spec: serde_json::Value,
+    /// Paused monitors aren't checked until resumed.
+    #[serde(default)]
+    pub paused: bool,
+}
+
+impl Monitor {
+    /// True if every key/value in `selector` is present in this monitor's labels.
+    pub fn matches_labels(&self, selector: &BTreeMap<String, String>) -> bool {
+        selector
+            .iter()
+            .all(|(k, v)| self.labels.get(k).is_some_and(|label| label == v))
+    }
 }
 
 #[api_model]
 pub enum MonitorStatus {
     Up {
         checked_at: chrono::DateTime<chrono::Utc>,
+        details: Option<serde_json::Value>,
+    },
+    Degraded {
+        checked_at: chrono::DateTime<chrono::Utc>,
+        error_reason: String,
+        details: Option<serde_json::Value>,
     },
     Down {
         checked_at: chrono::DateTime<chrono::Utc>,
         error_reason: String,
+        details: Option<serde_json::Value>,
+    },
+    /// Checked during a maintenance window. `status` is what the check
+    /// found, which doesn't count towards uptime.
+    Maintenance {
+        checked_at: chrono::DateTime<chrono::Utc>,
+        window: String,
+        status: Box<MonitorStatus>,
     },
     #[default]
     Unknown,
 }
 
+impl MonitorStatus {
+    /// Tags the status as checked during the maintenance `window`.
+    pub fn in_maintenance(self, window: String) -> MonitorStatus {
+        let checked_at = match &self {
+            MonitorStatus::Up { checked_at, .. }
+            | MonitorStatus::Degraded { checked_at, .. }
+            | MonitorStatus::Down { checked_at, .. }
+            | MonitorStatus::Maintenance { checked_at, .. } => *checked_at,
+            MonitorStatus::Unknown => chrono::Utc::now(),
+        };
+        MonitorStatus::Maintenance {
+            checked_at,
+            window,
+            status: Box::new(self.underlying().clone()),
+        }
+    }
+
+    /// What the check found, looking through a maintenance tag.
+    pub fn underlying(&self) -> &MonitorStatus {
+        match self {
+            MonitorStatus::Maintenance { status, .. } => status.underlying(),
+            status => status,
+        }
+    }
+}
+
+/// Share of checks that were up or degraded, ignoring checks made during
+/// maintenance. `None` if no check counts.
+pub fn uptime(statuses: &[MonitorStatus]) -> Option<f64> {
+    let (mut up, mut counted) = (0usize, 0usize);
+    for status in statuses {
+        match status {
+            MonitorStatus::Up { .. } | MonitorStatus::Degraded { .. } => {
+                up += 1;
+                counted += 1;
+            }
+            MonitorStatus::Down { .. } => counted += 1,
+            MonitorStatus::Maintenance { .. } | MonitorStatus::Unknown => {}
+        }
+    }
+    (counted > 0).then(|| up as f64 / counted as f64)
+}
+
 #[server]
 pub async fn get_monitors() -> Result<Vec<Monitor>, ServerFnError> {
     let server_state = expect_context::<ServerState>();
@@ -42,18 +114,80 @@ pub async fn get_monitor(id: String) -> Result<Monitor, ServerFnError> {
     Ok(monitor_repository.get_monitor(id).await?)
 }
 
+#[server]
+pub async fn pause_monitor(id: String) -> Result<(), ServerFnError> {
+    let control = expect_context::<crate::MonitorControlPointer>();
+
+    control.pause(&id).await.map_err(ServerFnError::new)
+}
+
+#[server]
+pub async fn resume_monitor(id: String) -> Result<(), ServerFnError> {
+    let control = expect_context::<crate::MonitorControlPointer>();
+
+    control.resume(&id).await.map_err(ServerFnError::new)
+}
+
+#[server]
+pub async fn check_monitor_now(id: String) -> Result<MonitorStatus, ServerFnError> {
+    let control = expect_context::<crate::MonitorControlPointer>();
+
+    control.check_now(&id).await.map_err(ServerFnError::new)
+}
+
+/// Uptime over the last day, see [`uptime`].
+#[server]
+pub async fn get_monitor_uptime(id: String) -> Result<Option<f64>, ServerFnError> {
+    let server_state = expect_context::<ServerState>();
+    let monitor_repository = server_state.db_factory.get_monitor_repository();
+
+    let since = chrono::Utc::now() - chrono::Duration::hours(24);
+    let statuses = monitor_repository.get_statuses_since(id, since).await?;
+    Ok(uptime(&statuses))
+}
+
 #[api_model]
 pub struct MonitorConfiguration {
-    #[serde(with = "humantime_serde")]
+    #[serde(default, with = "humantime_serde")]
     pub check_interval: Option<Duration>,
+    /// Cron expression the checks run on, instead of every `check_interval`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<String>,
+    /// IANA timezone for `schedule` and `active_hours`, UTC if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    /// Checks only run inside these windows, always if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub active_hours: Vec<ActiveHours>,
+}
+
+/// A daily window such as 08:00 to 20:00 on `mon-fri`. A window ending
+/// before it starts runs past midnight.
+#[api_model]
+pub struct ActiveHours {
+    /// Days (`mon`) or ranges of days (`mon-fri`), every day if empty.
+    #[serde(default)]
+    pub days: Vec<String>,
+    pub from: String,
+    pub to: String,
 }
 
 impl MonitorConfiguration {
     pub fn merge_with(&mut self, other: &MonitorConfiguration) {
+        // a monitor with its own interval doesn't pick up the global schedule
+        if self.schedule.is_none() && self.check_interval.is_none() {
+            self.schedule = other.schedule.clone();
+        }
         if let Some(interval) = other.check_interval {
             if self.check_interval.is_none() {
                 self.check_interval = Some(interval);
             }
         }
+        if self.timezone.is_none() {
+            self.timezone = other.timezone.clone();
+        }
+        if self.active_hours.is_empty() {
+            self.active_hours = other.active_hours.clone();
+        }
     }
 }
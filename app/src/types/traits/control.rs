@@ -0,0 +1,13 @@
+use crate::types::MonitorStatus;
+use async_trait::async_trait;
+use std::fmt::Debug;
+
+/// Control over running monitors, for the server functions behind the UI.
+#[async_trait]
+pub trait MonitorControl: Debug {
+    /// Stops the monitor's checks until resumed, also after a restart.
+    async fn pause(&self, name: &str) -> Result<(), anyhow::Error>;
+    async fn resume(&self, name: &str) -> Result<(), anyhow::Error>;
+    /// Checks the monitor right away, paused or not, and records the result.
+    async fn check_now(&self, name: &str) -> Result<MonitorStatus, anyhow::Error>;
+}
@@ -23,6 +23,21 @@ pub trait MonitorRepository {
     async fn get_monitors(&self) -> Result<Vec<Monitor>, RepositoryError>;
     async fn get_monitor(&self, id: String) -> Result<Monitor, RepositoryError>;
     async fn create_monitor(&self, monitor: Monitor) -> Result<(), RepositoryError>;
+    async fn update_monitor_spec(
+        &self,
+        id: String,
+        api_version: String,
+        spec: serde_json::Value,
+    ) -> Result<(), RepositoryError>;
+
+    async fn set_paused(&self, id: String, paused: bool) -> Result<(), RepositoryError>;
+
+    /// Statuses logged for the monitor since `since`, oldest first.
+    async fn get_statuses_since(
+        &self,
+        monitor_id: String,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<MonitorStatus>, RepositoryError>;
 
     async fn log_status(
         &self,
@@ -1,3 +1,5 @@
+mod control;
 mod db;
 
+pub use control::*;
 pub use db::*;
@@ -12,6 +12,9 @@ pub struct Model {
     pub kind: String,
     #[sea_orm(column_type = "Float", nullable)]
     pub check_interval: Option<f32>,
+    pub labels: Option<Json>,
+    pub schedule: Option<Json>,
+    pub paused: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
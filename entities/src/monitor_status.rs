@@ -12,6 +12,7 @@ pub struct Model {
     pub status: Status,
     pub error_reason: Option<String>,
     pub monitor_id: String,
+    pub details: Option<Json>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -7,6 +7,10 @@ use sea_orm::entity::prelude::*;
 pub enum Status {
     #[sea_orm(string_value = "up")]
     Up,
+    #[sea_orm(string_value = "degraded")]
+    Degraded,
     #[sea_orm(string_value = "down")]
     Down,
+    #[sea_orm(string_value = "maintenance")]
+    Maintenance,
 }
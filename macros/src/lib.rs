@@ -1,6 +1,10 @@
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{parse_macro_input, DeriveInput};
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, Data, DeriveInput, Field, Fields, GenericArgument, Lit, LitStr,
+    PathArguments, Type,
+};
 
 /// Common derives for most structs
 #[proc_macro_attribute]
@@ -53,3 +57,267 @@ pub fn api_model(_args: TokenStream, input: TokenStream) -> TokenStream {
 
     TokenStream::from(expanded)
 }
+
+/// Turns a monitor spec struct into a monitor kind.
+///
+/// Generates a `TaskBuilder` named after the spec (`...MonitorSpec` becomes
+/// `...MonitorBuilder`) that deserializes and validates the spec, hands it
+/// to the spec's `MonitorSpec::build`, and registers itself with every
+/// `TaskFactory`. Fields can carry checks, reported as `spec.<field>: ...`:
+///
+/// - `#[check(non_empty)]` for strings and collections
+/// - `#[check(min = 1)]`, `#[check(max = 100)]`
+/// - `#[check(with = path::to::fn)]`, a `fn(&T) -> Result<(), String>`
+///
+/// Optional fields are only checked when set.
+///
+/// Kinds that need shared state, such as the database, name it with
+/// `state = Type`. The builder then holds a `Type` taken from the
+/// registration's `BuildContext` and the spec implements
+/// `StatefulMonitorSpec<Type>` instead of `MonitorSpec`.
+#[proc_macro_attribute]
+pub fn monitor_kind(args: TokenStream, input: TokenStream) -> TokenStream {
+    let mut kind: Option<LitStr> = None;
+    let mut api_version: Option<LitStr> = None;
+    let mut state: Option<Type> = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("kind") {
+            kind = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("api_version") {
+            api_version = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("state") {
+            state = Some(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            Err(meta.error("expected `kind`, `api_version` or `state`"))
+        }
+    });
+    parse_macro_input!(args with parser);
+    let mut input = parse_macro_input!(input as DeriveInput);
+
+    let (Some(kind), Some(api_version)) = (kind, api_version) else {
+        return syn::Error::new(
+            Span::call_site(),
+            "monitor_kind needs both `kind` and `api_version`",
+        )
+        .to_compile_error()
+        .into();
+    };
+
+    match expand_monitor_kind(&kind, &api_version, state.as_ref(), &mut input) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand_monitor_kind(
+    kind: &LitStr,
+    api_version: &LitStr,
+    state: Option<&Type>,
+    input: &mut DeriveInput,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let Data::Struct(data) = &mut input.data else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "monitor_kind only applies to structs",
+        ));
+    };
+    let Fields::Named(fields) = &mut data.fields else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "monitor_kind needs a struct with named fields",
+        ));
+    };
+
+    let mut checks = vec![];
+    for field in fields.named.iter_mut() {
+        checks.extend(field_checks(field)?);
+    }
+
+    let spec = &input.ident;
+    let name = spec.to_string();
+    let builder = format_ident!("{}Builder", name.strip_suffix("Spec").unwrap_or(&name));
+    let vis = &input.vis;
+    let tasks = quote!(crate::monitor::tasks);
+
+    let (builder_def, build, register) = match state {
+        None => (
+            quote!(#vis struct #builder {}),
+            quote!(#tasks::spec::MonitorSpec::build(spec, monitor).await),
+            quote!(|_context| std::sync::Arc::new(#builder {})),
+        ),
+        Some(state) => (
+            quote! {
+                #vis struct #builder {
+                    state: #state,
+                }
+            },
+            quote! {
+                #tasks::spec::StatefulMonitorSpec::<#state>::build(spec, monitor, &self.state)
+                    .await
+            },
+            quote! {
+                |context| std::sync::Arc::new(#builder {
+                    state: #tasks::spec::KindState::from_context(context),
+                })
+            },
+        ),
+    };
+
+    Ok(quote! {
+        #input
+
+        impl #tasks::spec::ValidateSpec for #spec {
+            fn validate(&self) -> Vec<#tasks::spec::FieldError> {
+                let mut errors = vec![];
+                #(#checks)*
+                errors
+            }
+        }
+
+        #[derive(Debug)]
+        #builder_def
+
+        #[#tasks::spec::async_trait]
+        impl #tasks::TaskBuilder for #builder {
+            fn get_api_version(&self) -> String {
+                #api_version.to_string()
+            }
+
+            fn get_kind(&self) -> String {
+                #kind.to_string()
+            }
+
+            async fn build(
+                &self,
+                monitor: app::types::Monitor,
+            ) -> Result<#tasks::TaskPtr, anyhow::Error> {
+                let spec = #tasks::spec::parse_spec::<#spec>(monitor.spec.clone())?;
+                #build
+            }
+        }
+
+        #tasks::spec::inventory::submit! {
+            #tasks::spec::KindRegistration {
+                builder: #register,
+            }
+        }
+    })
+}
+
+/// Strips the field's `#[check(...)]` attributes and turns them into code
+/// pushing to `errors`.
+fn field_checks(field: &mut Field) -> syn::Result<Option<proc_macro2::TokenStream>> {
+    let ident = field.ident.clone().expect("named field");
+    let path = serde_rename(field)?.unwrap_or_else(|| ident.to_string());
+    let tasks = quote!(crate::monitor::tasks);
+
+    let mut checks = vec![];
+    let mut error = None;
+    field.attrs.retain(|attr| {
+        if !attr.path().is_ident("check") {
+            return true;
+        }
+        let parsed = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("non_empty") {
+                checks.push(quote! {
+                    if value.is_empty() {
+                        errors.push(#tasks::spec::FieldError::new(#path, "must not be empty"));
+                    }
+                });
+            } else if meta.path.is_ident("min") {
+                let min: Lit = meta.value()?.parse()?;
+                checks.push(quote! {
+                    if *value < #min {
+                        errors.push(#tasks::spec::FieldError::new(
+                            #path,
+                            format!("must be at least {}", #min),
+                        ));
+                    }
+                });
+            } else if meta.path.is_ident("max") {
+                let max: Lit = meta.value()?.parse()?;
+                checks.push(quote! {
+                    if *value > #max {
+                        errors.push(#tasks::spec::FieldError::new(
+                            #path,
+                            format!("must be at most {}", #max),
+                        ));
+                    }
+                });
+            } else if meta.path.is_ident("with") {
+                let check: syn::Path = meta.value()?.parse()?;
+                checks.push(quote! {
+                    if let Err(message) = #check(value) {
+                        errors.push(#tasks::spec::FieldError::new(#path, message));
+                    }
+                });
+            } else {
+                return Err(meta.error("expected `non_empty`, `min`, `max` or `with`"));
+            }
+            Ok(())
+        });
+        if let Err(e) = parsed {
+            error.get_or_insert(e);
+        }
+        false
+    });
+    if let Some(e) = error {
+        return Err(e);
+    }
+
+    if checks.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(if is_option(&field.ty) {
+        quote! {
+            if let Some(value) = &self.#ident {
+                #(#checks)*
+            }
+        }
+    } else {
+        quote! {
+            {
+                let value = &self.#ident;
+                #(#checks)*
+            }
+        }
+    }))
+}
+
+/// The name serde uses for the field, if renamed.
+fn serde_rename(field: &Field) -> syn::Result<Option<String>> {
+    let mut rename = None;
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.input.peek(syn::Token![=]) {
+                let value = meta.value()?.parse::<LitStr>()?;
+                if meta.path.is_ident("rename") {
+                    rename = Some(value.value());
+                }
+            } else if meta.input.peek(syn::token::Paren) {
+                let _nested;
+                syn::parenthesized!(_nested in meta.input);
+            }
+            Ok(())
+        })?;
+    }
+    Ok(rename)
+}
+
+fn is_option(ty: &Type) -> bool {
+    let Type::Path(path) = ty else {
+        return false;
+    };
+    let Some(last) = path.path.segments.last() else {
+        return false;
+    };
+    last.ident == "Option"
+        && matches!(
+            &last.arguments,
+            PathArguments::AngleBracketed(args)
+                if matches!(args.args.first(), Some(GenericArgument::Type(_)))
+        )
+}
@@ -3,12 +3,26 @@ pub use sea_orm_migration::prelude::*;
 pub mod helpers;
 
 mod m20220101_000001_create_monitor_table;
+mod m20261019_000001_add_monitor_status_details;
+mod m20261019_000002_add_degraded_status;
+mod m20261019_000003_add_monitor_labels;
+mod m20261019_000004_add_monitor_schedule;
+mod m20261019_000005_add_maintenance_status;
+mod m20261019_000006_add_monitor_paused;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20220101_000001_create_monitor_table::Migration)]
+        vec![
+            Box::new(m20220101_000001_create_monitor_table::Migration),
+            Box::new(m20261019_000001_add_monitor_status_details::Migration),
+            Box::new(m20261019_000002_add_degraded_status::Migration),
+            Box::new(m20261019_000003_add_monitor_labels::Migration),
+            Box::new(m20261019_000004_add_monitor_schedule::Migration),
+            Box::new(m20261019_000005_add_maintenance_status::Migration),
+            Box::new(m20261019_000006_add_monitor_paused::Migration),
+        ]
     }
 }
@@ -0,0 +1,35 @@
+use crate::helpers::is_postgres;
+use sea_orm_migration::prelude::extension::postgres::Type;
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // sqlite stores enums as plain text, only postgres has a type to extend
+        if is_postgres(manager) {
+            manager
+                .alter_type(
+                    Type::alter()
+                        .name(MonitorStatus::Status)
+                        .add_value(Alias::new("maintenance"))
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        // postgres can't drop a value from an enum type
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum MonitorStatus {
+    Status,
+}
@@ -0,0 +1,26 @@
+//! Connects to `spec.address` and checks that the first line the server
+//! sends contains `spec.expect`.
+
+use std::io::{self, BufRead, BufReader};
+use whoopsie_plugin_sdk::{export_survey, json, Monitor, Status, TcpStream};
+
+fn read_banner(address: &str) -> io::Result<String> {
+    let mut banner = String::new();
+    BufReader::new(TcpStream::connect(address)?).read_line(&mut banner)?;
+    Ok(banner.trim_end().to_string())
+}
+
+fn survey(monitor: Monitor) -> Status {
+    let Some(address) = monitor.spec["address"].as_str() else {
+        return Status::down("spec.address is required");
+    };
+    let expect = monitor.spec["expect"].as_str().unwrap_or_default();
+
+    match read_banner(address) {
+        Ok(banner) if banner.contains(expect) => Status::up(json!({ "banner": banner })),
+        Ok(banner) => Status::down(format!("banner '{banner}' doesn't contain '{expect}'")),
+        Err(e) => Status::down(format!("{address}: {e}")),
+    }
+}
+
+export_survey!(survey);
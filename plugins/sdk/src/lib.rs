@@ -0,0 +1,182 @@
+//! Guest side of whoopsie's wasm monitor plugins.
+//!
+//! A plugin is a `cdylib` built for `wasm32-wasip1` that hands a survey
+//! function to [`export_survey!`]:
+//!
+//! ```ignore
+//! use whoopsie_plugin_sdk::{export_survey, json, Monitor, Status};
+//!
+//! fn survey(monitor: Monitor) -> Status {
+//!     Status::up(json!({ "name": monitor.name }))
+//! }
+//!
+//! export_survey!(survey);
+//! ```
+//!
+//! Every survey runs in a fresh instance, so nothing survives between them.
+//! Network access is limited to the `allowed_hosts` of the plugin's config.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+
+pub use serde_json::{json, Value};
+
+/// The monitor being surveyed.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Monitor {
+    pub name: String,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    pub spec: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Status {
+    Up {
+        details: Option<Value>,
+    },
+    Degraded {
+        reason: String,
+        details: Option<Value>,
+    },
+    Down {
+        reason: String,
+        details: Option<Value>,
+    },
+}
+
+impl Status {
+    pub fn up(details: Value) -> Status {
+        Status::Up {
+            details: Some(details),
+        }
+    }
+
+    pub fn degraded(reason: impl Into<String>) -> Status {
+        Status::Degraded {
+            reason: reason.into(),
+            details: None,
+        }
+    }
+
+    pub fn down(reason: impl Into<String>) -> Status {
+        Status::Down {
+            reason: reason.into(),
+            details: None,
+        }
+    }
+}
+
+mod host {
+    #[link(wasm_import_module = "whoopsie")]
+    extern "C" {
+        pub fn log(ptr: *const u8, len: usize);
+        pub fn tcp_connect(ptr: *const u8, len: usize) -> i32;
+        pub fn tcp_read(handle: i32, ptr: *mut u8, len: usize) -> i32;
+        pub fn tcp_write(handle: i32, ptr: *const u8, len: usize) -> i32;
+        pub fn tcp_close(handle: i32);
+        pub fn last_error(ptr: *mut u8, len: usize) -> i32;
+    }
+}
+
+/// Writes a line to the server log.
+pub fn log(message: &str) {
+    unsafe { host::log(message.as_ptr(), message.len()) }
+}
+
+fn last_error() -> io::Error {
+    let mut buf = vec![0u8; 512];
+    let len = unsafe { host::last_error(buf.as_mut_ptr(), buf.len()) };
+    buf.truncate((len.max(0) as usize).min(512));
+    io::Error::other(String::from_utf8_lossy(&buf).into_owned())
+}
+
+/// A TCP connection opened by the host.
+#[derive(Debug)]
+pub struct TcpStream {
+    handle: i32,
+}
+
+impl TcpStream {
+    /// Connects to `address` as `host:port`, which must be in the plugin's
+    /// `allowed_hosts`.
+    pub fn connect(address: &str) -> io::Result<TcpStream> {
+        match unsafe { host::tcp_connect(address.as_ptr(), address.len()) } {
+            -1 => Err(last_error()),
+            handle => Ok(TcpStream { handle }),
+        }
+    }
+}
+
+impl io::Read for TcpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match unsafe { host::tcp_read(self.handle, buf.as_mut_ptr(), buf.len()) } {
+            -1 => Err(last_error()),
+            read => Ok(read as usize),
+        }
+    }
+}
+
+impl io::Write for TcpStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match unsafe { host::tcp_write(self.handle, buf.as_ptr(), buf.len()) } {
+            -1 => Err(last_error()),
+            written => Ok(written as usize),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        unsafe { host::tcp_close(self.handle) }
+    }
+}
+
+#[doc(hidden)]
+pub fn __alloc(len: usize) -> *mut u8 {
+    let mut buf = Vec::<u8>::with_capacity(len);
+    let ptr = buf.as_mut_ptr();
+    std::mem::forget(buf);
+    ptr
+}
+
+/// # Safety
+///
+/// `ptr` must come from [`__alloc`] called with `len`.
+#[doc(hidden)]
+pub unsafe fn __survey(ptr: *mut u8, len: usize, survey: fn(Monitor) -> Status) -> u64 {
+    let input = Vec::from_raw_parts(ptr, len, len);
+    let status = match serde_json::from_slice::<Monitor>(&input) {
+        Ok(monitor) => survey(monitor),
+        Err(e) => Status::down(format!("invalid monitor: {e}")),
+    };
+
+    let output = serde_json::to_vec(&status).unwrap_or_default();
+    let packed = ((output.as_ptr() as u64) << 32) | output.len() as u64;
+    // the instance is thrown away after the host read it
+    std::mem::forget(output);
+    packed
+}
+
+/// Exports `$survey`, a `fn(Monitor) -> Status`, as the plugin's survey.
+#[macro_export]
+macro_rules! export_survey {
+    ($survey:path) => {
+        #[no_mangle]
+        pub extern "C" fn whoopsie_alloc(len: usize) -> *mut u8 {
+            $crate::__alloc(len)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn whoopsie_survey(ptr: *mut u8, len: usize) -> u64 {
+            // the host writes the input into what whoopsie_alloc handed out
+            $crate::__survey(ptr, len, $survey)
+        }
+    };
+}
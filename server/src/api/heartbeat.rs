@@ -0,0 +1,116 @@
+use crate::monitor::{HeartbeatPing, HeartbeatRegistryPtr};
+use axum::extract::{Extension, Path};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::Router;
+
+/// Ping endpoints for `heartbeat` monitors. Both GET and POST are accepted so
+/// jobs can use whatever their http client makes easiest; the body of a
+/// `fail` ping is kept as the failure reason.
+pub fn routes<S>(registry: HeartbeatRegistryPtr) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/api/heartbeat/{token}", get(success).post(success))
+        .route("/api/heartbeat/{token}/start", get(start).post(start))
+        .route("/api/heartbeat/{token}/fail", get(fail).post(fail))
+        .layer(Extension(registry))
+}
+
+async fn success(
+    Path(token): Path<String>,
+    Extension(registry): Extension<HeartbeatRegistryPtr>,
+) -> (StatusCode, &'static str) {
+    record(&registry, &token, HeartbeatPing::Success, "").await
+}
+
+async fn start(
+    Path(token): Path<String>,
+    Extension(registry): Extension<HeartbeatRegistryPtr>,
+) -> (StatusCode, &'static str) {
+    record(&registry, &token, HeartbeatPing::Start, "").await
+}
+
+async fn fail(
+    Path(token): Path<String>,
+    Extension(registry): Extension<HeartbeatRegistryPtr>,
+    body: String,
+) -> (StatusCode, &'static str) {
+    record(&registry, &token, HeartbeatPing::Fail, &body).await
+}
+
+async fn record(
+    registry: &HeartbeatRegistryPtr,
+    token: &str,
+    ping: HeartbeatPing,
+    message: &str,
+) -> (StatusCode, &'static str) {
+    if registry.record(token, ping, message).await {
+        (StatusCode::OK, "OK")
+    } else {
+        (StatusCode::NOT_FOUND, "unknown heartbeat")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::{test_heartbeat_task, HeartbeatRegistry};
+    use app::types::MonitorStatus;
+    use axum::body::Body;
+    use axum::http::{Method, Request};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    async fn call(router: &Router, method: Method, uri: &str) -> StatusCode {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::from("exit code 3"))
+            .unwrap();
+        router.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_unknown_tokens_are_not_found() {
+        let router = routes(Arc::new(HeartbeatRegistry::new()));
+
+        for uri in [
+            "/api/heartbeat/nope",
+            "/api/heartbeat/nope/start",
+            "/api/heartbeat/nope/fail",
+        ] {
+            assert_eq!(call(&router, Method::GET, uri).await, StatusCode::NOT_FOUND);
+            assert_eq!(
+                call(&router, Method::POST, uri).await,
+                StatusCode::NOT_FOUND
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pings_change_the_monitor_status() {
+        let registry = Arc::new(HeartbeatRegistry::new());
+        let task = test_heartbeat_task(&registry, "backup", "nightly-backup")
+            .await
+            .unwrap();
+        let router = routes(registry);
+
+        let uri = "/api/heartbeat/nightly-backup/fail";
+        assert_eq!(call(&router, Method::POST, uri).await, StatusCode::OK);
+        match task.survey().await.unwrap() {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert!(error_reason.ends_with("exit code 3"), "{error_reason}")
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+
+        let uri = "/api/heartbeat/nightly-backup";
+        assert_eq!(call(&router, Method::GET, uri).await, StatusCode::OK);
+        assert!(matches!(
+            task.survey().await.unwrap(),
+            MonitorStatus::Up { .. }
+        ));
+    }
+}
@@ -0,0 +1,91 @@
+use crate::config::maintenance_config::MaintenanceWindow;
+use crate::monitor::MaintenanceRegistryPtr;
+use axum::extract::{Extension, Path};
+use axum::http::StatusCode;
+use axum::routing::{delete, get};
+use axum::{Json, Router};
+
+/// Lists, creates and removes maintenance windows at runtime. Posting a
+/// window with the name of an existing one replaces it.
+pub fn routes<S>(registry: MaintenanceRegistryPtr) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/api/maintenance", get(list).post(create))
+        .route("/api/maintenance/{name}", delete(remove))
+        .layer(Extension(registry))
+}
+
+async fn list(
+    Extension(registry): Extension<MaintenanceRegistryPtr>,
+) -> Json<Vec<MaintenanceWindow>> {
+    Json(registry.list().await)
+}
+
+async fn create(
+    Extension(registry): Extension<MaintenanceRegistryPtr>,
+    Json(window): Json<MaintenanceWindow>,
+) -> (StatusCode, String) {
+    match registry.add(window).await {
+        Ok(()) => (StatusCode::CREATED, "OK".to_string()),
+        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()),
+    }
+}
+
+async fn remove(
+    Path(name): Path<String>,
+    Extension(registry): Extension<MaintenanceRegistryPtr>,
+) -> (StatusCode, &'static str) {
+    if registry.remove(&name).await {
+        (StatusCode::OK, "OK")
+    } else {
+        (StatusCode::NOT_FOUND, "unknown maintenance window")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::MaintenanceRegistry;
+    use axum::body::Body;
+    use axum::http::{header, Method, Request};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    async fn call(router: &Router, method: Method, uri: &str, body: &str) -> StatusCode {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        router.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_create_and_remove() {
+        let registry = Arc::new(MaintenanceRegistry::default());
+        let router = routes(registry.clone());
+
+        let window = r#"{"name": "deploy", "monitors": ["api"], "start": "2026-07-01T10:00:00Z", "end": "2026-07-01T11:00:00Z"}"#;
+        assert_eq!(
+            call(&router, Method::POST, "/api/maintenance", window).await,
+            StatusCode::CREATED
+        );
+        assert_eq!(registry.list().await[0].name, "deploy");
+
+        let invalid = r#"{"name": "everything", "start": "2026-07-01T10:00:00Z", "end": "2026-07-01T11:00:00Z"}"#;
+        assert_eq!(
+            call(&router, Method::POST, "/api/maintenance", invalid).await,
+            StatusCode::BAD_REQUEST
+        );
+
+        let uri = "/api/maintenance/deploy";
+        assert_eq!(call(&router, Method::DELETE, uri, "").await, StatusCode::OK);
+        assert_eq!(
+            call(&router, Method::DELETE, uri, "").await,
+            StatusCode::NOT_FOUND
+        );
+    }
+}
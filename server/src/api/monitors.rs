@@ -0,0 +1,27 @@
+use crate::monitor::{MonitorSchedulerPtr, TaskHealth};
+use axum::extract::Extension;
+use axum::routing::get;
+use axum::{Json, Router};
+use std::collections::BTreeMap;
+
+/// Health of every scheduled monitor task, keyed by monitor name, and the
+/// scheduler's metrics for Prometheus.
+pub fn routes<S>(scheduler: MonitorSchedulerPtr) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/api/monitors/health", get(health))
+        .route("/metrics", get(metrics))
+        .layer(Extension(scheduler))
+}
+
+async fn health(
+    Extension(scheduler): Extension<MonitorSchedulerPtr>,
+) -> Json<BTreeMap<String, TaskHealth>> {
+    Json(scheduler.health().await)
+}
+
+async fn metrics(Extension(scheduler): Extension<MonitorSchedulerPtr>) -> String {
+    scheduler.metrics()
+}
@@ -1,11 +1,18 @@
 pub mod database_config;
+pub mod maintenance_config;
 pub mod monitor_config;
+pub mod plugin_config;
+pub mod scheduler_config;
+pub mod upgrade;
 
 use crate::config::database_config::DatabaseConfigBase;
+use crate::config::maintenance_config::MaintenanceWindow;
 use crate::config::monitor_config::MonitorBase;
+use crate::config::plugin_config::{PluginConfig, PluginConfigs, WasmPluginConfig};
+use crate::config::scheduler_config::SchedulerConfig;
 use crate::extensions::MappingExt;
 use app::config::AppConfig;
-use app::types::MonitorConfiguration;
+use app::types::{ActiveHours, MonitorConfiguration};
 use figment::providers::Format;
 use figment::{
     providers::{Env, YamlExtended},
@@ -13,7 +20,7 @@ use figment::{
 };
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use std::{env, fs};
 
@@ -26,18 +33,31 @@ pub struct ServerConfig {
     pub monitors: Option<Vec<MonitorBase>>,
     pub db: Option<DatabaseConfigBase>,
     pub global_monitor_config: Option<MonitorGeneralConfig>,
+    pub plugins: Option<Vec<PluginConfig>>,
+    pub wasm_plugins: Option<Vec<WasmPluginConfig>>,
+    pub scheduler: Option<SchedulerConfig>,
+    pub maintenance: Option<Vec<MaintenanceWindow>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MonitorGeneralConfig {
-    #[serde(with = "humantime_serde")]
+    #[serde(default, with = "humantime_serde")]
     pub check_interval: Option<Duration>,
+    #[serde(default)]
+    pub schedule: Option<String>,
+    #[serde(default)]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub active_hours: Vec<ActiveHours>,
 }
 
 impl Default for MonitorGeneralConfig {
     fn default() -> Self {
         MonitorGeneralConfig {
             check_interval: Some(Duration::from_secs(30)),
+            schedule: None,
+            timezone: None,
+            active_hours: vec![],
         }
     }
 }
@@ -46,6 +66,9 @@ impl MappingExt<MonitorConfiguration> for MonitorGeneralConfig {
     fn object_map(self) -> MonitorConfiguration {
         MonitorConfiguration {
             check_interval: self.check_interval,
+            schedule: self.schedule,
+            timezone: self.timezone,
+            active_hours: self.active_hours,
         }
     }
 }
@@ -65,6 +88,13 @@ impl ServerConfig {
         }
     }
 
+    pub fn plugin_configs(&self) -> PluginConfigs {
+        PluginConfigs {
+            executables: self.plugins.clone().unwrap_or_default(),
+            wasm: self.wasm_plugins.clone().unwrap_or_default(),
+        }
+    }
+
     pub fn merge(&mut self, other: ServerConfig) {
         // merge monitor config arrays
         if let Some(new_monitors) = other.monitors {
@@ -75,6 +105,25 @@ impl ServerConfig {
             }
         }
 
+        // plugins are merged like monitors
+        if let Some(new_plugins) = other.plugins {
+            self.plugins
+                .get_or_insert_with(Vec::new)
+                .extend(new_plugins);
+        }
+        if let Some(new_plugins) = other.wasm_plugins {
+            self.wasm_plugins
+                .get_or_insert_with(Vec::new)
+                .extend(new_plugins);
+        }
+
+        // and so are maintenance windows
+        if let Some(new_windows) = other.maintenance {
+            self.maintenance
+                .get_or_insert_with(Vec::new)
+                .extend(new_windows);
+        }
+
         // app config takes the last loaded config value
         if let Some(new_app_config) = other.app_config {
             self.app_config = Some(new_app_config);
@@ -84,6 +133,11 @@ impl ServerConfig {
         if let Some(new_db_config) = other.db {
             self.db = Some(new_db_config);
         }
+
+        // so does the scheduler config
+        if let Some(new_scheduler_config) = other.scheduler {
+            self.scheduler = Some(new_scheduler_config);
+        }
     }
 }
 
@@ -122,11 +176,48 @@ pub enum ConfigError {
     FigmentError(#[from] FigmentError),
 }
 
+/// The YAML files in the config directory, in loading order.
+#[allow(clippy::result_large_err)]
+pub fn config_files() -> Result<Vec<PathBuf>, ConfigError> {
+    yaml_files_in_dir(CONFIG_DIR_ENV_VAR)
+}
+
 #[allow(clippy::result_large_err)]
 fn load_config_from_dir(
     config_dir_env_var: &str,
     env_prefix: Option<&str>,
 ) -> Result<ServerConfig, ConfigError> {
+    let yaml_files = yaml_files_in_dir(config_dir_env_var)?;
+
+    // Start building the config
+    let mut ret = ServerConfig::default();
+
+    // Add each YAML file to the figment in order
+    for yaml_file in yaml_files {
+        debug!("Loading config from: {:?}", yaml_file);
+        let mut new_config: ServerConfig = Figment::new()
+            .merge(YamlExtended::file(&yaml_file))
+            .extract()?;
+
+        new_config.set_source_file(yaml_file.to_string_lossy());
+
+        ret.merge(new_config);
+    }
+
+    // Add environment variable provider last (highest precedence)
+    let env_config = if let Some(prefix) = env_prefix {
+        debug!("Using environment variable prefix: {}", prefix);
+        Figment::new().merge(Env::prefixed(prefix)).extract()?
+    } else {
+        Figment::new().merge(Env::raw()).extract()?
+    };
+    ret.merge(env_config);
+
+    Ok(ret)
+}
+
+#[allow(clippy::result_large_err)]
+fn yaml_files_in_dir(config_dir_env_var: &str) -> Result<Vec<PathBuf>, ConfigError> {
     // Get the config directory from environment variable
     let config_dir = env::var(config_dir_env_var).map_err(|_| ConfigError::EnvVarNotSet {
         var_name: config_dir_env_var.to_string(),
@@ -180,31 +271,7 @@ fn load_config_from_dir(
     // Sort files alphanumerically for consistent loading order
     yaml_files.sort();
 
-    // Start building the config
-    let mut ret = ServerConfig::default();
-
-    // Add each YAML file to the figment in order
-    for yaml_file in yaml_files {
-        debug!("Loading config from: {:?}", yaml_file);
-        let mut new_config: ServerConfig = Figment::new()
-            .merge(YamlExtended::file(&yaml_file))
-            .extract()?;
-
-        new_config.set_source_file(yaml_file.to_string_lossy());
-
-        ret.merge(new_config);
-    }
-
-    // Add environment variable provider last (highest precedence)
-    let env_config = if let Some(prefix) = env_prefix {
-        debug!("Using environment variable prefix: {}", prefix);
-        Figment::new().merge(Env::prefixed(prefix)).extract()?
-    } else {
-        Figment::new().merge(Env::raw()).extract()?
-    };
-    ret.merge(env_config);
-
-    Ok(ret)
+    Ok(yaml_files)
 }
 
 #[cfg(test)]
@@ -318,4 +385,18 @@ mod tests {
         // Clean up
         env::remove_var("TEST_CONFIG_DIR_EMPTY");
     }
+
+    #[test]
+    fn test_monitor_config_with_schedule() {
+        let config: MonitorGeneralConfig = serde_yaml::from_str(
+            "schedule: '0 9 * * mon'\ntimezone: Europe/Paris\nactive_hours:\n- days: [mon-fri]\n  from: '08:00'\n  to: '20:00'\n",
+        )
+        .unwrap();
+        let config = config.object_map();
+
+        assert_eq!(config.check_interval, None);
+        assert_eq!(config.schedule.as_deref(), Some("0 9 * * mon"));
+        assert_eq!(config.timezone.as_deref(), Some("Europe/Paris"));
+        assert_eq!(config.active_hours[0].days, vec!["mon-fri"]);
+    }
 }
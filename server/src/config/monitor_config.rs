@@ -2,6 +2,7 @@ use crate::config::MonitorGeneralConfig;
 use crate::extensions::MappingExt;
 use app::types::Monitor;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MonitorBase {
@@ -15,6 +16,8 @@ pub struct MonitorBase {
     pub source_file: String,
     #[serde(rename = "name")]
     pub name: String,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
     pub monitor_config: Option<MonitorGeneralConfig>,
 }
 
@@ -25,8 +28,10 @@ impl MappingExt<Monitor> for MonitorBase {
             current_status: None,
             api_version: self.api_version,
             kind: self.kind,
+            labels: self.labels,
             configuration: self.monitor_config.object_map(),
             spec: self.spec,
+            paused: false,
         }
     }
 }
@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// An out-of-process monitor plugin, see `monitor::tasks::plugin`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginConfig {
+    pub name: String,
+    pub command: PathBuf,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// How long a single build or survey request may take.
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+    /// Delay before the first restart after a crash, doubled on every crash
+    /// that follows soon after.
+    #[serde(default, with = "humantime_serde")]
+    pub restart_delay: Option<Duration>,
+}
+
+/// A sandboxed WebAssembly monitor plugin, see `monitor::tasks::wasm`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WasmPluginConfig {
+    pub name: String,
+    pub module: PathBuf,
+    pub kind: String,
+    #[serde(rename = "apiVersion")]
+    pub api_version: String,
+    /// `host:port` pairs the module may open TCP connections to.
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+    /// Upper bound for the module's linear memory, in bytes.
+    pub max_memory: Option<usize>,
+}
+
+/// Every plugin declared in config, registered with the task factory at
+/// startup.
+#[derive(Debug, Clone, Default)]
+pub struct PluginConfigs {
+    pub executables: Vec<PluginConfig>,
+    pub wasm: Vec<WasmPluginConfig>,
+}
@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Limits on how the scheduler runs checks.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SchedulerConfig {
+    /// Surveys in flight at once across all monitors, 64 unless set.
+    pub max_concurrent_checks: Option<usize>,
+    /// Surveys in flight at once for monitors of the same kind.
+    pub max_concurrent_checks_per_kind: Option<usize>,
+    /// Surveys in flight at once against the same target host.
+    pub max_concurrent_checks_per_host: Option<usize>,
+    /// First runs are spread over this long, or the monitor's interval if
+    /// shorter. A minute unless set.
+    #[serde(default, with = "humantime_serde")]
+    pub max_jitter: Option<Duration>,
+}
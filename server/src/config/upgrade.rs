@@ -0,0 +1,154 @@
+use crate::config::config_files;
+use crate::monitor::upgrade_spec;
+use anyhow::{anyhow, bail, Error};
+use serde_yaml::Value;
+use std::fs;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "usage: server upgrade-config [--check] [FILE...]
+
+Rewrites the monitors in config files to the latest apiVersion of their kind.
+Without files, the YAML files in CONFIG_DIR are upgraded. Comments and
+anchors don't survive the rewrite. With --check nothing is written, and the
+command fails if any file is out of date.";
+
+/// `server upgrade-config`, see [`USAGE`].
+pub fn run(args: &[String]) -> Result<(), Error> {
+    let mut check = false;
+    let mut paths = vec![];
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            flag if flag.starts_with('-') => bail!("unknown flag {flag}\n\n{USAGE}"),
+            path => paths.push(PathBuf::from(path)),
+        }
+    }
+    if paths.is_empty() {
+        paths = config_files()?;
+    }
+
+    let mut outdated = vec![];
+    for path in &paths {
+        let contents =
+            fs::read_to_string(path).map_err(|e| anyhow!("reading {}: {e}", path.display()))?;
+        let Some(upgraded) =
+            upgrade_config(&contents).map_err(|e| anyhow!("upgrading {}: {e}", path.display()))?
+        else {
+            continue;
+        };
+
+        if !check {
+            fs::write(path, upgraded).map_err(|e| anyhow!("writing {}: {e}", path.display()))?;
+            println!("upgraded {}", path.display());
+        }
+        outdated.push(path.as_path());
+    }
+
+    if check && !outdated.is_empty() {
+        let outdated = outdated.iter().map(|p| p.display().to_string());
+        bail!(
+            "monitors need upgrading in {}",
+            outdated.collect::<Vec<_>>().join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// The config file's contents with its monitors upgraded, if any were out of
+/// date.
+pub fn upgrade_config(contents: &str) -> Result<Option<String>, Error> {
+    let mut config: Value = serde_yaml::from_str(contents)?;
+    let Some(monitors) = config
+        .get_mut("monitors")
+        .and_then(|monitors| monitors.as_sequence_mut())
+    else {
+        return Ok(None);
+    };
+
+    let mut upgraded = false;
+    for monitor in monitors.iter_mut() {
+        upgraded |= upgrade_monitor(monitor)?;
+    }
+
+    match upgraded {
+        true => Ok(Some(serde_yaml::to_string(&config)?)),
+        false => Ok(None),
+    }
+}
+
+fn upgrade_monitor(monitor: &mut Value) -> Result<bool, Error> {
+    let field = |name: &str| monitor.get(name).and_then(|value| value.as_str());
+    let (Some(name), Some(kind), Some(api_version)) =
+        (field("name"), field("kind"), field("apiVersion"))
+    else {
+        return Ok(false);
+    };
+    let (name, kind, mut api_version) =
+        (name.to_string(), kind.to_string(), api_version.to_string());
+
+    let mut spec = serde_json::to_value(monitor.get("spec").unwrap_or(&Value::Null))?;
+    if !upgrade_spec(&name, &kind, &mut api_version, &mut spec)
+        .map_err(|e| anyhow!("monitor {name}: {e}"))?
+    {
+        return Ok(false);
+    }
+
+    monitor["apiVersion"] = Value::String(api_version);
+    monitor["spec"] = serde_yaml::to_value(spec)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upgrade_config() {
+        let config = r#"
+monitors:
+  - apiVersion: v1alpha1
+    kind: endpoint
+    name: github
+    monitor_config:
+      check_interval: 15s
+    spec:
+      uri: https://github.com
+      assert: status_code == 200
+  - apiVersion: v1alpha1
+    kind: redis
+    name: cache
+    spec:
+      host: localhost
+"#;
+        let upgraded: Value =
+            serde_yaml::from_str(&upgrade_config(config).unwrap().unwrap()).unwrap();
+        let expected: Value = serde_yaml::from_str(
+            r#"
+monitors:
+  - apiVersion: v1alpha2
+    kind: endpoint
+    name: github
+    monitor_config:
+      check_interval: 15s
+    spec:
+      url: https://github.com
+      assert: status_code == 200
+  - apiVersion: v1alpha1
+    kind: redis
+    name: cache
+    spec:
+      host: localhost
+"#,
+        )
+        .unwrap();
+        assert_eq!(upgraded, expected);
+
+        let upgraded = serde_yaml::to_string(&upgraded).unwrap();
+        assert_eq!(upgrade_config(&upgraded).unwrap(), None);
+        assert_eq!(upgrade_config("app_config: {}").unwrap(), None);
+    }
+}
@@ -1,19 +1,53 @@
 use crate::extensions::*;
-use app::types::{Monitor, MonitorConfiguration, MonitorStatus};
+use app::types::{ActiveHours, Monitor, MonitorConfiguration, MonitorStatus};
 use entities::monitor::Model;
 use entities::sea_orm_active_enums::Status;
 use entities::{monitor, monitor_status};
 use sea_orm::{NotSet, Set};
+use serde::{Deserialize, Serialize};
+
+/// The `schedule` column, everything but the interval of a monitor's configuration.
+#[derive(Default, Serialize, Deserialize)]
+struct ScheduleColumn {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    schedule: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timezone: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    active_hours: Vec<ActiveHours>,
+}
+
+impl ScheduleColumn {
+    fn is_set(&self) -> bool {
+        self.schedule.is_some() || self.timezone.is_some() || !self.active_hours.is_empty()
+    }
+}
 
 impl MappingExt<MonitorStatus> for monitor_status::Model {
     fn object_map(self) -> MonitorStatus {
         match self.status {
             Status::Up => MonitorStatus::Up {
                 checked_at: self.created_at.into(),
+                details: self.details,
+            },
+            Status::Degraded => MonitorStatus::Degraded {
+                checked_at: self.created_at.into(),
+                error_reason: self.error_reason.unwrap_or_default(),
+                details: self.details,
             },
             Status::Down => MonitorStatus::Down {
                 checked_at: self.created_at.into(),
                 error_reason: self.error_reason.unwrap_or_default(),
+                details: self.details,
+            },
+            Status::Maintenance => MonitorStatus::Maintenance {
+                checked_at: self.created_at.into(),
+                window: self.error_reason.unwrap_or_default(),
+                status: Box::new(
+                    self.details
+                        .and_then(|status| serde_json::from_value(status).ok())
+                        .unwrap_or_default(),
+                ),
             },
         }
     }
@@ -40,27 +74,53 @@ impl MappingExtraField1Ext<Monitor, Vec<monitor_status::Model>> for monitor::Mod
         let api_version = self.api_version;
         let kind = self.kind;
 
-        let configuration = self.check_interval.map(|interval| MonitorConfiguration {
-            check_interval: Some(std::time::Duration::from_secs_f32(interval)),
-        });
+        let schedule: ScheduleColumn = self
+            .schedule
+            .and_then(|schedule| serde_json::from_value(schedule).ok())
+            .unwrap_or_default();
+        let configuration =
+            (self.check_interval.is_some() || schedule.is_set()).then(|| MonitorConfiguration {
+                check_interval: self.check_interval.map(std::time::Duration::from_secs_f32),
+                schedule: schedule.schedule,
+                timezone: schedule.timezone,
+                active_hours: schedule.active_hours,
+            });
+
+        let labels = self
+            .labels
+            .and_then(|labels| serde_json::from_value(labels).ok())
+            .unwrap_or_default();
 
         Monitor {
             name: self.id,
+            labels,
             configuration,
             current_status,
             api_version,
             kind,
             spec: self.spec,
+            paused: self.paused,
         }
     }
 }
 
 impl MappingExt<monitor::Model> for Monitor {
     fn object_map(self) -> Model {
-        let check_interval = if let Some(cfg) = self.configuration {
-            cfg.check_interval.map(|i| i.as_secs_f32())
-        } else {
-            None
+        let (check_interval, schedule) = match self.configuration {
+            Some(cfg) => {
+                let schedule = ScheduleColumn {
+                    schedule: cfg.schedule,
+                    timezone: cfg.timezone,
+                    active_hours: cfg.active_hours,
+                };
+                (
+                    cfg.check_interval.map(|i| i.as_secs_f32()),
+                    schedule
+                        .is_set()
+                        .then(|| serde_json::to_value(schedule).unwrap_or_default()),
+                )
+            }
+            None => (None, None),
         };
 
         Model {
@@ -69,6 +129,9 @@ impl MappingExt<monitor::Model> for Monitor {
             api_version: self.api_version,
             kind: self.kind,
             check_interval,
+            schedule,
+            paused: self.paused,
+            labels: Some(serde_json::to_value(self.labels).unwrap_or_default()),
         }
     }
 }
@@ -76,12 +139,31 @@ impl MappingExt<monitor::Model> for Monitor {
 impl MappingExtraField1Ext<monitor_status::ActiveModel, String> for MonitorStatus {
     fn object_map_field(self, monitor_id: String) -> monitor_status::ActiveModel {
         let mut reason_val = NotSet;
-        let monitor_status = match self {
-            MonitorStatus::Up { .. } => Status::Up,
-            MonitorStatus::Down { error_reason, .. } => {
+        let (monitor_status, details) = match self {
+            MonitorStatus::Up { details, .. } => (Status::Up, details),
+            MonitorStatus::Degraded {
+                error_reason,
+                details,
+                ..
+            } => {
+                reason_val = Set(Some(error_reason));
+
+                (Status::Degraded, details)
+            }
+            MonitorStatus::Down {
+                error_reason,
+                details,
+                ..
+            } => {
                 reason_val = Set(Some(error_reason));
 
-                Status::Down
+                (Status::Down, details)
+            }
+            MonitorStatus::Maintenance { window, status, .. } => {
+                reason_val = Set(Some(window));
+
+                // the underlying status is kept whole in the details
+                (Status::Maintenance, serde_json::to_value(status).ok())
             }
             MonitorStatus::Unknown => {
                 panic!("Unknown monitor status - we can't create this in the DB")
@@ -94,6 +176,7 @@ impl MappingExtraField1Ext<monitor_status::ActiveModel, String> for MonitorStatu
             status: Set(monitor_status),
             monitor_id: Set(monitor_id),
             error_reason: reason_val,
+            details: Set(details),
         }
     }
 }
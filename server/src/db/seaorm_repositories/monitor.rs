@@ -4,9 +4,13 @@ use app::types::{Monitor, MonitorRepository, MonitorStatus, RepositoryError};
 use entities::{monitor, monitor_status};
 use migration::async_trait::async_trait;
 use sea_orm::prelude::*;
-use sea_orm::QueryOrder;
+use sea_orm::sqlx::types::chrono::{DateTime, Utc};
+use sea_orm::ActiveValue::{Set, Unchanged};
+use sea_orm::{DbBackend, QueryOrder};
 use std::sync::Arc;
 
+const SQLITE_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
 pub fn new_monitor_repository(db: Arc<DbConn>) -> Arc<impl MonitorRepository + Send + Sync> {
     Arc::new(SeaormMonitorRepository { db })
 }
@@ -60,6 +64,58 @@ impl MonitorRepository for SeaormMonitorRepository {
         Ok(())
     }
 
+    async fn update_monitor_spec(
+        &self,
+        id: String,
+        api_version: String,
+        spec: serde_json::Value,
+    ) -> Result<(), RepositoryError> {
+        let model = monitor::ActiveModel {
+            id: Unchanged(id),
+            api_version: Set(api_version),
+            spec: Set(spec),
+            ..Default::default()
+        };
+
+        let _ = model.update(self.db.as_ref()).await.to_repo_err()?;
+
+        Ok(())
+    }
+
+    async fn set_paused(&self, id: String, paused: bool) -> Result<(), RepositoryError> {
+        let model = monitor::ActiveModel {
+            id: Unchanged(id),
+            paused: Set(paused),
+            ..Default::default()
+        };
+
+        let _ = model.update(self.db.as_ref()).await.to_repo_err()?;
+
+        Ok(())
+    }
+
+    async fn get_statuses_since(
+        &self,
+        monitor_id: String,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<MonitorStatus>, RepositoryError> {
+        // sqlite keeps CURRENT_TIMESTAMP as text, so compare against the same format
+        let since: Value = match self.db.get_database_backend() {
+            DbBackend::Sqlite => since.format(SQLITE_TIMESTAMP_FORMAT).to_string().into(),
+            _ => since.into(),
+        };
+
+        let statuses = monitor_status::Entity::find()
+            .filter(monitor_status::Column::MonitorId.eq(monitor_id))
+            .filter(monitor_status::Column::CreatedAt.gte(since))
+            .order_by_asc(monitor_status::Column::CreatedAt)
+            .all(self.db.as_ref())
+            .await
+            .to_repo_err()?;
+
+        Ok(statuses.into_iter().map(|s| s.object_map()).collect())
+    }
+
     async fn log_status(
         &self,
         monitor_id: String,
@@ -70,3 +126,40 @@ impl MonitorRepository for SeaormMonitorRepository {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::db::get_db_factory;
+    use app::types::{ActiveHours, Monitor, MonitorConfiguration};
+
+    #[tokio::test]
+    async fn test_active_hours_without_interval_are_stored() {
+        let db = get_db_factory(&None).await.unwrap();
+        db.initialize_db().await.unwrap();
+        let repo = db.get_monitor_repository();
+
+        let configuration = MonitorConfiguration {
+            timezone: Some("Europe/Berlin".to_string()),
+            active_hours: vec![ActiveHours {
+                days: vec!["mon-fri".to_string()],
+                from: "08:00".to_string(),
+                to: "20:00".to_string(),
+            }],
+            ..Default::default()
+        };
+        let monitor = Monitor {
+            name: "test-endpoint".to_string(),
+            current_status: None,
+            api_version: "v1alpha2".to_string(),
+            kind: "endpoint".to_string(),
+            labels: Default::default(),
+            configuration: Some(configuration.clone()),
+            spec: serde_json::Value::Null,
+            paused: false,
+        };
+        repo.create_monitor(monitor.clone()).await.unwrap();
+
+        let stored = repo.get_monitor(monitor.name).await.unwrap();
+        assert_eq!(stored.configuration, Some(configuration));
+    }
+}
@@ -3,6 +3,7 @@ extern crate thiserror;
 #[macro_use]
 extern crate tracing;
 
+mod api;
 pub(crate) mod config;
 pub(crate) mod db;
 pub mod extensions;
@@ -14,14 +15,16 @@ use crate::config::{MonitorGeneralConfig, ServerConfig};
 use crate::db::get_db_factory;
 use crate::extensions::MappingExt;
 use crate::fileserv::file_and_error_handler;
-use crate::monitor::MonitorController;
+use crate::monitor::{HeartbeatRegistry, MaintenanceRegistry, MonitorController};
 use crate::signal::{ExitSignal, ExitSignaler};
 use app::state::ServerState;
-use app::{shell, App};
+use app::{shell, App, MonitorControlPointer};
 use axum::Router;
 use leptos::config::get_configuration;
+use leptos::prelude::provide_context;
 use leptos_axum::{generate_route_list, LeptosRoutes};
 use std::net::SocketAddr;
+use std::sync::Arc;
 use tokio::try_join;
 
 #[tokio::main]
@@ -29,21 +32,50 @@ async fn main() -> anyhow::Result<()> {
     load_env();
     app::tracing::init_tracing();
 
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args
+        .first()
+        .is_some_and(|command| command == "upgrade-config")
+    {
+        return config::upgrade::run(&args[1..]);
+    }
+
     let exit_signaler = ExitSignaler::new();
 
     let server_config = config::load_config()?;
+    let plugins = server_config.plugin_configs();
+    let scheduler_config = server_config.scheduler.clone().unwrap_or_default();
+    let maintenance = Arc::new(MaintenanceRegistry::from_config(
+        server_config.maintenance.clone().unwrap_or_default(),
+    )?);
     let server_state = build_server_state(server_config).await?;
-    let monitor_controller = MonitorController::new(server_state.clone());
+    let heartbeats = Arc::new(HeartbeatRegistry::new());
+    let monitor_controller = MonitorController::new(
+        server_state.clone(),
+        heartbeats.clone(),
+        maintenance.clone(),
+        plugins,
+        &scheduler_config,
+    );
 
     let leptos_options = server_state.leptos_options.clone();
     let addr = leptos_options.site_addr;
     let routes = generate_route_list(App);
+    let monitor_control: MonitorControlPointer = monitor_controller.scheduler();
 
     let app = Router::new()
-        .leptos_routes(&server_state, routes, {
-            let leptos_options = leptos_options.clone();
-            move || shell(leptos_options.clone())
-        })
+        .merge(api::heartbeat::routes(heartbeats))
+        .merge(api::maintenance::routes(maintenance))
+        .merge(api::monitors::routes(monitor_controller.scheduler()))
+        .leptos_routes_with_context(
+            &server_state,
+            routes,
+            move || provide_context(monitor_control.clone()),
+            {
+                let leptos_options = leptos_options.clone();
+                move || shell(leptos_options.clone())
+            },
+        )
         .fallback(file_and_error_handler)
         .with_state(server_state);
 
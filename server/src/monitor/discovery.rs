@@ -1,6 +1,7 @@
 use crate::config::load_config;
 use crate::config::monitor_config::MonitorBase;
 use crate::extensions::MappingCloneExt;
+use crate::monitor::tasks::conversion;
 use app::state::ServerState;
 use app::types::{Monitor, RepositoryError};
 use app::DbFactoryPointer;
@@ -19,11 +20,25 @@ impl MonitorDiscovery {
     pub async fn discover(&self) -> Result<Vec<Monitor>, anyhow::Error> {
         self.discover_fs().await?;
 
-        let monitors = self
-            .db_factory
-            .get_monitor_repository()
-            .get_monitors()
-            .await?;
+        let repo = self.db_factory.get_monitor_repository();
+        let mut monitors = repo.get_monitors().await?;
+
+        // stored specs are kept at the latest apiVersion of their kind, a
+        // spec failing to convert is left for its task to report
+        for monitor in monitors.iter_mut() {
+            match conversion::upgrade(monitor) {
+                Ok(true) => {
+                    repo.update_monitor_spec(
+                        monitor.name.clone(),
+                        monitor.api_version.clone(),
+                        monitor.spec.clone(),
+                    )
+                    .await?
+                }
+                Ok(false) => {}
+                Err(e) => log::warn!("error upgrading monitor {}: {e}", monitor.name),
+            }
+        }
 
         Ok(monitors)
     }
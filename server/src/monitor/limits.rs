@@ -0,0 +1,252 @@
+use crate::config::scheduler_config::SchedulerConfig;
+use app::types::Monitor;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+pub type CheckLimiterPtr = Arc<CheckLimiter>;
+
+const DEFAULT_MAX_CONCURRENT_CHECKS: usize = 64;
+const DEFAULT_MAX_JITTER: Duration = Duration::from_secs(60);
+/// Upper bounds of the queueing delay histogram, in seconds.
+const QUEUE_DELAY_BUCKETS: [f64; 10] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0];
+
+/// Caps how many surveys run at once, globally and per kind and target host,
+/// and keeps track of how long surveys waited for a slot.
+#[derive(Debug)]
+pub struct CheckLimiter {
+    max_concurrent: usize,
+    global: Arc<Semaphore>,
+    per_kind: Option<usize>,
+    per_host: Option<usize>,
+    kinds: Mutex<HashMap<String, Arc<Semaphore>>>,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+    max_jitter: Duration,
+    queue_delay: Mutex<Histogram>,
+}
+
+/// Held while a survey runs.
+#[derive(Debug)]
+pub struct CheckPermit {
+    _permits: Vec<OwnedSemaphorePermit>,
+}
+
+impl CheckLimiter {
+    pub fn new(config: &SchedulerConfig) -> Self {
+        let max_concurrent = config
+            .max_concurrent_checks
+            .unwrap_or(DEFAULT_MAX_CONCURRENT_CHECKS)
+            .max(1);
+        CheckLimiter {
+            max_concurrent,
+            global: Arc::new(Semaphore::new(max_concurrent)),
+            per_kind: config.max_concurrent_checks_per_kind.map(|n| n.max(1)),
+            per_host: config.max_concurrent_checks_per_host.map(|n| n.max(1)),
+            kinds: Mutex::new(HashMap::new()),
+            hosts: Mutex::new(HashMap::new()),
+            max_jitter: config.max_jitter.unwrap_or(DEFAULT_MAX_JITTER),
+            queue_delay: Mutex::new(Histogram::default()),
+        }
+    }
+
+    /// Waits for a slot to survey `monitor`. The most specific limits are
+    /// taken first, so surveys queued behind a busy host don't hold global
+    /// slots.
+    pub async fn acquire(&self, monitor: &Monitor) -> CheckPermit {
+        let started = Instant::now();
+        let mut semaphores = vec![];
+        if let (Some(limit), Some(host)) = (self.per_host, target_host(monitor)) {
+            semaphores.push(semaphore(&self.hosts, &host, limit));
+        }
+        if let Some(limit) = self.per_kind {
+            semaphores.push(semaphore(&self.kinds, &monitor.kind, limit));
+        }
+        semaphores.push(self.global.clone());
+
+        let mut permits = vec![];
+        for semaphore in semaphores {
+            permits.push(semaphore.acquire_owned().await.expect("never closed"));
+        }
+
+        let waited = started.elapsed();
+        self.queue_delay.lock().unwrap().observe(waited);
+        if waited >= Duration::from_secs(1) {
+            log::debug!("survey of {} waited {waited:?} for a slot", monitor.name);
+        }
+        CheckPermit { _permits: permits }
+    }
+
+    /// Delay before a monitor's first survey, spreading monitors over their
+    /// interval. The same monitor always gets the same delay.
+    pub fn first_run_delay(&self, monitor_name: &str, interval: Duration) -> Duration {
+        let span = interval.min(self.max_jitter);
+        span.mul_f64(stable_hash(monitor_name.as_bytes()) as f64 / u64::MAX as f64)
+    }
+
+    /// Prometheus text exposition of the limiter's metrics.
+    pub fn metrics(&self) -> String {
+        let mut out = String::new();
+        let in_flight = self.max_concurrent - self.global.available_permits();
+        let _ = writeln!(
+            out,
+            "# HELP whoopsie_checks_in_flight Surveys currently running.\n\
+             # TYPE whoopsie_checks_in_flight gauge\n\
+             whoopsie_checks_in_flight {in_flight}"
+        );
+        self.queue_delay.lock().unwrap().render(
+            &mut out,
+            "whoopsie_check_queue_delay_seconds",
+            "Time surveys waited for a concurrency slot.",
+        );
+        out
+    }
+}
+
+fn semaphore(
+    semaphores: &Mutex<HashMap<String, Arc<Semaphore>>>,
+    key: &str,
+    limit: usize,
+) -> Arc<Semaphore> {
+    semaphores
+        .lock()
+        .unwrap()
+        .entry(key.to_string())
+        .or_insert_with(|| Arc::new(Semaphore::new(limit)))
+        .clone()
+}
+
+/// The host a monitor checks, from a `host` field or a URL in `url`, `uri`
+/// or `endpoint`.
+pub fn target_host(monitor: &Monitor) -> Option<String> {
+    if let Some(host) = monitor.spec.get("host").and_then(|h| h.as_str()) {
+        return Some(host.to_lowercase());
+    }
+    ["url", "uri", "endpoint"].iter().find_map(|key| {
+        let url = monitor.spec.get(key)?.as_str()?;
+        let url = reqwest::Url::parse(url).ok()?;
+        url.host_str().map(|host| host.to_lowercase())
+    })
+}
+
+/// FNV-1a with a final mix so similar names land far apart. Stable across
+/// builds and platforms, unlike `DefaultHasher`.
+fn stable_hash(bytes: &[u8]) -> u64 {
+    let mut hash = bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    });
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^ (hash >> 33)
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [u64; QUEUE_DELAY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: Duration) {
+        let seconds = value.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter_mut().zip(QUEUE_DELAY_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} histogram");
+        for (count, bound) in self.buckets.iter().zip(QUEUE_DELAY_BUCKETS) {
+            let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {count}");
+        }
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {}", self.count);
+        let _ = writeln!(out, "{name}_sum {}", self.sum);
+        let _ = writeln!(out, "{name}_count {}", self.count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::tasks::test_monitor;
+    use serde_json::json;
+
+    fn monitor(name: &str, kind: &str, spec: serde_json::Value) -> Monitor {
+        let mut monitor = test_monitor(kind, "v1alpha1", spec);
+        monitor.name = name.to_string();
+        monitor
+    }
+
+    #[test]
+    fn test_first_run_delay() {
+        let limiter = CheckLimiter::new(&SchedulerConfig::default());
+        let interval = Duration::from_secs(30);
+
+        let delays = (0..500)
+            .map(|i| limiter.first_run_delay(&format!("monitor-{i}"), interval))
+            .collect::<Vec<_>>();
+        assert!(delays.iter().all(|d| *d < interval));
+        assert_eq!(delays[7], limiter.first_run_delay("monitor-7", interval));
+        // spread out rather than bunched up
+        let first_half = delays.iter().filter(|d| **d < interval / 2).count();
+        assert!((200..300).contains(&first_half), "{first_half} of 500");
+
+        let hourly = limiter.first_run_delay("monitor-7", Duration::from_secs(3600));
+        assert!(hourly < DEFAULT_MAX_JITTER);
+    }
+
+    #[test]
+    fn test_target_host() {
+        let host = |spec| target_host(&monitor("m", "x", spec));
+        assert_eq!(
+            host(json!({ "host": "DB.local", "port": 1 })).unwrap(),
+            "db.local"
+        );
+        assert_eq!(
+            host(json!({ "url": "https://Example.com/health" })).unwrap(),
+            "example.com"
+        );
+        assert_eq!(
+            host(json!({ "uri": "http://10.0.0.1:8080" })).unwrap(),
+            "10.0.0.1"
+        );
+        assert_eq!(host(json!({ "path": "/var/log" })), None);
+    }
+
+    #[tokio::test]
+    async fn test_limits() {
+        let limiter = Arc::new(CheckLimiter::new(&SchedulerConfig {
+            max_concurrent_checks: Some(3),
+            max_concurrent_checks_per_host: Some(1),
+            ..Default::default()
+        }));
+        let a = monitor("a", "http", json!({ "url": "http://a.local/" }));
+        let b = monitor("b", "tcp", json!({ "host": "a.local" }));
+        let c = monitor("c", "http", json!({ "url": "http://c.local/" }));
+
+        let held = limiter.acquire(&a).await;
+        // same host as `a`, has to wait
+        let queued = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire(&b).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!queued.is_finished());
+
+        // other hosts aren't held up
+        let _other = limiter.acquire(&c).await;
+        drop(held);
+        let _b = queued.await.unwrap();
+
+        let metrics = limiter.metrics();
+        assert!(metrics.contains("whoopsie_checks_in_flight 2\n"));
+        assert!(metrics.contains("whoopsie_check_queue_delay_seconds_count 3\n"));
+        assert!(metrics.contains("whoopsie_check_queue_delay_seconds_bucket{le=\"0.01\"} 2\n"));
+    }
+}
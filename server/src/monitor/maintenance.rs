@@ -0,0 +1,340 @@
+use crate::config::maintenance_config::MaintenanceWindow;
+use crate::monitor::schedule::{parse_cron, parse_timezone};
+use anyhow::{bail, Error};
+use app::types::Monitor;
+use chrono::TimeDelta;
+use chrono_tz::Tz;
+use croner::Cron;
+use sea_orm::sqlx::types::chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub type MaintenanceRegistryPtr = Arc<MaintenanceRegistry>;
+
+#[derive(Debug)]
+enum Period {
+    Once {
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    },
+    Recurring {
+        cron: Box<Cron>,
+        duration: TimeDelta,
+        timezone: Tz,
+    },
+}
+
+#[derive(Debug)]
+struct ParsedWindow {
+    window: MaintenanceWindow,
+    period: Period,
+}
+
+impl ParsedWindow {
+    fn parse(window: MaintenanceWindow) -> Result<Self, Error> {
+        let name = &window.name;
+        if name.trim().is_empty() {
+            bail!("maintenance window name must not be empty");
+        }
+        if window.monitors.is_empty() && window.labels.is_empty() {
+            bail!("maintenance window '{name}' must target monitors or labels");
+        }
+
+        let period = match &window {
+            MaintenanceWindow {
+                start: Some(start),
+                end: Some(end),
+                schedule: None,
+                duration: None,
+                ..
+            } => {
+                if end <= start {
+                    bail!("maintenance window '{name}' ends before it starts");
+                }
+                Period::Once {
+                    start: *start,
+                    end: *end,
+                }
+            }
+            MaintenanceWindow {
+                start: None,
+                end: None,
+                schedule: Some(schedule),
+                duration: Some(duration),
+                ..
+            } => Period::Recurring {
+                cron: Box::new(parse_cron(schedule)?),
+                duration: TimeDelta::from_std(*duration)?,
+                timezone: parse_timezone(window.timezone.as_deref())?,
+            },
+            _ => bail!(
+                "maintenance window '{name}' needs either start and end, or schedule and duration"
+            ),
+        };
+
+        Ok(ParsedWindow { window, period })
+    }
+
+    fn targets(&self, monitor: &Monitor) -> bool {
+        self.window.monitors.contains(&monitor.name)
+            || (!self.window.labels.is_empty() && monitor.matches_labels(&self.window.labels))
+    }
+
+    fn is_active(&self, now: DateTime<Utc>) -> bool {
+        match &self.period {
+            Period::Once { start, end } => *start <= now && now < *end,
+            Period::Recurring {
+                cron,
+                duration,
+                timezone,
+            } => {
+                // active if an occurrence started less than `duration` ago
+                let from = (now - *duration).with_timezone(timezone);
+                cron.find_next_occurrence(&from, false)
+                    .is_ok_and(|occurrence| occurrence <= now)
+            }
+        }
+    }
+}
+
+/// Maintenance windows from the config and those created through the api.
+/// Windows created at runtime are in-memory and gone after a restart.
+#[derive(Debug, Default)]
+pub struct MaintenanceRegistry {
+    windows: RwLock<BTreeMap<String, ParsedWindow>>,
+}
+
+impl MaintenanceRegistry {
+    pub fn from_config(windows: Vec<MaintenanceWindow>) -> Result<Self, Error> {
+        let mut parsed = BTreeMap::new();
+        for window in windows {
+            let window = ParsedWindow::parse(window)?;
+            let name = window.window.name.clone();
+            if parsed.insert(name.clone(), window).is_some() {
+                bail!("duplicate maintenance window '{name}'");
+            }
+        }
+        Ok(Self {
+            windows: RwLock::new(parsed),
+        })
+    }
+
+    /// Adds a window, replacing any with the same name.
+    pub async fn add(&self, window: MaintenanceWindow) -> Result<(), Error> {
+        let window = ParsedWindow::parse(window)?;
+        let mut guard = self.windows.write().await;
+        guard.insert(window.window.name.clone(), window);
+        Ok(())
+    }
+
+    /// Removes a window, returning false if there is none by that name.
+    pub async fn remove(&self, name: &str) -> bool {
+        self.windows.write().await.remove(name).is_some()
+    }
+
+    pub async fn list(&self) -> Vec<MaintenanceWindow> {
+        let guard = self.windows.read().await;
+        guard.values().map(|w| w.window.clone()).collect()
+    }
+
+    /// The name of a window the monitor is in maintenance for at `now`.
+    pub async fn window_for(&self, monitor: &Monitor, now: DateTime<Utc>) -> Option<String> {
+        let guard = self.windows.read().await;
+        guard
+            .values()
+            .find(|w| w.targets(monitor) && w.is_active(now))
+            .map(|w| w.window.name.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::get_db_factory;
+    use crate::monitor::tasks::test_monitor;
+    use app::types::{uptime, MonitorStatus};
+    use std::time::Duration;
+
+    fn at(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn window(name: &str) -> MaintenanceWindow {
+        MaintenanceWindow {
+            name: name.to_string(),
+            monitors: vec![],
+            labels: BTreeMap::new(),
+            start: None,
+            end: None,
+            schedule: None,
+            duration: None,
+            timezone: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_one_off_by_name() {
+        let registry = MaintenanceRegistry::from_config(vec![MaintenanceWindow {
+            monitors: vec!["test-endpoint".to_string()],
+            start: Some(at("2026-07-01T10:00:00Z")),
+            end: Some(at("2026-07-01T11:00:00Z")),
+            ..window("deploy")
+        }])
+        .unwrap();
+        let monitor = test_monitor("endpoint", "v1alpha2", serde_json::Value::Null);
+        let other = test_monitor("udp", "v1alpha1", serde_json::Value::Null);
+
+        let during = at("2026-07-01T10:30:00Z");
+        assert_eq!(
+            registry.window_for(&monitor, during).await.as_deref(),
+            Some("deploy")
+        );
+        assert_eq!(registry.window_for(&other, during).await, None);
+        assert_eq!(
+            registry
+                .window_for(&monitor, at("2026-07-01T11:00:00Z"))
+                .await,
+            None
+        );
+
+        assert!(registry.remove("deploy").await);
+        assert_eq!(registry.window_for(&monitor, during).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_recurring_by_label() {
+        let registry = MaintenanceRegistry::default();
+        registry
+            .add(MaintenanceWindow {
+                labels: BTreeMap::from([("team".to_string(), "payments".to_string())]),
+                schedule: Some("0 2 * * sun".to_string()),
+                duration: Some(Duration::from_secs(7200)),
+                timezone: Some("Europe/Berlin".to_string()),
+                ..window("weekly")
+            })
+            .await
+            .unwrap();
+        let mut monitor = test_monitor("endpoint", "v1alpha2", serde_json::Value::Null);
+        monitor
+            .labels
+            .insert("team".to_string(), "payments".to_string());
+
+        // Sunday 02:00 to 04:00 in Berlin is 00:00 to 02:00 UTC in summer
+        for (now, window) in [
+            ("2026-07-05T00:00:00Z", Some("weekly")),
+            ("2026-07-05T01:59:00Z", Some("weekly")),
+            ("2026-07-05T02:00:00Z", None),
+            ("2026-07-04T23:59:00Z", None),
+        ] {
+            assert_eq!(
+                registry.window_for(&monitor, at(now)).await.as_deref(),
+                window,
+                "{now}"
+            );
+        }
+
+        monitor.labels.clear();
+        assert_eq!(
+            registry
+                .window_for(&monitor, at("2026-07-05T01:00:00Z"))
+                .await,
+            None
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let error = |window: MaintenanceWindow| {
+            MaintenanceRegistry::from_config(vec![window])
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert_eq!(
+            error(window("all")),
+            "maintenance window 'all' must target monitors or labels"
+        );
+        assert_eq!(
+            error(MaintenanceWindow {
+                monitors: vec!["a".to_string()],
+                start: Some(at("2026-07-01T10:00:00Z")),
+                schedule: Some("0 2 * * *".to_string()),
+                ..window("mixed")
+            }),
+            "maintenance window 'mixed' needs either start and end, or schedule and duration"
+        );
+        assert_eq!(
+            error(MaintenanceWindow {
+                monitors: vec!["a".to_string()],
+                start: Some(at("2026-07-01T10:00:00Z")),
+                end: Some(at("2026-07-01T09:00:00Z")),
+                ..window("backwards")
+            }),
+            "maintenance window 'backwards' ends before it starts"
+        );
+    }
+
+    #[test]
+    fn test_uptime_ignores_maintenance() {
+        let checked_at = at("2026-07-01T10:00:00Z");
+        let up = MonitorStatus::Up {
+            checked_at,
+            details: None,
+        };
+        let down = MonitorStatus::Down {
+            checked_at,
+            error_reason: "connection refused".to_string(),
+            details: None,
+        };
+
+        let tagged = down.clone().in_maintenance("deploy".to_string());
+        assert_eq!(tagged.underlying(), &down);
+        assert_eq!(
+            uptime(&[up.clone(), down, tagged.clone(), tagged.clone()]),
+            Some(0.5)
+        );
+        assert_eq!(uptime(&[tagged]), None);
+        assert_eq!(uptime(&[up]), Some(1.0));
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_status_is_stored() {
+        let db = get_db_factory(&None).await.unwrap();
+        db.initialize_db().await.unwrap();
+        let repo = db.get_monitor_repository();
+        let monitor = test_monitor("endpoint", "v1alpha2", serde_json::Value::Null);
+        repo.create_monitor(monitor.clone()).await.unwrap();
+
+        let down = MonitorStatus::Down {
+            checked_at: Utc::now(),
+            error_reason: "connection refused".to_string(),
+            details: None,
+        };
+        repo.log_status(
+            monitor.name.clone(),
+            down.clone().in_maintenance("deploy".to_string()),
+        )
+        .await
+        .unwrap();
+
+        let since = Utc::now() - TimeDelta::minutes(1);
+        let statuses = repo
+            .get_statuses_since(monitor.name.clone(), since)
+            .await
+            .unwrap();
+        match &statuses[..] {
+            [MonitorStatus::Maintenance { window, status, .. }] => {
+                assert_eq!(window, "deploy");
+                assert_eq!(**status, down);
+            }
+            other => panic!("expected a maintenance status, got {other:?}"),
+        }
+
+        let later = Utc::now() + TimeDelta::minutes(1);
+        let statuses = repo.get_statuses_since(monitor.name, later).await.unwrap();
+        assert!(statuses.is_empty());
+    }
+}
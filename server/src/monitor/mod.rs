@@ -1,11 +1,26 @@
 mod discovery;
+mod limits;
+mod maintenance;
+mod schedule;
 mod scheduler;
 mod tasks;
 
+pub use crate::monitor::maintenance::{MaintenanceRegistry, MaintenanceRegistryPtr};
+pub use crate::monitor::scheduler::{MonitorSchedulerPtr, TaskHealth};
+pub use crate::monitor::tasks::conversion::upgrade_spec;
+#[cfg(test)]
+pub(crate) use crate::monitor::tasks::heartbeat::test_heartbeat_task;
+pub use crate::monitor::tasks::heartbeat::{
+    HeartbeatPing, HeartbeatRegistry, HeartbeatRegistryPtr,
+};
+
+use crate::config::plugin_config::PluginConfigs;
+use crate::config::scheduler_config::SchedulerConfig;
 use crate::monitor::discovery::MonitorDiscovery;
 use crate::monitor::scheduler::MonitorScheduler;
 use crate::signal::ExitSignaler;
 use app::state::ServerState;
+use std::sync::Arc;
 use tokio::select;
 use tokio::task::JoinHandle;
 
@@ -13,13 +28,25 @@ use tokio::task::JoinHandle;
 pub struct MonitorController {
     server_state: ServerState,
     discovery: MonitorDiscovery,
-    scheduler: MonitorScheduler,
+    scheduler: MonitorSchedulerPtr,
 }
 
 impl MonitorController {
-    pub fn new(server_state: ServerState) -> Self {
+    pub fn new(
+        server_state: ServerState,
+        heartbeats: HeartbeatRegistryPtr,
+        maintenance: MaintenanceRegistryPtr,
+        plugins: PluginConfigs,
+        scheduler_config: &SchedulerConfig,
+    ) -> Self {
         let discovery = MonitorDiscovery::new(&server_state);
-        let scheduler = MonitorScheduler::new(server_state.db_factory.clone());
+        let scheduler = Arc::new(MonitorScheduler::new(
+            server_state.db_factory.clone(),
+            heartbeats,
+            maintenance,
+            plugins,
+            scheduler_config,
+        ));
         Self {
             server_state,
             discovery,
@@ -27,6 +54,10 @@ impl MonitorController {
         }
     }
 
+    pub fn scheduler(&self) -> MonitorSchedulerPtr {
+        self.scheduler.clone()
+    }
+
     pub fn start(self, exit_signaler: ExitSignaler) -> JoinHandle<Result<(), anyhow::Error>> {
         debug!("Starting MonitorController, cfg {:#?}", self);
         tokio::spawn(self.run(exit_signaler))
@@ -75,8 +106,9 @@ impl MonitorController {
         }
 
         self.scheduler
-            .ensure_monitors_scheduled(monitors, exit_signaler)
+            .ensure_monitors_scheduled(monitors, exit_signaler.clone())
             .await?;
+        self.scheduler.supervise(&exit_signaler).await;
 
         Ok(())
     }
@@ -0,0 +1,329 @@
+use anyhow::{anyhow, bail, Error};
+use app::types::{ActiveHours, MonitorConfiguration};
+use chrono::{DateTime, Datelike, Days, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use croner::Cron;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Windows are searched this many days ahead, enough for any weekly pattern.
+const WINDOW_SEARCH_DAYS: u64 = 8;
+/// Cron occurrences tried while looking for one inside the active hours.
+const MAX_CRON_CANDIDATES: usize = 1000;
+
+/// When a monitor's checks run: every `check_interval` or on a cron
+/// `schedule`, in either case only inside the active hours.
+#[derive(Debug)]
+pub struct Schedule {
+    cadence: Cadence,
+    timezone: Tz,
+    windows: Vec<Window>,
+}
+
+#[derive(Debug)]
+enum Cadence {
+    Interval(Duration),
+    Cron(Box<Cron>),
+}
+
+#[derive(Debug, PartialEq)]
+struct Window {
+    /// Days the window opens on, every day if empty.
+    days: Vec<Weekday>,
+    from: NaiveTime,
+    to: NaiveTime,
+}
+
+impl Schedule {
+    pub fn new(config: &MonitorConfiguration) -> Result<Self, Error> {
+        let cadence = match (&config.schedule, config.check_interval) {
+            (Some(schedule), _) => Cadence::Cron(Box::new(parse_cron(schedule)?)),
+            (None, Some(interval)) if !interval.is_zero() => Cadence::Interval(interval),
+            (None, _) => bail!("neither schedule nor check_interval set"),
+        };
+        let timezone = parse_timezone(config.timezone.as_deref())?;
+        let windows = config
+            .active_hours
+            .iter()
+            .map(Window::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Schedule {
+            cadence,
+            timezone,
+            windows,
+        })
+    }
+
+    /// The interval checks run at, if not on a cron schedule.
+    pub fn interval(&self) -> Option<Duration> {
+        match self.cadence {
+            Cadence::Interval(interval) => Some(interval),
+            Cadence::Cron(_) => None,
+        }
+    }
+
+    /// The first check, `delay` from now for interval schedules. Cron
+    /// schedules run at their next occurrence.
+    pub fn first_check(&self, now: DateTime<Utc>, delay: Duration) -> DateTime<Utc> {
+        match &self.cadence {
+            Cadence::Interval(_) => self.next_active(now + delay),
+            Cadence::Cron(cron) => self.next_cron(cron, now),
+        }
+    }
+
+    /// The check following one that finished at `now`.
+    pub fn next_check(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        match &self.cadence {
+            Cadence::Interval(interval) => self.next_active(now + *interval),
+            Cadence::Cron(cron) => self.next_cron(cron, now),
+        }
+    }
+
+    fn next_cron(&self, cron: &Cron, now: DateTime<Utc>) -> DateTime<Utc> {
+        let mut candidate = now.with_timezone(&self.timezone);
+        let mut inclusive = false;
+        for _ in 0..MAX_CRON_CANDIDATES {
+            let Ok(next) = cron.find_next_occurrence(&candidate, inclusive) else {
+                break;
+            };
+            let next = next.with_timezone(&Utc);
+            let active = self.next_active(next);
+            if active == next {
+                return next;
+            }
+            // skip ahead to the window, the occurrence at its opening counts
+            candidate = active.with_timezone(&self.timezone);
+            inclusive = true;
+        }
+        log::warn!("no occurrence of the schedule inside the active hours, checking daily");
+        now + Duration::from_secs(86400)
+    }
+
+    /// `at` if it's inside the active hours, otherwise when they next open.
+    fn next_active(&self, at: DateTime<Utc>) -> DateTime<Utc> {
+        if self.windows.is_empty() || self.is_active(at) {
+            return at;
+        }
+
+        let local = at.with_timezone(&self.timezone).date_naive();
+        (0..WINDOW_SEARCH_DAYS)
+            .filter_map(|offset| local.checked_add_days(Days::new(offset)))
+            .flat_map(|date| {
+                self.windows
+                    .iter()
+                    .filter(move |window| window.opens_on(date.weekday()))
+                    .filter_map(move |window| self.resolve(date, window.from))
+            })
+            .filter(|opening| *opening > at)
+            .min()
+            .unwrap_or(at)
+    }
+
+    fn is_active(&self, at: DateTime<Utc>) -> bool {
+        let local = at.with_timezone(&self.timezone);
+        let (day, time) = (local.weekday(), local.time());
+        self.windows.iter().any(|window| {
+            if window.from < window.to {
+                window.opens_on(day) && window.from <= time && time < window.to
+            } else {
+                // past midnight, belonging to the day it opened on
+                (window.opens_on(day) && time >= window.from)
+                    || (window.opens_on(day.pred()) && time < window.to)
+            }
+        })
+    }
+
+    /// Local wall clock time on `date`, moved past a DST gap if need be.
+    fn resolve(&self, date: NaiveDate, time: NaiveTime) -> Option<DateTime<Utc>> {
+        let local = date.and_time(time);
+        self.timezone
+            .from_local_datetime(&local)
+            .earliest()
+            .or_else(|| {
+                let later = local + chrono::Duration::hours(1);
+                self.timezone.from_local_datetime(&later).earliest()
+            })
+            .map(|at| at.with_timezone(&Utc))
+    }
+}
+
+impl Window {
+    fn parse(hours: &ActiveHours) -> Result<Self, Error> {
+        let time = |value: &str| {
+            // "24:00" closes the window at midnight
+            let value = if value == "24:00" { "00:00" } else { value };
+            NaiveTime::parse_from_str(value, "%H:%M")
+                .map_err(|_| anyhow!("invalid time '{value}' in active_hours, expected HH:MM"))
+        };
+        let from = time(&hours.from)?;
+        let to = time(&hours.to)?;
+        if from == to && hours.to != "24:00" {
+            bail!("active_hours from {} to {} is empty", hours.from, hours.to);
+        }
+
+        let mut days = vec![];
+        for entry in &hours.days {
+            match entry.split_once('-') {
+                Some((first, last)) => {
+                    let (mut day, last) = (weekday(first)?, weekday(last)?);
+                    days.push(day);
+                    while day != last {
+                        day = day.succ();
+                        days.push(day);
+                    }
+                }
+                None => days.push(weekday(entry)?),
+            }
+        }
+
+        Ok(Window { days, from, to })
+    }
+
+    fn opens_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+}
+
+pub(super) fn parse_cron(schedule: &str) -> Result<Cron, Error> {
+    Cron::from_str(schedule).map_err(|e| anyhow!("invalid schedule '{schedule}': {e}"))
+}
+
+/// The named IANA timezone, UTC if `None`.
+pub(super) fn parse_timezone(timezone: Option<&str>) -> Result<Tz, Error> {
+    match timezone {
+        Some(timezone) => timezone
+            .parse::<Tz>()
+            .map_err(|_| anyhow!("unknown timezone '{timezone}'")),
+        None => Ok(Tz::UTC),
+    }
+}
+
+fn weekday(name: &str) -> Result<Weekday, Error> {
+    name.trim()
+        .parse::<Weekday>()
+        .map_err(|_| anyhow!("invalid day '{name}' in active_hours"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn hours(days: &[&str], from: &str, to: &str) -> ActiveHours {
+        ActiveHours {
+            days: days.iter().map(|d| d.to_string()).collect(),
+            from: from.to_string(),
+            to: to.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_interval_in_active_hours() {
+        let schedule = Schedule::new(&MonitorConfiguration {
+            check_interval: Some(Duration::from_secs(600)),
+            timezone: Some("Europe/Berlin".to_string()),
+            active_hours: vec![hours(&["mon-fri"], "08:00", "20:00")],
+            ..Default::default()
+        })
+        .unwrap();
+
+        // Wednesday 10:00 in Berlin (UTC+2 in summer)
+        assert_eq!(
+            schedule.next_check(at("2026-07-01T08:00:00Z")),
+            at("2026-07-01T08:10:00Z")
+        );
+        // 19:55 is followed by Thursday's opening
+        assert_eq!(
+            schedule.next_check(at("2026-07-01T17:55:00Z")),
+            at("2026-07-02T06:00:00Z")
+        );
+        // Friday evening skips the weekend
+        assert_eq!(
+            schedule.next_check(at("2026-07-03T18:00:00Z")),
+            at("2026-07-06T06:00:00Z")
+        );
+        assert_eq!(
+            schedule.first_check(at("2026-07-04T12:00:00Z"), Duration::from_secs(5)),
+            at("2026-07-06T06:00:00Z")
+        );
+    }
+
+    #[test]
+    fn test_cron_with_timezone() {
+        let schedule = Schedule::new(&MonitorConfiguration {
+            schedule: Some("30 6 * * *".to_string()),
+            timezone: Some("America/New_York".to_string()),
+            active_hours: vec![hours(&["sat", "sun"], "00:00", "24:00")],
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(schedule.interval(), None);
+
+        // Wednesday, so the next run is Saturday 06:30 EDT
+        assert_eq!(
+            schedule.next_check(at("2026-07-01T12:00:00Z")),
+            at("2026-07-04T10:30:00Z")
+        );
+        assert_eq!(
+            schedule.next_check(at("2026-07-04T10:30:00Z")),
+            at("2026-07-05T10:30:00Z")
+        );
+    }
+
+    #[test]
+    fn test_overnight_window() {
+        let schedule = Schedule::new(&MonitorConfiguration {
+            check_interval: Some(Duration::from_secs(3600)),
+            active_hours: vec![hours(&["fri"], "22:00", "06:00")],
+            ..Default::default()
+        })
+        .unwrap();
+
+        // Saturday 02:00 still belongs to Friday's window
+        assert_eq!(
+            schedule.next_check(at("2026-07-04T01:00:00Z")),
+            at("2026-07-04T02:00:00Z")
+        );
+        assert_eq!(
+            schedule.next_check(at("2026-07-04T05:30:00Z")),
+            at("2026-07-10T22:00:00Z")
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let error = |config: MonitorConfiguration| Schedule::new(&config).unwrap_err().to_string();
+
+        assert_eq!(
+            error(MonitorConfiguration::default()),
+            "neither schedule nor check_interval set"
+        );
+        assert!(error(MonitorConfiguration {
+            schedule: Some("every day".to_string()),
+            ..Default::default()
+        })
+        .starts_with("invalid schedule 'every day'"));
+        assert_eq!(
+            error(MonitorConfiguration {
+                check_interval: Some(Duration::from_secs(60)),
+                timezone: Some("Mars/Olympus".to_string()),
+                ..Default::default()
+            }),
+            "unknown timezone 'Mars/Olympus'"
+        );
+        assert_eq!(
+            error(MonitorConfiguration {
+                check_interval: Some(Duration::from_secs(60)),
+                active_hours: vec![hours(&["someday"], "08:00", "20:00")],
+                ..Default::default()
+            }),
+            "invalid day 'someday' in active_hours"
+        );
+    }
+}
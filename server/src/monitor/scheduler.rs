@@ -1,35 +1,115 @@
-use crate::monitor::tasks::{monitor_task, TaskFactory, TaskFactoryPtr};
+use crate::config::plugin_config::PluginConfigs;
+use crate::config::scheduler_config::SchedulerConfig;
+use crate::monitor::limits::{CheckLimiter, CheckLimiterPtr};
+use crate::monitor::maintenance::MaintenanceRegistryPtr;
+use crate::monitor::tasks::heartbeat::HeartbeatRegistryPtr;
+use crate::monitor::tasks::{check, monitor_task, RunningTask, TaskFactory, TaskFactoryPtr};
 use crate::signal::ExitSignaler;
-use app::types::Monitor;
+use anyhow::bail;
+use app::types::{Monitor, MonitorControl, MonitorStatus};
 use app::DbFactoryPointer;
 use futures::future::join_all;
-use std::collections::HashMap;
+use migration::async_trait::async_trait;
+use sea_orm::sqlx::types::chrono::Utc;
+use serde::Serialize;
+use serde_json::json;
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::select;
 use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
 
 type MonitorTask = JoinHandle<Result<(), anyhow::Error>>;
+pub type MonitorSchedulerPtr = Arc<MonitorScheduler>;
+
+/// First restart delay after a crash, doubled for every crash in a row.
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(300);
+/// A task running this long since its last restart is no longer crash-looping.
+const STABLE_AFTER: Duration = Duration::from_secs(600);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "kebab-case")]
+pub enum TaskHealth {
+    Running,
+    /// Crashed and waiting to restart, or restarted but not stable yet.
+    CrashLooping {
+        failures: u32,
+        last_error: String,
+    },
+    /// Exited on shutdown.
+    Stopped,
+    Paused,
+}
+
+/// A monitor's task and what it takes to restart it.
+#[derive(Debug)]
+struct SupervisedTask {
+    monitor: Monitor,
+    /// `None` while waiting to restart, while paused, or once stopped.
+    handle: Option<MonitorTask>,
+    /// Kept across pauses, replaced whenever the task is restarted.
+    running: RunningTask,
+    started_at: Instant,
+    failures: u32,
+    last_error: Option<String>,
+    restart_at: Option<Instant>,
+}
+
+impl SupervisedTask {
+    fn health(&self) -> TaskHealth {
+        if self.monitor.paused {
+            return TaskHealth::Paused;
+        }
+        match (&self.handle, &self.last_error) {
+            (Some(_), None) => TaskHealth::Running,
+            (_, Some(last_error)) => TaskHealth::CrashLooping {
+                failures: self.failures,
+                last_error: last_error.clone(),
+            },
+            (None, None) => TaskHealth::Stopped,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct MonitorScheduler {
-    monitor_tasks: Mutex<HashMap<String, MonitorTask>>,
+    monitor_tasks: Mutex<HashMap<String, SupervisedTask>>,
     task_factory: TaskFactoryPtr,
     db_factory: DbFactoryPointer,
+    heartbeats: HeartbeatRegistryPtr,
+    maintenance: MaintenanceRegistryPtr,
+    plugins: PluginConfigs,
+    limiter: CheckLimiterPtr,
 }
 
 impl MonitorScheduler {
-    pub fn new(db_factory: DbFactoryPointer) -> Self {
+    pub fn new(
+        db_factory: DbFactoryPointer,
+        heartbeats: HeartbeatRegistryPtr,
+        maintenance: MaintenanceRegistryPtr,
+        plugins: PluginConfigs,
+        config: &SchedulerConfig,
+    ) -> Self {
         Self {
             monitor_tasks: Mutex::new(HashMap::new()),
             task_factory: Arc::new(TaskFactory::new()),
             db_factory,
+            heartbeats,
+            maintenance,
+            plugins,
+            limiter: Arc::new(CheckLimiter::new(config)),
         }
     }
 
     pub async fn wait_for_shutdown(&self) -> Result<(), anyhow::Error> {
         let mut guard = self.monitor_tasks.lock().await;
-        let tasks = guard.drain().map(|(_k, v)| v).collect::<Vec<_>>();
+        let tasks = guard
+            .drain()
+            .filter_map(|(_k, v)| v.handle)
+            .collect::<Vec<_>>();
         select! {
             _ = join_all(tasks) => {},
             _ = tokio::time::sleep(std::time::Duration::from_secs(30)) => {}
@@ -39,7 +119,12 @@ impl MonitorScheduler {
     }
 
     pub async fn setup(&self) -> Result<(), anyhow::Error> {
-        self.task_factory.register_standard_builders().await;
+        self.task_factory
+            .register_standard_builders(self.db_factory.clone(), self.heartbeats.clone())
+            .await;
+        self.task_factory
+            .register_plugin_builders(&self.plugins)
+            .await;
 
         Ok(())
     }
@@ -53,20 +138,478 @@ impl MonitorScheduler {
         for monitor in monitors.into_iter() {
             if !guard.contains_key(&monitor.name) {
                 let name = monitor.name.clone();
-                let task = self.build_monitor_task(monitor, &exit_signaler);
+                let running = RunningTask::default();
+                let handle = (!monitor.paused).then(|| {
+                    self.build_monitor_task(monitor.clone(), running.clone(), &exit_signaler)
+                });
+                let task = SupervisedTask {
+                    handle,
+                    running,
+                    monitor,
+                    started_at: Instant::now(),
+                    failures: 0,
+                    last_error: None,
+                    restart_at: None,
+                };
                 guard.insert(name, task);
             }
         }
         Ok(())
     }
 
-    fn build_monitor_task(&self, monitor: Monitor, signaler: &ExitSignaler) -> MonitorTask {
+    /// Reaps tasks that returned an error or panicked, records the failure as
+    /// the monitor's status and restarts them with exponential backoff.
+    pub async fn supervise(&self, exit_signaler: &ExitSignaler) {
+        let mut guard = self.monitor_tasks.lock().await;
+        for (name, task) in guard.iter_mut() {
+            if task.handle.as_ref().is_some_and(|h| h.is_finished()) {
+                let handle = task.handle.take().expect("checked above");
+                let error = match handle.await {
+                    Ok(Ok(())) => None,
+                    Ok(Err(e)) => Some(e.to_string()),
+                    Err(e) if e.is_panic() => {
+                        Some(format!("panicked: {}", panic_message(e.into_panic())))
+                    }
+                    Err(_) => None,
+                };
+                match error {
+                    Some(error) => self.record_failure(name, task, error).await,
+                    None => task.last_error = None,
+                }
+            }
+
+            if task.handle.is_none() && task.restart_at.is_some_and(|at| at <= Instant::now()) {
+                log::info!("restarting monitor task {name}");
+                task.handle = Some(self.build_monitor_task(
+                    task.monitor.clone(),
+                    task.running.clone(),
+                    exit_signaler,
+                ));
+                task.started_at = Instant::now();
+                task.restart_at = None;
+            } else if task.handle.is_some() && task.started_at.elapsed() >= STABLE_AFTER {
+                task.failures = 0;
+                task.last_error = None;
+            }
+        }
+    }
+
+    async fn record_failure(&self, name: &str, task: &mut SupervisedTask, error: String) {
+        if task.started_at.elapsed() >= STABLE_AFTER {
+            task.failures = 0;
+        }
+        task.failures += 1;
+        let delay = restart_delay(task.failures);
+        task.restart_at = Some(Instant::now() + delay);
+        log::error!(
+            "monitor task {name} failed ({} in a row), restarting in {delay:?}: {error}",
+            task.failures
+        );
+
+        let status = MonitorStatus::Down {
+            checked_at: Utc::now(),
+            error_reason: error.clone(),
+            details: Some(json!({
+                "failures": task.failures,
+                "restart_in_s": delay.as_secs(),
+            })),
+        };
+        task.last_error = Some(error);
+        let monitor_repo = self.db_factory.get_monitor_repository();
+        if let Err(e) = monitor_repo.log_status(name.to_string(), status).await {
+            log::error!("error logging monitor status: {e}");
+        }
+    }
+
+    pub async fn health(&self) -> BTreeMap<String, TaskHealth> {
+        let guard = self.monitor_tasks.lock().await;
+        guard
+            .iter()
+            .map(|(name, task)| (name.clone(), task.health()))
+            .collect()
+    }
+
+    /// Prometheus text exposition of the scheduler's metrics.
+    pub fn metrics(&self) -> String {
+        self.limiter.metrics()
+    }
+
+    fn build_monitor_task(
+        &self,
+        monitor: Monitor,
+        running: RunningTask,
+        signaler: &ExitSignaler,
+    ) -> MonitorTask {
         let monitor_handle = monitor_task(
             monitor,
             self.db_factory.clone(),
             self.task_factory.clone(),
+            self.limiter.clone(),
+            self.maintenance.clone(),
+            running,
             signaler.new_exit_signal(),
         );
         tokio::spawn(monitor_handle)
     }
 }
+
+#[async_trait]
+impl MonitorControl for MonitorScheduler {
+    async fn pause(&self, name: &str) -> Result<(), anyhow::Error> {
+        let mut guard = self.monitor_tasks.lock().await;
+        let Some(task) = guard.get_mut(name) else {
+            bail!("monitor '{name}' is not scheduled");
+        };
+        let monitor_repo = self.db_factory.get_monitor_repository();
+        monitor_repo.set_paused(name.to_string(), true).await?;
+
+        if let Some(handle) = task.handle.take() {
+            handle.abort();
+        }
+        task.monitor.paused = true;
+        task.restart_at = None;
+        log::info!("paused monitor {name}");
+        Ok(())
+    }
+
+    /// The task is started again by the next `supervise`.
+    async fn resume(&self, name: &str) -> Result<(), anyhow::Error> {
+        let mut guard = self.monitor_tasks.lock().await;
+        let Some(task) = guard.get_mut(name) else {
+            bail!("monitor '{name}' is not scheduled");
+        };
+        let monitor_repo = self.db_factory.get_monitor_repository();
+        monitor_repo.set_paused(name.to_string(), false).await?;
+
+        if task.monitor.paused && task.handle.is_none() {
+            task.failures = 0;
+            task.last_error = None;
+            task.restart_at = Some(Instant::now());
+        }
+        task.monitor.paused = false;
+        log::info!("resumed monitor {name}");
+        Ok(())
+    }
+
+    /// Surveys the task the monitor is running, so kinds that keep state
+    /// between checks report what they've seen. A monitor that hasn't built
+    /// its task yet, or is paused since startup, gets one built here.
+    async fn check_now(&self, name: &str) -> Result<MonitorStatus, anyhow::Error> {
+        let (monitor, running) = match self.monitor_tasks.lock().await.get(name) {
+            Some(task) => (task.monitor.clone(), task.running.clone()),
+            None => bail!("monitor '{name}' is not scheduled"),
+        };
+        let task = match running.get() {
+            Some(task) => task,
+            None => {
+                let task = self.task_factory.construct_task(&monitor).await?;
+                running.set(task.clone());
+                task
+            }
+        };
+
+        let status = check(&monitor, &task, &self.limiter, &self.maintenance).await;
+        let monitor_repo = self.db_factory.get_monitor_repository();
+        monitor_repo
+            .log_status(name.to_string(), status.clone())
+            .await?;
+        Ok(status)
+    }
+}
+
+fn restart_delay(failures: u32) -> Duration {
+    let factor = 2u32.saturating_pow(failures.saturating_sub(1));
+    RESTART_DELAY.saturating_mul(factor).min(MAX_RESTART_DELAY)
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::get_db_factory;
+    use crate::monitor::tasks::{test_monitor, MonitorTask, TaskBuilder, TaskPtr};
+    use crate::monitor::{HeartbeatRegistry, MaintenanceRegistry};
+    use app::types::MonitorConfiguration;
+    use migration::async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Panicking;
+
+    #[async_trait]
+    impl MonitorTask for Panicking {
+        async fn survey(&self) -> Result<MonitorStatus, anyhow::Error> {
+            panic!("boom")
+        }
+    }
+
+    #[derive(Debug)]
+    struct PanickingBuilder;
+
+    #[async_trait]
+    impl TaskBuilder for PanickingBuilder {
+        fn get_api_version(&self) -> String {
+            "v1alpha1".to_string()
+        }
+
+        fn get_kind(&self) -> String {
+            "panicking".to_string()
+        }
+
+        async fn build(&self, _monitor: Monitor) -> Result<TaskPtr, anyhow::Error> {
+            Ok(Arc::new(Panicking))
+        }
+    }
+
+    struct AlwaysUp;
+
+    #[async_trait]
+    impl MonitorTask for AlwaysUp {
+        async fn survey(&self) -> Result<MonitorStatus, anyhow::Error> {
+            Ok(MonitorStatus::Up {
+                checked_at: Utc::now(),
+                details: None,
+            })
+        }
+    }
+
+    #[derive(Debug)]
+    struct AlwaysUpBuilder;
+
+    #[async_trait]
+    impl TaskBuilder for AlwaysUpBuilder {
+        fn get_api_version(&self) -> String {
+            "v1alpha1".to_string()
+        }
+
+        fn get_kind(&self) -> String {
+            "always-up".to_string()
+        }
+
+        async fn build(&self, _monitor: Monitor) -> Result<TaskPtr, anyhow::Error> {
+            Ok(Arc::new(AlwaysUp))
+        }
+    }
+
+    /// Counts the tasks it builds.
+    #[derive(Debug)]
+    struct CountingBuilder(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl TaskBuilder for CountingBuilder {
+        fn get_api_version(&self) -> String {
+            "v1alpha1".to_string()
+        }
+
+        fn get_kind(&self) -> String {
+            "counting".to_string()
+        }
+
+        async fn build(&self, _monitor: Monitor) -> Result<TaskPtr, anyhow::Error> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(Arc::new(AlwaysUp))
+        }
+    }
+
+    async fn test_scheduler(db: &DbFactoryPointer) -> MonitorScheduler {
+        let scheduler = MonitorScheduler::new(
+            db.clone(),
+            Arc::new(HeartbeatRegistry::new()),
+            Arc::new(MaintenanceRegistry::default()),
+            PluginConfigs::default(),
+            &SchedulerConfig::default(),
+        );
+        scheduler
+            .task_factory
+            .bulk_register(vec![Arc::new(PanickingBuilder), Arc::new(AlwaysUpBuilder)])
+            .await;
+        scheduler
+    }
+
+    async fn wait_until_finished(scheduler: &MonitorScheduler, name: &str) {
+        for _ in 0..100 {
+            let guard = scheduler.monitor_tasks.lock().await;
+            if guard[name].handle.as_ref().is_some_and(|h| h.is_finished()) {
+                return;
+            }
+            drop(guard);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("task {name} didn't finish");
+    }
+
+    #[tokio::test]
+    async fn test_restart_after_panic() {
+        let db = get_db_factory(&None).await.unwrap();
+        db.initialize_db().await.unwrap();
+        let scheduler = test_scheduler(&db).await;
+
+        let mut monitor = test_monitor("panicking", "v1alpha1", serde_json::Value::Null);
+        monitor.configuration = Some(MonitorConfiguration {
+            check_interval: Some(Duration::from_millis(100)),
+            ..Default::default()
+        });
+        db.get_monitor_repository()
+            .create_monitor(monitor.clone())
+            .await
+            .unwrap();
+        let name = monitor.name.clone();
+        let signaler = ExitSignaler::new();
+        scheduler
+            .ensure_monitors_scheduled(vec![monitor], signaler.clone())
+            .await
+            .unwrap();
+
+        for failures in 1..=2 {
+            wait_until_finished(&scheduler, &name).await;
+            scheduler.supervise(&signaler).await;
+            assert_eq!(
+                scheduler.health().await[&name],
+                TaskHealth::CrashLooping {
+                    failures,
+                    last_error: "panicked: boom".to_string()
+                }
+            );
+
+            // statuses logged within the same second can't be told apart
+            if failures == 1 {
+                let repo = db.get_monitor_repository();
+                match repo.get_monitor(name.clone()).await.unwrap().current_status {
+                    Some(MonitorStatus::Down {
+                        error_reason,
+                        details,
+                        ..
+                    }) => {
+                        assert_eq!(error_reason, "panicked: boom");
+                        assert_eq!(details.unwrap()["failures"], 1);
+                    }
+                    other => panic!("expected Down, got {other:?}"),
+                }
+            }
+
+            let mut guard = scheduler.monitor_tasks.lock().await;
+            let task = guard.get_mut(&name).unwrap();
+            assert!(task.handle.is_none());
+            let restart_at = task.restart_at.unwrap();
+            assert!(
+                restart_at > Instant::now()
+                    && restart_at <= Instant::now() + restart_delay(failures)
+            );
+            task.restart_at = Some(Instant::now());
+            drop(guard);
+
+            scheduler.supervise(&signaler).await;
+            assert!(scheduler.monitor_tasks.lock().await[&name].handle.is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pause_resume_and_check_now() {
+        let db = get_db_factory(&None).await.unwrap();
+        db.initialize_db().await.unwrap();
+        let scheduler = test_scheduler(&db).await;
+        let repo = db.get_monitor_repository();
+
+        let mut monitor = test_monitor("always-up", "v1alpha1", serde_json::Value::Null);
+        monitor.configuration = Some(MonitorConfiguration {
+            check_interval: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        });
+        repo.create_monitor(monitor.clone()).await.unwrap();
+        let name = monitor.name.clone();
+        let signaler = ExitSignaler::new();
+        scheduler
+            .ensure_monitors_scheduled(vec![monitor.clone()], signaler.clone())
+            .await
+            .unwrap();
+        assert_eq!(scheduler.health().await[&name], TaskHealth::Running);
+
+        scheduler.pause(&name).await.unwrap();
+        assert_eq!(scheduler.health().await[&name], TaskHealth::Paused);
+        assert!(scheduler.monitor_tasks.lock().await[&name].handle.is_none());
+        assert!(repo.get_monitor(name.clone()).await.unwrap().paused);
+
+        // paused monitors can still be checked on demand
+        let status = scheduler.check_now(&name).await.unwrap();
+        assert!(matches!(status, MonitorStatus::Up { .. }));
+        assert!(matches!(
+            repo.get_monitor(name.clone()).await.unwrap().current_status,
+            Some(MonitorStatus::Up { .. })
+        ));
+
+        // a restarted server keeps the monitor paused
+        let restarted = test_scheduler(&db).await;
+        let stored = repo.get_monitor(name.clone()).await.unwrap();
+        restarted
+            .ensure_monitors_scheduled(vec![stored], signaler.clone())
+            .await
+            .unwrap();
+        assert_eq!(restarted.health().await[&name], TaskHealth::Paused);
+
+        scheduler.resume(&name).await.unwrap();
+        scheduler.supervise(&signaler).await;
+        assert_eq!(scheduler.health().await[&name], TaskHealth::Running);
+        assert!(!repo.get_monitor(name.clone()).await.unwrap().paused);
+
+        assert!(scheduler.pause("nope").await.is_err());
+        assert!(scheduler.check_now("nope").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_check_now_surveys_the_running_task() {
+        let db = get_db_factory(&None).await.unwrap();
+        db.initialize_db().await.unwrap();
+        let scheduler = test_scheduler(&db).await;
+        let builds = Arc::new(AtomicUsize::new(0));
+        scheduler
+            .task_factory
+            .bulk_register(vec![Arc::new(CountingBuilder(builds.clone()))])
+            .await;
+
+        let mut monitor = test_monitor("counting", "v1alpha1", serde_json::Value::Null);
+        monitor.configuration = Some(MonitorConfiguration {
+            check_interval: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        });
+        db.get_monitor_repository()
+            .create_monitor(monitor.clone())
+            .await
+            .unwrap();
+        let name = monitor.name.clone();
+        scheduler
+            .ensure_monitors_scheduled(vec![monitor], ExitSignaler::new())
+            .await
+            .unwrap();
+        for _ in 0..100 {
+            if scheduler.monitor_tasks.lock().await[&name]
+                .running
+                .get()
+                .is_some()
+            {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        for _ in 0..2 {
+            let status = scheduler.check_now(&name).await.unwrap();
+            assert!(matches!(status, MonitorStatus::Up { .. }));
+        }
+        assert_eq!(builds.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_restart_delay() {
+        assert_eq!(restart_delay(1), Duration::from_secs(5));
+        assert_eq!(restart_delay(3), Duration::from_secs(20));
+        assert_eq!(restart_delay(40), MAX_RESTART_DELAY);
+    }
+}
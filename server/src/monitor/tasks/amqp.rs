@@ -0,0 +1,495 @@
+use crate::monitor::tasks::spec::MonitorSpec;
+use crate::monitor::tasks::{MonitorTask, TaskPtr};
+use anyhow::{bail, Error};
+use app::types::{Monitor, MonitorStatus};
+use macros::monitor_kind;
+use migration::async_trait::async_trait;
+use sea_orm::sqlx::types::chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_PORT: u16 = 5672;
+const PROTOCOL_HEADER: &[u8] = b"AMQP\x00\x00\x09\x01";
+const FRAME_METHOD: u8 = 1;
+const FRAME_HEARTBEAT: u8 = 8;
+const FRAME_END: u8 = 0xce;
+const MAX_FRAME_BYTES: usize = 128 * 1024;
+
+const CONNECTION: u16 = 10;
+const START: u16 = 10;
+const START_OK: u16 = 11;
+const TUNE: u16 = 30;
+const TUNE_OK: u16 = 31;
+const OPEN: u16 = 40;
+const OPEN_OK: u16 = 41;
+const CLOSE: u16 = 50;
+const CLOSE_OK: u16 = 51;
+const REPLY_SUCCESS: u16 = 200;
+
+/// Encodes the AMQP 0-9-1 method argument types.
+#[derive(Default)]
+struct AmqpWriter(Vec<u8>);
+
+impl AmqpWriter {
+    fn u8(mut self, value: u8) -> Self {
+        self.0.push(value);
+        self
+    }
+
+    fn u16(mut self, value: u16) -> Self {
+        self.0.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    fn u32(mut self, value: u32) -> Self {
+        self.0.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    fn short_str(mut self, value: &str) -> Self {
+        self.0.push(value.len() as u8);
+        self.0.extend_from_slice(value.as_bytes());
+        self
+    }
+
+    fn long_str(mut self, value: &[u8]) -> Self {
+        self.0
+            .extend_from_slice(&(value.len() as u32).to_be_bytes());
+        self.0.extend_from_slice(value);
+        self
+    }
+
+    /// A field table holding only long string values.
+    fn table(self, fields: &[(&str, &str)]) -> Self {
+        let mut table = AmqpWriter::default();
+        for (key, value) in fields {
+            table = table.short_str(key).u8(b'S').long_str(value.as_bytes());
+        }
+        self.long_str(&table.0)
+    }
+}
+
+struct AmqpReader<'a>(&'a [u8]);
+
+impl<'a> AmqpReader<'a> {
+    fn raw(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < len {
+            bail!("truncated frame");
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.raw(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_be_bytes(self.raw(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_be_bytes(self.raw(4)?.try_into()?))
+    }
+
+    fn short_str(&mut self) -> Result<String, Error> {
+        let len = self.u8()? as usize;
+        Ok(String::from_utf8_lossy(self.raw(len)?).into_owned())
+    }
+
+    fn long_str(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.u32()? as usize;
+        self.raw(len)
+    }
+
+    /// Reads the string values of a field table, skipping everything else.
+    fn table(&mut self) -> Result<BTreeMap<String, String>, Error> {
+        let mut table = AmqpReader(self.long_str()?);
+        let mut strings = BTreeMap::new();
+        while !table.0.is_empty() {
+            let key = table.short_str()?;
+            let size = match table.u8()? {
+                b'S' => {
+                    let value = String::from_utf8_lossy(table.long_str()?).into_owned();
+                    strings.insert(key, value);
+                    continue;
+                }
+                b'F' | b'A' | b'x' => table.u32()? as usize,
+                b't' | b'b' | b'B' => 1,
+                b's' | b'u' => 2,
+                b'I' | b'i' | b'f' => 4,
+                b'l' | b'd' | b'T' => 8,
+                b'D' => 5,
+                b'V' => 0,
+                // can't know how far to skip, keep what was read so far
+                _ => break,
+            };
+            table.raw(size)?;
+        }
+        Ok(strings)
+    }
+}
+
+struct Method {
+    class: u16,
+    method: u16,
+    args: Vec<u8>,
+}
+
+struct AmqpConnection {
+    stream: TcpStream,
+}
+
+impl AmqpConnection {
+    async fn send_method(
+        &mut self,
+        class: u16,
+        method: u16,
+        args: AmqpWriter,
+    ) -> Result<(), Error> {
+        let payload = AmqpWriter::default().u16(class).u16(method).0;
+        let frame = AmqpWriter::default()
+            .u8(FRAME_METHOD)
+            .u16(0)
+            .u32((payload.len() + args.0.len()) as u32)
+            .0
+            .into_iter()
+            .chain(payload)
+            .chain(args.0)
+            .chain([FRAME_END])
+            .collect::<Vec<_>>();
+        self.stream.write_all(&frame).await?;
+        Ok(())
+    }
+
+    /// Reads the next method frame on channel 0, failing on Connection.Close.
+    async fn read_method(&mut self) -> Result<Method, Error> {
+        loop {
+            let frame_type = self.stream.read_u8().await?;
+            let _channel = self.stream.read_u16().await?;
+            let size = self.stream.read_u32().await? as usize;
+            if size > MAX_FRAME_BYTES {
+                bail!("frame of {size} bytes is too large");
+            }
+            let mut payload = vec![0u8; size + 1];
+            self.stream.read_exact(&mut payload).await?;
+            if payload.pop() != Some(FRAME_END) {
+                bail!("malformed frame");
+            }
+
+            match frame_type {
+                FRAME_HEARTBEAT => continue,
+                FRAME_METHOD => {}
+                other => bail!("unexpected frame type {other}"),
+            }
+
+            let mut reader = AmqpReader(&payload);
+            let class = reader.u16()?;
+            let method = reader.u16()?;
+            if (class, method) == (CONNECTION, CLOSE) {
+                let code = reader.u16()?;
+                let text = reader.short_str()?;
+                let _ = self
+                    .send_method(CONNECTION, CLOSE_OK, AmqpWriter::default())
+                    .await;
+                bail!("connection refused: {code} {text}");
+            }
+            return Ok(Method {
+                class,
+                method,
+                args: reader.0.to_vec(),
+            });
+        }
+    }
+
+    async fn expect_method(&mut self, method: u16, name: &str) -> Result<Vec<u8>, Error> {
+        let received = self.read_method().await?;
+        if (received.class, received.method) != (CONNECTION, method) {
+            bail!(
+                "expected connection.{name}, got method {}.{}",
+                received.class,
+                received.method
+            );
+        }
+        Ok(received.args)
+    }
+}
+
+struct AmqpMonitor {
+    host: String,
+    port: u16,
+    vhost: String,
+    username: String,
+    password: String,
+    timeout: Duration,
+}
+
+impl AmqpMonitor {
+    async fn check(&self) -> Result<serde_json::Value, Error> {
+        let started = Instant::now();
+        let stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+        let mut conn = AmqpConnection { stream };
+        conn.stream.write_all(PROTOCOL_HEADER).await?;
+
+        let start = match conn.expect_method(START, "start").await {
+            Ok(start) => start,
+            Err(err) => {
+                // a server that doesn't speak 0-9-1 answers with its own header
+                bail!("no amqp 0-9-1 handshake: {err}")
+            }
+        };
+        let mut reader = AmqpReader(&start);
+        let version = (reader.u8()?, reader.u8()?);
+        let properties = reader.table()?;
+        let mechanisms = String::from_utf8_lossy(reader.long_str()?).into_owned();
+        if !mechanisms.split_whitespace().any(|m| m == "PLAIN") {
+            bail!("server doesn't offer PLAIN authentication, only {mechanisms}");
+        }
+
+        let response = format!("\0{}\0{}", self.username, self.password);
+        conn.send_method(
+            CONNECTION,
+            START_OK,
+            AmqpWriter::default()
+                .table(&[("product", "whoopsie")])
+                .short_str("PLAIN")
+                .long_str(response.as_bytes())
+                .short_str("en_US"),
+        )
+        .await?;
+
+        let tune = conn.expect_method(TUNE, "tune").await?;
+        let mut reader = AmqpReader(&tune);
+        let (channel_max, frame_max, heartbeat) = (reader.u16()?, reader.u32()?, reader.u16()?);
+        // heartbeats are turned off, the connection doesn't live long enough
+        conn.send_method(
+            CONNECTION,
+            TUNE_OK,
+            AmqpWriter::default().u16(channel_max).u32(frame_max).u16(0),
+        )
+        .await?;
+
+        conn.send_method(
+            CONNECTION,
+            OPEN,
+            AmqpWriter::default()
+                .short_str(&self.vhost)
+                .short_str("")
+                .u8(0),
+        )
+        .await?;
+        conn.expect_method(OPEN_OK, "open-ok").await?;
+        let handshake_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+        conn.send_method(
+            CONNECTION,
+            CLOSE,
+            AmqpWriter::default()
+                .u16(REPLY_SUCCESS)
+                .short_str("bye")
+                .u16(0)
+                .u16(0),
+        )
+        .await?;
+        let _ = conn.expect_method(CLOSE_OK, "close-ok").await;
+
+        Ok(serde_json::json!({
+            "handshake_ms": handshake_ms,
+            "protocol": format!("0-{}-{}", version.0, version.1),
+            "product": properties.get("product"),
+            "version": properties.get("version"),
+            "channel_max": channel_max,
+            "frame_max": frame_max,
+            "heartbeat": heartbeat,
+        }))
+    }
+}
+
+#[async_trait]
+impl MonitorTask for AmqpMonitor {
+    async fn survey(&self) -> Result<MonitorStatus, Error> {
+        match tokio::time::timeout(self.timeout, self.check()).await {
+            Ok(Ok(details)) => Ok(MonitorStatus::Up {
+                checked_at: Utc::now(),
+                details: Some(details),
+            }),
+            Ok(Err(err)) => Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: err.to_string(),
+                details: None,
+            }),
+            Err(_) => Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: format!("timed out after {:?}", self.timeout),
+                details: None,
+            }),
+        }
+    }
+}
+
+fn default_vhost() -> String {
+    "/".to_string()
+}
+
+fn default_guest() -> String {
+    "guest".to_string()
+}
+
+#[monitor_kind(kind = "amqp", api_version = "v1alpha1")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct V1Alpha1AmqpMonitorSpec {
+    #[check(non_empty)]
+    pub host: String,
+    #[check(min = 1)]
+    pub port: Option<u16>,
+    #[serde(default = "default_vhost")]
+    #[check(with = short_string)]
+    pub vhost: String,
+    /// Defaults to RabbitMQ's `guest` user.
+    #[serde(default = "default_guest")]
+    pub username: String,
+    #[serde(default = "default_guest")]
+    pub password: String,
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+}
+
+fn short_string(value: &str) -> Result<(), String> {
+    match value.len() > u8::MAX as usize {
+        true => Err("must not be longer than 255 bytes".to_string()),
+        false => Ok(()),
+    }
+}
+
+#[async_trait]
+impl MonitorSpec for V1Alpha1AmqpMonitorSpec {
+    async fn build(self, _monitor: Monitor) -> Result<TaskPtr, Error> {
+        Ok(Arc::new(AmqpMonitor {
+            host: self.host,
+            port: self.port.unwrap_or(DEFAULT_PORT),
+            vhost: self.vhost,
+            username: self.username,
+            password: self.password,
+            timeout: self.timeout.unwrap_or(DEFAULT_TIMEOUT),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::tasks::{test_monitor, TaskBuilder};
+    use serde_json::json;
+    use std::net::SocketAddr;
+    use tokio::net::TcpListener;
+
+    /// Broker with a single `/orders` vhost.
+    async fn spawn_broker() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut conn = AmqpConnection { stream };
+                    let mut header = [0u8; 8];
+                    conn.stream.read_exact(&mut header).await.unwrap();
+                    assert_eq!(header, PROTOCOL_HEADER);
+
+                    let start = AmqpWriter::default()
+                        .u8(0)
+                        .u8(9)
+                        .table(&[("product", "RabbitMQ"), ("version", "4.1.0")])
+                        .long_str(b"AMQPLAIN PLAIN")
+                        .long_str(b"en_US");
+                    conn.send_method(CONNECTION, START, start).await.unwrap();
+                    conn.expect_method(START_OK, "start-ok").await.unwrap();
+
+                    let tune = AmqpWriter::default().u16(2047).u32(131072).u16(60);
+                    conn.send_method(CONNECTION, TUNE, tune).await.unwrap();
+                    conn.expect_method(TUNE_OK, "tune-ok").await.unwrap();
+
+                    let open = conn.expect_method(OPEN, "open").await.unwrap();
+                    let vhost = AmqpReader(&open).short_str().unwrap();
+                    if vhost != "/orders" {
+                        let close = AmqpWriter::default()
+                            .u16(530)
+                            .short_str(&format!("NOT_ALLOWED - vhost {vhost} not found"))
+                            .u16(CONNECTION)
+                            .u16(OPEN);
+                        conn.send_method(CONNECTION, CLOSE, close).await.unwrap();
+                        return;
+                    }
+                    let open_ok = AmqpWriter::default().short_str("");
+                    conn.send_method(CONNECTION, OPEN_OK, open_ok)
+                        .await
+                        .unwrap();
+
+                    // the client's close
+                    let _ = conn.read_method().await;
+                });
+            }
+        });
+        addr
+    }
+
+    async fn survey(addr: SocketAddr, spec: serde_json::Value) -> MonitorStatus {
+        let mut spec = spec;
+        spec["host"] = json!("127.0.0.1");
+        spec["port"] = json!(addr.port());
+        let monitor = test_monitor("amqp", "v1alpha1", spec);
+        let task = V1Alpha1AmqpMonitorBuilder {}.build(monitor).await.unwrap();
+        task.survey().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_handshake() {
+        let addr = spawn_broker().await;
+
+        match survey(addr, json!({ "vhost": "/orders" })).await {
+            MonitorStatus::Up { details, .. } => {
+                let details = details.unwrap();
+                assert_eq!(details["product"], "RabbitMQ");
+                assert_eq!(details["version"], "4.1.0");
+                assert_eq!(details["protocol"], "0-0-9");
+                assert_eq!(details["frame_max"], 131072);
+            }
+            other => panic!("expected Up, got {other:?}"),
+        }
+
+        match survey(addr, json!({})).await {
+            MonitorStatus::Down { error_reason, .. } => assert_eq!(
+                error_reason,
+                "connection refused: 530 NOT_ALLOWED - vhost / not found"
+            ),
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_table_skips_other_types() {
+        let mut table = AmqpWriter::default()
+            .short_str("capabilities")
+            .u8(b'F')
+            .long_str(b"\x0cpublisher_ct\x01")
+            .short_str("weight")
+            .u8(b'I')
+            .u32(7)
+            .short_str("product")
+            .u8(b'S')
+            .long_str(b"RabbitMQ");
+        table = AmqpWriter::default().long_str(&table.0);
+
+        let strings = AmqpReader(&table.0).table().unwrap();
+        assert_eq!(
+            strings,
+            BTreeMap::from([("product".to_string(), "RabbitMQ".to_string())])
+        );
+    }
+}
@@ -0,0 +1,455 @@
+use crate::monitor::tasks::{MonitorTask, TaskPtr};
+use anyhow::{anyhow, bail, Error};
+use app::types::{Monitor, MonitorStatus};
+use migration::async_trait::async_trait;
+use rhai::{Dynamic, Engine, EvalAltResult, Scope, AST};
+use sea_orm::sqlx::types::chrono::Utc;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+// Rhai only checks sizes value by value and not at all when assigning into a
+// map or array, so scripts are bounded by what each operation can allocate:
+// at most MAX_OPERATIONS / 8 stored values of MAX_STRING_BYTES, about 100 MiB.
+// The body is not a script string, so it can be larger (see `Body`).
+const MAX_OPERATIONS: u64 = 50_000;
+const MAX_STRING_BYTES: usize = 16 * 1024;
+const MAX_COLLECTION_SIZE: usize = 1_000;
+const MAX_CALL_LEVELS: usize = 32;
+const MAX_EXPR_DEPTH: usize = 64;
+/// Response bodies beyond this are cut off before the script sees them.
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// What a check saw, handed to assert scripts.
+#[derive(Debug)]
+pub struct Observation {
+    pub status: MonitorStatus,
+    pub response: Option<HttpResponse>,
+}
+
+impl From<MonitorStatus> for Observation {
+    fn from(status: MonitorStatus) -> Self {
+        Observation {
+            status,
+            response: None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct HttpResponse {
+    pub status_code: u16,
+    /// Lowercase names, repeated headers joined with ", ".
+    pub headers: BTreeMap<String, String>,
+    pub body: String,
+}
+
+impl HttpResponse {
+    pub async fn read(mut response: reqwest::Response) -> Result<HttpResponse, Error> {
+        let mut headers = BTreeMap::<String, String>::new();
+        for (name, value) in response.headers() {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            headers
+                .entry(name.as_str().to_string())
+                .and_modify(|joined| {
+                    joined.push_str(", ");
+                    joined.push_str(&value);
+                })
+                .or_insert_with(|| value.into_owned());
+        }
+
+        let status_code = response.status().as_u16();
+        let mut body = vec![];
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() >= MAX_BODY_BYTES {
+                body.truncate(MAX_BODY_BYTES);
+                break;
+            }
+        }
+
+        Ok(HttpResponse {
+            status_code,
+            headers,
+            body: String::from_utf8_lossy(&body).into_owned(),
+        })
+    }
+}
+
+/// Status picked by a script through `up()`, `degraded(msg)` or `down(msg)`.
+#[derive(Debug, Clone)]
+enum Verdict {
+    Up,
+    Degraded(String),
+    Down(String),
+}
+
+/// The response body as scripts see it. Shared rather than copied into a
+/// string, so it is not held to `MAX_STRING_BYTES`; whatever scripts take
+/// out of it is.
+#[derive(Debug, Clone)]
+struct Body(Arc<str>);
+
+fn register_body(engine: &mut Engine) {
+    engine
+        .register_type_with_name::<Body>("Body")
+        .register_get("len", |body: &mut Body| body.0.chars().count() as i64)
+        .register_fn("is_empty", |body: &mut Body| body.0.is_empty())
+        .register_fn("contains", |body: &mut Body, text: &str| {
+            body.0.contains(text)
+        })
+        .register_fn("starts_with", |body: &mut Body, text: &str| {
+            body.0.starts_with(text)
+        })
+        .register_fn("ends_with", |body: &mut Body, text: &str| {
+            body.0.ends_with(text)
+        })
+        .register_fn("==", |body: &mut Body, text: &str| *body.0 == *text)
+        .register_fn("!=", |body: &mut Body, text: &str| *body.0 != *text)
+        .register_fn("to_string", |body: &mut Body| body.0.to_string())
+        .register_fn("to_debug", |body: &mut Body| format!("{:?}", &*body.0))
+        .register_fn(
+            "parse_json",
+            |body: &mut Body| -> Result<Dynamic, Box<EvalAltResult>> {
+                let value = serde_json::from_str::<serde_json::Value>(&body.0)
+                    .map_err(|e| format!("invalid JSON body: {e}"))?;
+                rhai::serde::to_dynamic(value)
+            },
+        );
+}
+
+fn engine(monitor_name: &str) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_string_size(MAX_STRING_BYTES)
+        .set_max_array_size(MAX_COLLECTION_SIZE)
+        .set_max_map_size(MAX_COLLECTION_SIZE)
+        .set_max_call_levels(MAX_CALL_LEVELS)
+        .set_max_expr_depths(MAX_EXPR_DEPTH, MAX_EXPR_DEPTH)
+        .disable_symbol("eval");
+    register_body(&mut engine);
+
+    let name = monitor_name.to_string();
+    engine.on_print(move |text| log::info!("assert script of {name}: {text}"));
+    let name = monitor_name.to_string();
+    engine.on_debug(move |text, _, _| log::debug!("assert script of {name}: {text}"));
+
+    engine
+        .register_type_with_name::<Verdict>("Verdict")
+        .register_fn("up", || Verdict::Up)
+        .register_fn("degraded", |reason: &str| Verdict::Degraded(reason.into()))
+        .register_fn("down", |reason: &str| Verdict::Down(reason.into()));
+    engine
+}
+
+/// Takes the `assert` script out of the monitor's spec, so builders never
+/// see it.
+pub fn take_script(monitor: &mut Monitor) -> Result<Option<String>, Error> {
+    let Some(spec) = monitor.spec.as_object_mut() else {
+        return Ok(None);
+    };
+    match spec.remove("assert") {
+        None => Ok(None),
+        Some(serde_json::Value::String(script)) => Ok(Some(script)),
+        Some(_) => bail!("assert must be a script"),
+    }
+}
+
+/// Runs the monitor's task and lets the script override its status.
+///
+/// The script sees `status` ("up", "degraded" or "down"), `reason`,
+/// `latency_ms` and `details`, plus `status_code`, `headers` and `body` for
+/// monitors that made an HTTP request. `body` offers `len`, `contains`,
+/// `starts_with`, `ends_with`, `parse_json()` and `==`; used as a string, as
+/// in `${body}`, it must fit the script's string limit. The script can
+/// return `up()`,
+/// `degraded(msg)` or `down(msg)`, `false` for a plain failure, or nothing
+/// to keep the monitor's own status.
+pub struct AssertedTask {
+    inner: TaskPtr,
+    engine: Engine,
+    script: AST,
+}
+
+impl AssertedTask {
+    pub fn new(inner: TaskPtr, monitor_name: &str, script: &str) -> Result<Self, Error> {
+        let engine = engine(monitor_name);
+        let script = engine
+            .compile(script)
+            .map_err(|e| anyhow!("invalid assert script: {e}"))?;
+        Ok(AssertedTask {
+            inner,
+            engine,
+            script,
+        })
+    }
+
+    fn evaluate(&self, observation: Observation, latency_ms: f64) -> MonitorStatus {
+        let (status, reason, details) = match observation.status.underlying() {
+            MonitorStatus::Up { details, .. } => ("up", "", details),
+            MonitorStatus::Degraded {
+                error_reason,
+                details,
+                ..
+            } => ("degraded", error_reason.as_str(), details),
+            MonitorStatus::Down {
+                error_reason,
+                details,
+                ..
+            } => ("down", error_reason.as_str(), details),
+            MonitorStatus::Unknown | MonitorStatus::Maintenance { .. } => ("unknown", "", &None),
+        };
+
+        let mut scope = Scope::new();
+        scope
+            .push_constant("status", status.to_string())
+            .push_constant("reason", reason.to_string())
+            .push_constant("latency_ms", latency_ms)
+            .push_constant(
+                "details",
+                rhai::serde::to_dynamic(details).unwrap_or(Dynamic::UNIT),
+            );
+        match &observation.response {
+            Some(response) => scope
+                .push_constant("status_code", response.status_code as i64)
+                .push_constant(
+                    "headers",
+                    rhai::serde::to_dynamic(&response.headers).unwrap_or(Dynamic::UNIT),
+                )
+                .push_constant("body", Body(response.body.as_str().into())),
+            None => scope
+                .push_constant("status_code", ())
+                .push_constant("headers", ())
+                .push_constant("body", ()),
+        };
+
+        let details = details.clone();
+        let result = self
+            .engine
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &self.script);
+        let checked_at = Utc::now();
+        let verdict = match result {
+            Ok(result) if result.is_unit() || result.as_bool() == Ok(true) => {
+                return observation.status
+            }
+            Ok(result) if result.as_bool() == Ok(false) => {
+                Verdict::Down("assert script returned false".to_string())
+            }
+            Ok(result) => match result.try_cast::<Verdict>() {
+                Some(verdict) => verdict,
+                None => Verdict::Down(
+                    "assert script must return up(), degraded(msg), down(msg) or a bool"
+                        .to_string(),
+                ),
+            },
+            Err(e) => Verdict::Down(format!("assert script failed: {e}")),
+        };
+
+        match verdict {
+            Verdict::Up => MonitorStatus::Up {
+                checked_at,
+                details,
+            },
+            Verdict::Degraded(error_reason) => MonitorStatus::Degraded {
+                checked_at,
+                error_reason,
+                details,
+            },
+            Verdict::Down(error_reason) => MonitorStatus::Down {
+                checked_at,
+                error_reason,
+                details,
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl MonitorTask for AssertedTask {
+    async fn survey(&self) -> Result<MonitorStatus, Error> {
+        let started = Instant::now();
+        let observation = self.inner.observe().await?;
+        let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+        Ok(self.evaluate(observation, latency_ms))
+    }
+}
+
+pub fn wrap(inner: TaskPtr, monitor_name: &str, script: &str) -> Result<TaskPtr, Error> {
+    Ok(Arc::new(AssertedTask::new(inner, monitor_name, script)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::tasks::{spec, test_monitor, TaskFactory};
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use axum::Router;
+    use serde_json::json;
+
+    struct Fixed(MonitorStatus);
+
+    #[async_trait]
+    impl MonitorTask for Fixed {
+        async fn survey(&self) -> Result<MonitorStatus, Error> {
+            Ok(self.0.clone())
+        }
+    }
+
+    async fn assert_on(status: MonitorStatus, script: &str) -> MonitorStatus {
+        let task = wrap(Arc::new(Fixed(status)), "test", script).unwrap();
+        task.survey().await.unwrap()
+    }
+
+    fn up(details: serde_json::Value) -> MonitorStatus {
+        MonitorStatus::Up {
+            checked_at: Utc::now(),
+            details: Some(details),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_details() {
+        let script = r#"
+            if details.lag_s > 30 { return down(`lag is ${details.lag_s}s`) }
+            if details.lag_s > 10 { return degraded("lagging") }
+        "#;
+
+        match assert_on(up(json!({ "lag_s": 12 })), script).await {
+            MonitorStatus::Degraded {
+                error_reason,
+                details,
+                ..
+            } => {
+                assert_eq!(error_reason, "lagging");
+                assert_eq!(details.unwrap()["lag_s"], 12);
+            }
+            other => panic!("expected Degraded, got {other:?}"),
+        }
+        match assert_on(up(json!({ "lag_s": 45 })), script).await {
+            MonitorStatus::Down { error_reason, .. } => assert_eq!(error_reason, "lag is 45s"),
+            other => panic!("expected Down, got {other:?}"),
+        }
+        assert!(matches!(
+            assert_on(up(json!({ "lag_s": 1 })), script).await,
+            MonitorStatus::Up { .. }
+        ));
+
+        let down = MonitorStatus::Down {
+            checked_at: Utc::now(),
+            error_reason: "connection refused".to_string(),
+            details: None,
+        };
+        let script = r#"if status == "down" && reason.contains("refused") { up() }"#;
+        assert!(matches!(
+            assert_on(down, script).await,
+            MonitorStatus::Up { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_sandbox() {
+        match assert_on(up(json!({})), "loop {}").await {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert!(error_reason.starts_with("assert script failed: Too many operations"))
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+
+        match assert_on(up(json!({})), r#"let s = "x"; loop { s += s; }"#).await {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert!(error_reason.starts_with("assert script failed: Length of string"))
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+
+        // distinct strings, so nothing is shared between the array's items
+        let hoard = r#"
+            let s = "x";
+            while s.len < 8192 { s += s; }
+            let a = [];
+            for i in 0..1000 { a.push(s + i); }
+        "#;
+        match assert_on(up(json!({})), hoard).await {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert!(error_reason.starts_with("assert script failed: Length of string"))
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+
+        // the body alone may be larger than the script's own strings
+        let task = AssertedTask::new(
+            Arc::new(Fixed(MonitorStatus::Unknown)),
+            "test",
+            r#"body.len == 1048576 && body.ends_with("y")"#,
+        )
+        .unwrap();
+        let observation = Observation {
+            status: up(json!({})),
+            response: Some(HttpResponse {
+                status_code: 200,
+                body: "x".repeat(MAX_BODY_BYTES - 1) + "y",
+                ..Default::default()
+            }),
+        };
+        assert!(matches!(
+            task.evaluate(observation, 1.0),
+            MonitorStatus::Up { .. }
+        ));
+
+        match assert_on(up(json!({})), "false").await {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert_eq!(error_reason, "assert script returned false")
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+
+        let invalid = wrap(Arc::new(Fixed(MonitorStatus::Unknown)), "test", "if {");
+        assert!(invalid
+            .err()
+            .unwrap()
+            .to_string()
+            .starts_with("invalid assert script"));
+    }
+
+    #[tokio::test]
+    async fn test_http_response() {
+        let app = Router::new().route(
+            "/health",
+            get(|| async {
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    [("x-shard", "eu-1")],
+                    r#"{"db":"down"}"#,
+                )
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let factory = TaskFactory::new();
+        factory
+            .bulk_register(spec::registered_builders(&spec::test_build_context().await))
+            .await;
+
+        let script = r#"
+            if status_code != 200 && parse_json(body).db == "down" {
+                down(`${headers["x-shard"]} answered ${status_code}: ${body}`)
+            }
+        "#;
+        let monitor = test_monitor(
+            "endpoint",
+            "v1alpha1",
+            json!({ "uri": format!("http://{addr}/health"), "assert": script }),
+        );
+        let task = factory.construct_task(&monitor).await.unwrap();
+        match task.survey().await.unwrap() {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert_eq!(error_reason, r#"eu-1 answered 503: {"db":"down"}"#)
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use crate::monitor::tasks::spec::StatefulMonitorSpec;
+use crate::monitor::tasks::{MonitorTask, TaskPtr};
+use anyhow::{bail, Error};
+use app::types::{Monitor, MonitorStatus};
+use app::DbFactoryPointer;
+use macros::monitor_kind;
+use migration::async_trait::async_trait;
+use sea_orm::sqlx::types::chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum CompositeRule {
+    AllUp,
+    AnyUp,
+    AtLeast(usize),
+}
+
+impl CompositeRule {
+    fn required(&self, total: usize) -> usize {
+        match self {
+            CompositeRule::AllUp => total,
+            CompositeRule::AnyUp => 1,
+            CompositeRule::AtLeast(n) => *n,
+        }
+    }
+}
+
+struct CompositeMonitor {
+    db: DbFactoryPointer,
+    name: String,
+    monitors: Vec<String>,
+    selector: Option<BTreeMap<String, String>>,
+    rule: CompositeRule,
+}
+
+impl CompositeMonitor {
+    fn is_member(&self, monitor: &Monitor) -> bool {
+        if monitor.name == self.name {
+            return false;
+        }
+
+        self.monitors.contains(&monitor.name)
+            || self
+                .selector
+                .as_ref()
+                .is_some_and(|selector| monitor.matches_labels(selector))
+    }
+}
+
+#[async_trait]
+impl MonitorTask for CompositeMonitor {
+    async fn survey(&self) -> Result<MonitorStatus, Error> {
+        let all_monitors = self.db.get_monitor_repository().get_monitors().await?;
+        let members = all_monitors
+            .into_iter()
+            .filter(|m| self.is_member(m))
+            .collect::<Vec<_>>();
+
+        let missing = self
+            .monitors
+            .iter()
+            .filter(|name| !members.iter().any(|m| &&m.name == name))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let total = members.len() + missing.len();
+        if total == 0 {
+            bail!("no monitors matched the composite");
+        }
+
+        let mut up = 0;
+        let mut not_up = missing
+            .iter()
+            .map(|name| format!("{name} (not found)"))
+            .collect::<Vec<_>>();
+        let mut statuses = BTreeMap::new();
+
+        for member in &members {
+            // members in maintenance count as whatever their check found
+            let status = match member
+                .current_status
+                .as_ref()
+                .map(MonitorStatus::underlying)
+            {
+                Some(MonitorStatus::Up { .. }) => {
+                    up += 1;
+                    "up"
+                }
+                Some(MonitorStatus::Degraded { .. }) => {
+                    // still serving, but the composite can't be fully up
+                    up += 1;
+                    not_up.push(format!("{} (degraded)", member.name));
+                    "degraded"
+                }
+                Some(MonitorStatus::Down { .. }) => {
+                    not_up.push(format!("{} (down)", member.name));
+                    "down"
+                }
+                Some(MonitorStatus::Unknown | MonitorStatus::Maintenance { .. }) | None => {
+                    not_up.push(format!("{} (unknown)", member.name));
+                    "unknown"
+                }
+            };
+            statuses.insert(member.name.clone(), status);
+        }
+
+        let required = self.rule.required(total);
+        let details = Some(serde_json::json!({
+            "up": up,
+            "required": required,
+            "total": total,
+            "members": statuses,
+        }));
+
+        if up < required {
+            Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: format!(
+                    "{up} of {total} monitors up, {required} required: {}",
+                    not_up.join(", ")
+                ),
+                details,
+            })
+        } else if !not_up.is_empty() {
+            Ok(MonitorStatus::Degraded {
+                checked_at: Utc::now(),
+                error_reason: format!("{up} of {total} monitors up: {}", not_up.join(", ")),
+                details,
+            })
+        } else {
+            Ok(MonitorStatus::Up {
+                checked_at: Utc::now(),
+                details,
+            })
+        }
+    }
+}
+
+#[monitor_kind(kind = "composite", api_version = "v1alpha1", state = DbFactoryPointer)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct V1Alpha1CompositeMonitorSpec {
+    /// Monitors included by name.
+    #[serde(default)]
+    pub monitors: Vec<String>,
+    /// Monitors included because they carry all of these labels.
+    pub selector: Option<BTreeMap<String, String>>,
+    /// `all_up`, `any_up` or `at_least: N`.
+    #[check(with = at_least_one)]
+    pub rule: CompositeRule,
+}
+
+fn at_least_one(rule: &CompositeRule) -> Result<(), String> {
+    match rule {
+        CompositeRule::AtLeast(0) => Err("at_least must be greater than zero".to_string()),
+        _ => Ok(()),
+    }
+}
+
+#[async_trait]
+impl StatefulMonitorSpec<DbFactoryPointer> for V1Alpha1CompositeMonitorSpec {
+    async fn build(self, monitor: Monitor, db: &DbFactoryPointer) -> Result<TaskPtr, Error> {
+        if self.monitors.is_empty() && self.selector.as_ref().is_none_or(|s| s.is_empty()) {
+            bail!("spec: requires monitors or a selector");
+        }
+        if self.monitors.contains(&monitor.name) {
+            bail!("spec.monitors: can't include the composite itself");
+        }
+
+        Ok(Arc::new(CompositeMonitor {
+            db: db.clone(),
+            name: monitor.name,
+            monitors: self.monitors,
+            selector: self.selector,
+            rule: self.rule,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::get_db_factory;
+    use crate::monitor::tasks::{test_monitor, TaskBuilder};
+    use serde_json::json;
+
+    fn up() -> MonitorStatus {
+        MonitorStatus::Up {
+            checked_at: Utc::now(),
+            details: None,
+        }
+    }
+
+    fn down() -> MonitorStatus {
+        MonitorStatus::Down {
+            checked_at: Utc::now(),
+            error_reason: "boom".to_string(),
+            details: None,
+        }
+    }
+
+    async fn seeded_db(members: Vec<(&str, &str, MonitorStatus)>) -> DbFactoryPointer {
+        let db = get_db_factory(&None).await.unwrap();
+        db.initialize_db().await.unwrap();
+        let repo = db.get_monitor_repository();
+
+        for (name, team, status) in members {
+            let mut monitor = test_monitor("endpoint", "v1alpha1", json!({}));
+            monitor.name = name.to_string();
+            monitor.labels = BTreeMap::from([("team".to_string(), team.to_string())]);
+            repo.create_monitor(monitor).await.unwrap();
+            repo.log_status(name.to_string(), status).await.unwrap();
+        }
+
+        db
+    }
+
+    async fn survey(db: &DbFactoryPointer, spec: serde_json::Value) -> MonitorStatus {
+        let monitor = test_monitor("composite", "v1alpha1", spec);
+        let task = V1Alpha1CompositeMonitorBuilder { state: db.clone() }
+            .build(monitor)
+            .await
+            .unwrap();
+        task.survey().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_rules() {
+        let db = seeded_db(vec![
+            ("checkout-http", "checkout", up()),
+            ("checkout-tcp", "checkout", up()),
+            ("checkout-dns", "checkout", down()),
+            ("search-http", "search", up()),
+        ])
+        .await;
+
+        let all_up = survey(
+            &db,
+            json!({ "selector": { "team": "checkout" }, "rule": "all_up" }),
+        )
+        .await;
+        match all_up {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert_eq!(
+                    error_reason,
+                    "2 of 3 monitors up, 3 required: checkout-dns (down)"
+                )
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+
+        let at_least = survey(
+            &db,
+            json!({ "selector": { "team": "checkout" }, "rule": { "at_least": 2 } }),
+        )
+        .await;
+        assert!(
+            matches!(at_least, MonitorStatus::Degraded { .. }),
+            "{at_least:?}"
+        );
+
+        let any_up = survey(
+            &db,
+            json!({ "monitors": ["checkout-http", "search-http"], "rule": "any_up" }),
+        )
+        .await;
+        match any_up {
+            MonitorStatus::Up { details, .. } => assert_eq!(details.unwrap()["total"], 2),
+            other => panic!("expected Up, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_missing_members_count_as_down() {
+        let db = seeded_db(vec![("checkout-http", "checkout", up())]).await;
+
+        let status = survey(
+            &db,
+            json!({ "monitors": ["checkout-http", "checkout-gone"], "rule": "all_up" }),
+        )
+        .await;
+
+        match status {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert!(error_reason.contains("checkout-gone (not found)"))
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_specs_are_rejected() {
+        let db = seeded_db(vec![]).await;
+        let builder = V1Alpha1CompositeMonitorBuilder { state: db };
+
+        for spec in [
+            json!({ "rule": "all_up" }),
+            json!({ "monitors": ["test-composite"], "rule": "all_up" }),
+            json!({ "monitors": ["a"], "rule": { "at_least": 0 } }),
+        ] {
+            let monitor = test_monitor("composite", "v1alpha1", spec.clone());
+            assert!(builder.build(monitor).await.is_err(), "{spec}");
+        }
+    }
+}
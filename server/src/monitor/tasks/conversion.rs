@@ -0,0 +1,176 @@
+use anyhow::{anyhow, bail, Error};
+use app::types::Monitor;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// More steps than any kind has versions means the conversions loop.
+const MAX_STEPS: usize = 16;
+
+/// Upgrades `kind` specs from apiVersion `from` to `to`. Kinds submit one per
+/// step with `inventory::submit!`, chaining v1alpha1 → v1alpha2 → v1, and
+/// only need a builder for the last version.
+pub struct Conversion {
+    pub kind: &'static str,
+    pub from: &'static str,
+    pub to: &'static str,
+    pub convert: fn(serde_json::Value) -> Result<serde_json::Value, Error>,
+    /// Warns about monitors still written against `from`.
+    pub deprecated: bool,
+}
+
+inventory::collect!(Conversion);
+
+/// Converts through the old spec type's `Into` the new one.
+pub fn via<From, To>(spec: serde_json::Value) -> Result<serde_json::Value, Error>
+where
+    From: DeserializeOwned + Into<To>,
+    To: Serialize,
+{
+    let spec: From = serde_json::from_value(spec)?;
+    Ok(serde_json::to_value(spec.into())?)
+}
+
+fn next(kind: &str, api_version: &str) -> Option<&'static Conversion> {
+    inventory::iter::<Conversion>
+        .into_iter()
+        .find(|c| c.kind == kind && c.from == api_version)
+}
+
+/// The apiVersion `upgrade_spec` ends up at.
+pub fn latest_version(kind: &str, api_version: &str) -> String {
+    let mut version = api_version;
+    for _ in 0..MAX_STEPS {
+        match next(kind, version) {
+            Some(conversion) => version = conversion.to,
+            None => break,
+        }
+    }
+    version.to_string()
+}
+
+/// Upgrades a spec to the latest apiVersion of its kind, returning whether
+/// anything changed. The `assert` script is carried over untouched, and a
+/// failing conversion leaves the spec at the last version it reached.
+pub fn upgrade_spec(
+    name: &str,
+    kind: &str,
+    api_version: &mut String,
+    spec: &mut serde_json::Value,
+) -> Result<bool, Error> {
+    let mut steps = 0;
+    while let Some(conversion) = next(kind, api_version) {
+        steps += 1;
+        if steps > MAX_STEPS {
+            bail!("conversions of {kind} loop at {api_version}");
+        }
+        if conversion.deprecated {
+            log::warn!(
+                "monitor {name} uses deprecated {kind}/{}, upgrading it to {kind}/{}",
+                conversion.from,
+                latest_version(kind, conversion.from)
+            );
+        }
+
+        let mut old = spec.clone();
+        let script = old.as_object_mut().and_then(|s| s.remove("assert"));
+        let mut converted = (conversion.convert)(old).map_err(|e| {
+            anyhow!(
+                "converting {kind}/{} to {kind}/{}: {e}",
+                conversion.from,
+                conversion.to
+            )
+        })?;
+        if let (Some(script), Some(converted)) = (script, converted.as_object_mut()) {
+            converted.insert("assert".to_string(), script);
+        }
+
+        *spec = converted;
+        *api_version = conversion.to.to_string();
+    }
+    Ok(steps > 0)
+}
+
+pub fn upgrade(monitor: &mut Monitor) -> Result<bool, Error> {
+    upgrade_spec(
+        &monitor.name,
+        &monitor.kind,
+        &mut monitor.api_version,
+        &mut monitor.spec,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::tasks::test_monitor;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Deserialize)]
+    struct V1 {
+        host: String,
+    }
+
+    #[derive(Serialize)]
+    struct V2 {
+        hosts: Vec<String>,
+    }
+
+    impl From<V1> for V2 {
+        fn from(spec: V1) -> Self {
+            V2 {
+                hosts: vec![spec.host],
+            }
+        }
+    }
+
+    fn add_port(mut spec: serde_json::Value) -> Result<serde_json::Value, Error> {
+        spec["port"] = json!(80);
+        Ok(spec)
+    }
+
+    inventory::submit! {
+        Conversion {
+            kind: "conversion-test",
+            from: "v1alpha1",
+            to: "v1alpha2",
+            convert: via::<V1, V2>,
+            deprecated: true,
+        }
+    }
+
+    inventory::submit! {
+        Conversion {
+            kind: "conversion-test",
+            from: "v1alpha2",
+            to: "v1",
+            convert: add_port,
+            deprecated: false,
+        }
+    }
+
+    #[test]
+    fn test_upgrade_chain() {
+        let mut monitor = test_monitor(
+            "conversion-test",
+            "v1alpha1",
+            json!({ "host": "db", "assert": "up()" }),
+        );
+        assert!(upgrade(&mut monitor).unwrap());
+        assert_eq!(monitor.api_version, "v1");
+        assert_eq!(
+            monitor.spec,
+            json!({ "hosts": ["db"], "port": 80, "assert": "up()" })
+        );
+
+        assert!(!upgrade(&mut monitor).unwrap());
+        assert_eq!(latest_version("conversion-test", "v1alpha2"), "v1");
+        assert_eq!(latest_version("other", "v1alpha1"), "v1alpha1");
+
+        let mut monitor = test_monitor("conversion-test", "v1alpha1", json!({ "hosts": [] }));
+        assert_eq!(
+            upgrade(&mut monitor).unwrap_err().to_string(),
+            "converting conversion-test/v1alpha1 to conversion-test/v1alpha2: missing field `host`"
+        );
+    }
+}
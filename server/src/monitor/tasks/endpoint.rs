@@ -1,33 +1,57 @@
-use crate::monitor::tasks::{MonitorTask, TaskBuilder, TaskBuilderPtr, TaskPtr};
+use crate::monitor::tasks::assert::{HttpResponse, Observation};
+use crate::monitor::tasks::conversion::{self, Conversion};
+use crate::monitor::tasks::spec::MonitorSpec;
+use crate::monitor::tasks::{MonitorTask, TaskPtr};
 use anyhow::Error;
 use app::types::{Monitor, MonitorStatus};
+use macros::monitor_kind;
 use migration::async_trait::async_trait;
 use sea_orm::sqlx::types::chrono;
 use sea_orm::sqlx::types::chrono::Utc;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-
-pub fn get_builders() -> Vec<TaskBuilderPtr> {
-    vec![Arc::new(V1Alpha1EndpointMonitorBuilder {})]
-}
+use std::time::Duration;
 
 struct EndpointMonitor {
-    pub uri: String,
+    pub url: String,
+    pub client: reqwest::Client,
 }
 
 #[async_trait]
 impl MonitorTask for EndpointMonitor {
     async fn survey(&self) -> Result<MonitorStatus, Error> {
-        let resp = reqwest::get(self.uri.as_str()).await;
+        let resp = self.client.get(self.url.as_str()).send().await;
 
         match resp {
             Ok(_) => Ok(MonitorStatus::Up {
                 checked_at: chrono::Utc::now(),
+                details: None,
             }),
             Err(err) => Ok(MonitorStatus::Down {
                 checked_at: Utc::now(),
                 error_reason: err.to_string(),
+                details: None,
+            }),
+        }
+    }
+
+    async fn observe(&self) -> Result<Observation, Error> {
+        let resp = self.client.get(self.url.as_str()).send().await;
+
+        match resp {
+            Ok(resp) => Ok(Observation {
+                status: MonitorStatus::Up {
+                    checked_at: Utc::now(),
+                    details: None,
+                },
+                response: Some(HttpResponse::read(resp).await?),
             }),
+            Err(err) => Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: err.to_string(),
+                details: None,
+            }
+            .into()),
         }
     }
 }
@@ -37,22 +61,50 @@ struct V1Alpha1EndpointMonitorSpec {
     pub uri: String,
 }
 
-#[derive(Debug)]
-struct V1Alpha1EndpointMonitorBuilder {}
+#[monitor_kind(kind = "endpoint", api_version = "v1alpha2")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct V1Alpha2EndpointMonitorSpec {
+    #[check(non_empty)]
+    pub url: String,
+    /// No timeout unless set.
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub timeout: Option<Duration>,
+}
 
-#[async_trait]
-impl TaskBuilder for V1Alpha1EndpointMonitorBuilder {
-    fn get_api_version(&self) -> String {
-        "v1alpha1".to_string()
+impl From<V1Alpha1EndpointMonitorSpec> for V1Alpha2EndpointMonitorSpec {
+    fn from(spec: V1Alpha1EndpointMonitorSpec) -> Self {
+        V1Alpha2EndpointMonitorSpec {
+            url: spec.uri,
+            timeout: None,
+        }
     }
+}
 
-    fn get_kind(&self) -> String {
-        "endpoint".to_string()
+inventory::submit! {
+    Conversion {
+        kind: "endpoint",
+        from: "v1alpha1",
+        to: "v1alpha2",
+        convert: conversion::via::<V1Alpha1EndpointMonitorSpec, V1Alpha2EndpointMonitorSpec>,
+        deprecated: true,
     }
+}
 
-    async fn build(&self, monitor: Monitor) -> Result<TaskPtr, Error> {
-        let spec = serde_json::from_value::<V1Alpha1EndpointMonitorSpec>(monitor.spec)?;
+#[async_trait]
+impl MonitorSpec for V1Alpha2EndpointMonitorSpec {
+    async fn build(self, _monitor: Monitor) -> Result<TaskPtr, Error> {
+        let mut client = reqwest::Client::builder();
+        if let Some(timeout) = self.timeout {
+            client = client.timeout(timeout);
+        }
 
-        Ok(Arc::new(EndpointMonitor { uri: spec.uri }))
+        Ok(Arc::new(EndpointMonitor {
+            url: self.url,
+            client: client.build()?,
+        }))
     }
 }
@@ -0,0 +1,312 @@
+use crate::monitor::tasks::spec::MonitorSpec;
+use crate::monitor::tasks::{MonitorTask, TaskPtr};
+use anyhow::Error;
+use app::types::{Monitor, MonitorStatus};
+use macros::monitor_kind;
+use migration::async_trait::async_trait;
+use nix::sys::signal::{killpg, Signal};
+use nix::unistd::Pid;
+use sea_orm::sqlx::types::chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_OUTPUT_BYTES: usize = 1024;
+
+struct ExecMonitor {
+    command: String,
+    args: Vec<String>,
+    env: HashMap<String, String>,
+    working_dir: Option<String>,
+    degraded_exit_codes: Vec<i32>,
+    timeout: Duration,
+}
+
+impl ExecMonitor {
+    fn down(&self, error_reason: String) -> MonitorStatus {
+        MonitorStatus::Down {
+            checked_at: Utc::now(),
+            error_reason,
+            details: None,
+        }
+    }
+}
+
+#[async_trait]
+impl MonitorTask for ExecMonitor {
+    async fn survey(&self) -> Result<MonitorStatus, Error> {
+        let mut cmd = Command::new(&self.command);
+        cmd.args(&self.args)
+            .envs(&self.env)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .process_group(0);
+        if let Some(dir) = &self.working_dir {
+            cmd.current_dir(dir);
+        }
+
+        let started = Instant::now();
+        let mut child = match cmd.spawn() {
+            Ok(child) => child,
+            Err(e) => return Ok(self.down(format!("failed to run '{}': {e}", self.command))),
+        };
+
+        // dropped when the survey times out or is cancelled on shutdown
+        let mut group = ProcessGroupGuard(child.id());
+
+        let stdout = read_output(child.stdout.take());
+        let stderr = read_output(child.stderr.take());
+        let waited = async { tokio::try_join!(child.wait(), stdout, stderr) };
+        let (status, stdout, stderr) = match tokio::time::timeout(self.timeout, waited).await {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => {
+                return Ok(self.down(format!("failed to wait for '{}': {e}", self.command)))
+            }
+            Err(_) => return Ok(self.down(format!("timed out after {:?}", self.timeout))),
+        };
+        // the child is reaped, its pid may already belong to someone else
+        group.disarm();
+
+        let stdout = truncate_output(&stdout);
+        let stderr = truncate_output(&stderr);
+        let exit_code = status.code();
+        let details = Some(serde_json::json!({
+            "exit_code": exit_code,
+            "duration_ms": started.elapsed().as_secs_f64() * 1000.0,
+            "stdout": stdout,
+            "stderr": stderr,
+        }));
+
+        let error_reason = || {
+            let code = match exit_code {
+                Some(code) => format!("exited with code {code}"),
+                None => "terminated by signal".to_string(),
+            };
+            let output = [stdout.as_str(), stderr.as_str()]
+                .into_iter()
+                .filter(|s| !s.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            if output.is_empty() {
+                code
+            } else {
+                format!("{code}: {output}")
+            }
+        };
+
+        match exit_code {
+            Some(0) => Ok(MonitorStatus::Up {
+                checked_at: Utc::now(),
+                details,
+            }),
+            Some(code) if self.degraded_exit_codes.contains(&code) => Ok(MonitorStatus::Degraded {
+                checked_at: Utc::now(),
+                error_reason: error_reason(),
+                details,
+            }),
+            _ => Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: error_reason(),
+                details,
+            }),
+        }
+    }
+}
+
+/// Kills every process in the child's process group when dropped, so scripts
+/// that spawn their own children don't outlive a timed out or cancelled check.
+struct ProcessGroupGuard(Option<u32>);
+
+impl ProcessGroupGuard {
+    fn disarm(&mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for ProcessGroupGuard {
+    fn drop(&mut self) {
+        if let Some(pid) = self.0 {
+            // the group is usually gone already, ESRCH is expected
+            let _ = killpg(Pid::from_raw(pid as i32), Signal::SIGKILL);
+        }
+    }
+}
+
+/// Reads at most one byte more than `truncate_output` keeps and discards the
+/// rest, so a chatty command can't block on a full pipe or fill our memory.
+async fn read_output(pipe: Option<impl AsyncRead + Unpin>) -> std::io::Result<Vec<u8>> {
+    let mut output = Vec::new();
+    if let Some(mut pipe) = pipe {
+        (&mut pipe)
+            .take(MAX_OUTPUT_BYTES as u64 + 1)
+            .read_to_end(&mut output)
+            .await?;
+        tokio::io::copy(&mut pipe, &mut tokio::io::sink()).await?;
+    }
+    Ok(output)
+}
+
+fn truncate_output(output: &[u8]) -> String {
+    let text = String::from_utf8_lossy(output);
+    let text = text.trim();
+    if text.len() <= MAX_OUTPUT_BYTES {
+        return text.to_string();
+    }
+
+    let mut end = MAX_OUTPUT_BYTES;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &text[..end])
+}
+
+#[monitor_kind(kind = "exec", api_version = "v1alpha1")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct V1Alpha1ExecMonitorSpec {
+    #[check(non_empty)]
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    pub working_dir: Option<String>,
+    /// Exit codes reported as Degraded rather than Down, e.g. `[1]` for the
+    /// Nagios plugin convention of 0 = OK, 1 = WARNING, 2 = CRITICAL, 3 = UNKNOWN.
+    #[serde(default)]
+    pub degraded_exit_codes: Vec<i32>,
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+}
+
+#[async_trait]
+impl MonitorSpec for V1Alpha1ExecMonitorSpec {
+    async fn build(self, _monitor: Monitor) -> Result<TaskPtr, Error> {
+        Ok(Arc::new(ExecMonitor {
+            command: self.command,
+            args: self.args,
+            env: self.env,
+            working_dir: self.working_dir,
+            degraded_exit_codes: self.degraded_exit_codes,
+            timeout: self.timeout.unwrap_or(DEFAULT_TIMEOUT),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::tasks::{test_monitor, TaskBuilder};
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    async fn survey(spec: serde_json::Value) -> MonitorStatus {
+        let monitor = test_monitor("exec", "v1alpha1", spec);
+        let task = V1Alpha1ExecMonitorBuilder {}.build(monitor).await.unwrap();
+        task.survey().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_exit_codes_map_to_status() {
+        let up = survey(json!({ "command": "sh", "args": ["-c", "echo OK - all good"] })).await;
+        match up {
+            MonitorStatus::Up { details, .. } => {
+                assert_eq!(details.unwrap()["stdout"], "OK - all good")
+            }
+            other => panic!("expected Up, got {other:?}"),
+        }
+
+        let degraded = survey(json!({
+            "command": "sh",
+            "args": ["-c", "echo WARNING - disk 85%; exit 1"],
+            "degraded_exit_codes": [1],
+        }))
+        .await;
+        match degraded {
+            MonitorStatus::Degraded { error_reason, .. } => {
+                assert_eq!(error_reason, "exited with code 1: WARNING - disk 85%")
+            }
+            other => panic!("expected Degraded, got {other:?}"),
+        }
+
+        let down = survey(json!({
+            "command": "sh",
+            "args": ["-c", "echo CRITICAL >&2; exit 2"],
+            "degraded_exit_codes": [1],
+        }))
+        .await;
+        match down {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert_eq!(error_reason, "exited with code 2: CRITICAL")
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_env_and_working_dir() {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("marker"), "").unwrap();
+
+        let status = survey(json!({
+            "command": "sh",
+            "args": ["-c", "test -f marker && test \"$EXPECTED\" = yes"],
+            "env": { "EXPECTED": "yes" },
+            "working_dir": dir.path(),
+        }))
+        .await;
+
+        assert!(matches!(status, MonitorStatus::Up { .. }), "{status:?}");
+    }
+
+    #[tokio::test]
+    async fn test_output_is_truncated() {
+        let status = survey(json!({
+            "command": "sh",
+            "args": ["-c", "head -c 5000000 /dev/zero | tr '\\0' x; exit 2"],
+        }))
+        .await;
+
+        match status {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert!(error_reason.len() < MAX_OUTPUT_BYTES + 64);
+                assert!(error_reason.ends_with("..."));
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timeout_kills_process_group() {
+        let dir = TempDir::new().unwrap();
+        let marker = dir.path().join("survived");
+
+        let status = survey(json!({
+            "command": "sh",
+            "args": ["-c", format!("(sleep 1; touch {}) & sleep 5", marker.display())],
+            "timeout": "200ms",
+        }))
+        .await;
+
+        match status {
+            MonitorStatus::Down { error_reason, .. } => assert!(error_reason.contains("timed out")),
+            other => panic!("expected Down, got {other:?}"),
+        }
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(!marker.exists(), "background child outlived the check");
+    }
+
+    #[tokio::test]
+    async fn test_missing_command_is_down() {
+        let status = survey(json!({ "command": "/nonexistent/check" })).await;
+        assert!(matches!(status, MonitorStatus::Down { .. }), "{status:?}");
+    }
+}
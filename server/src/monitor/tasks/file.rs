@@ -0,0 +1,342 @@
+use crate::monitor::tasks::spec::{valid_regex, MonitorSpec};
+use crate::monitor::tasks::{MonitorTask, TaskPtr};
+use anyhow::{bail, Error};
+use app::types::{Monitor, MonitorStatus};
+use humantime_serde::re::humantime::format_duration;
+use macros::monitor_kind;
+use migration::async_trait::async_trait;
+use regex::bytes::Regex;
+use sea_orm::sqlx::types::chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Only the head of the newest file is searched for `content`.
+const MAX_CONTENT_BYTES: u64 = 1024 * 1024;
+
+struct Newest {
+    path: PathBuf,
+    is_dir: bool,
+    modified: SystemTime,
+    size: u64,
+    matches: usize,
+}
+
+#[derive(Clone)]
+struct FileMonitor {
+    path: String,
+    max_age: Option<Duration>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    content: Option<Regex>,
+}
+
+impl FileMonitor {
+    fn find_newest(&self) -> Result<Option<Newest>, Error> {
+        let mut newest: Option<Newest> = None;
+        let mut matches = 0;
+
+        for entry in glob::glob(&self.path)? {
+            // rotated away since it was listed, or unreadable
+            let Ok(path) = entry else {
+                continue;
+            };
+            let Ok(metadata) = std::fs::metadata(&path) else {
+                continue;
+            };
+            let Ok(modified) = metadata.modified() else {
+                continue;
+            };
+            if !metadata.is_file() && !metadata.is_dir() {
+                continue;
+            }
+
+            matches += 1;
+            if newest.as_ref().is_none_or(|n| modified > n.modified) {
+                newest = Some(Newest {
+                    path,
+                    is_dir: metadata.is_dir(),
+                    modified,
+                    size: metadata.len(),
+                    matches: 0,
+                });
+            }
+        }
+
+        Ok(newest.map(|n| Newest { matches, ..n }))
+    }
+
+    fn contains_match(&self, path: &PathBuf, regex: &Regex) -> Result<bool, Error> {
+        let mut head = Vec::new();
+        std::fs::File::open(path)?
+            .take(MAX_CONTENT_BYTES)
+            .read_to_end(&mut head)?;
+        Ok(regex.is_match(&head))
+    }
+
+    fn check(&self) -> Result<MonitorStatus, Error> {
+        let Some(newest) = self.find_newest()? else {
+            return Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: format!("no file matches '{}'", self.path),
+                details: None,
+            });
+        };
+
+        // clock skew can put the mtime slightly in the future
+        let age = SystemTime::now()
+            .duration_since(newest.modified)
+            .unwrap_or_default();
+        let details = Some(serde_json::json!({
+            "path": newest.path,
+            "matches": newest.matches,
+            "modified_at": DateTime::<Utc>::from(newest.modified),
+            "age_s": age.as_secs(),
+            "size": newest.size,
+        }));
+
+        let mut problems = vec![];
+        if let Some(max_age) = self.max_age {
+            if age > max_age {
+                problems.push(format!(
+                    "is {} old, max {}",
+                    format_duration(Duration::from_secs(age.as_secs())),
+                    format_duration(max_age)
+                ));
+            }
+        }
+        if let Some(min_size) = self.min_size {
+            if newest.size < min_size {
+                problems.push(format!("is {} bytes, min {min_size}", newest.size));
+            }
+        }
+        if let Some(max_size) = self.max_size {
+            if newest.size > max_size {
+                problems.push(format!("is {} bytes, max {max_size}", newest.size));
+            }
+        }
+        if let Some(regex) = &self.content {
+            if newest.is_dir {
+                problems.push("is a directory, content can't be searched".to_string());
+            } else if !self.contains_match(&newest.path, regex)? {
+                problems.push(format!("doesn't match /{}/", regex.as_str()));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(MonitorStatus::Up {
+                checked_at: Utc::now(),
+                details,
+            })
+        } else {
+            Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: format!("{} {}", newest.path.display(), problems.join(", ")),
+                details,
+            })
+        }
+    }
+}
+
+#[async_trait]
+impl MonitorTask for FileMonitor {
+    async fn survey(&self) -> Result<MonitorStatus, Error> {
+        let monitor = self.clone();
+        let result = tokio::task::spawn_blocking(move || monitor.check()).await?;
+
+        Ok(result.unwrap_or_else(|err| MonitorStatus::Down {
+            checked_at: Utc::now(),
+            error_reason: err.to_string(),
+            details: None,
+        }))
+    }
+}
+
+#[monitor_kind(kind = "file", api_version = "v1alpha1")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct V1Alpha1FileMonitorSpec {
+    /// A path or glob; the newest matching file or directory is checked.
+    #[check(non_empty, with = glob_pattern)]
+    pub path: String,
+    #[serde(default, with = "humantime_serde")]
+    pub max_age: Option<Duration>,
+    /// Size bounds in bytes.
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    /// Regex searched for in the first MiB of the file.
+    #[check(with = valid_regex)]
+    pub content: Option<String>,
+}
+
+fn glob_pattern(path: &str) -> Result<(), String> {
+    glob::Pattern::new(path)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+#[async_trait]
+impl MonitorSpec for V1Alpha1FileMonitorSpec {
+    async fn build(self, _monitor: Monitor) -> Result<TaskPtr, Error> {
+        if let (Some(min), Some(max)) = (self.min_size, self.max_size) {
+            if min > max {
+                bail!("spec.min_size: {min} is larger than max_size {max}");
+            }
+        }
+
+        Ok(Arc::new(FileMonitor {
+            path: self.path,
+            max_age: self.max_age,
+            min_size: self.min_size,
+            max_size: self.max_size,
+            content: self.content.as_deref().map(Regex::new).transpose()?,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::tasks::{test_monitor, TaskBuilder};
+    use serde_json::json;
+    use std::fs::{File, FileTimes};
+    use tempfile::TempDir;
+
+    fn write(dir: &TempDir, name: &str, content: &str, age: Duration) {
+        let path = dir.path().join(name);
+        std::fs::write(&path, content).unwrap();
+        let modified = SystemTime::now() - age;
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_times(FileTimes::new().set_modified(modified))
+            .unwrap();
+    }
+
+    async fn survey(dir: &TempDir, spec: serde_json::Value) -> MonitorStatus {
+        let mut spec = spec;
+        let pattern = dir.path().join(spec["path"].as_str().unwrap());
+        spec["path"] = json!(pattern.to_str().unwrap());
+        let monitor = test_monitor("file", "v1alpha1", spec);
+        let task = V1Alpha1FileMonitorBuilder {}.build(monitor).await.unwrap();
+        task.survey().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_newest_match_is_checked() {
+        let dir = TempDir::new().unwrap();
+        write(&dir, "export-1.csv", "id,name\n", Duration::from_secs(7200));
+        write(
+            &dir,
+            "export-2.csv",
+            "id,name\n1,a\n",
+            Duration::from_secs(60),
+        );
+        write(&dir, "notes.txt", "", Duration::ZERO);
+
+        let status = survey(
+            &dir,
+            json!({ "path": "export-*.csv", "max_age": "1h", "min_size": 8, "content": "^id,name" }),
+        )
+        .await;
+
+        match status {
+            MonitorStatus::Up { details, .. } => {
+                let details = details.unwrap();
+                assert_eq!(details["matches"], 2);
+                assert!(details["path"].as_str().unwrap().ends_with("export-2.csv"));
+                assert_eq!(details["size"], 12);
+            }
+            other => panic!("expected Up, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stale_small_and_unmatched_files_are_down() {
+        let dir = TempDir::new().unwrap();
+        write(&dir, "backup.tar", "tiny", Duration::from_secs(3 * 86400));
+
+        let status = survey(
+            &dir,
+            json!({ "path": "backup.tar", "max_age": "1d", "min_size": 1024, "content": "ustar" }),
+        )
+        .await;
+
+        match status {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert!(
+                    error_reason.contains("is 3days old, max 1day"),
+                    "{error_reason}"
+                );
+                assert!(
+                    error_reason.contains("is 4 bytes, min 1024"),
+                    "{error_reason}"
+                );
+                assert!(
+                    error_reason.contains("doesn't match /ustar/"),
+                    "{error_reason}"
+                );
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_missing_file_is_down() {
+        let dir = TempDir::new().unwrap();
+
+        let status = survey(&dir, json!({ "path": "*.parquet" })).await;
+
+        match status {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert!(error_reason.starts_with("no file matches"))
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+    #[tokio::test]
+    async fn test_directory() {
+        let dir = TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("spool")).unwrap();
+
+        match survey(&dir, json!({ "path": "spool", "max_age": "1h" })).await {
+            MonitorStatus::Up { details, .. } => {
+                assert!(details.unwrap()["path"]
+                    .as_str()
+                    .unwrap()
+                    .ends_with("spool"))
+            }
+            other => panic!("expected Up, got {other:?}"),
+        }
+
+        match survey(&dir, json!({ "path": "spool", "content": "x" })).await {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert!(error_reason.ends_with("is a directory, content can't be searched"))
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_vanished_match_is_skipped() {
+        let dir = TempDir::new().unwrap();
+        write(&dir, "export-1.csv", "id,name\n", Duration::from_secs(60));
+        // listed by the glob, gone by the time it's looked at
+        std::os::unix::fs::symlink(
+            dir.path().join("rotated.csv"),
+            dir.path().join("export-2.csv"),
+        )
+        .unwrap();
+
+        match survey(&dir, json!({ "path": "export-*.csv" })).await {
+            MonitorStatus::Up { details, .. } => {
+                let details = details.unwrap();
+                assert_eq!(details["matches"], 1);
+                assert!(details["path"].as_str().unwrap().ends_with("export-1.csv"));
+            }
+            other => panic!("expected Up, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use crate::monitor::tasks::spec::MonitorSpec;
+use crate::monitor::tasks::{MonitorTask, TaskPtr};
+use anyhow::{bail, Error};
+use app::types::{Monitor, MonitorStatus};
+use macros::monitor_kind;
+use migration::async_trait::async_trait;
+use sea_orm::sqlx::types::chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint};
+use tonic_health::pb::health_check_response::ServingStatus;
+use tonic_health::pb::health_client::HealthClient;
+use tonic_health::pb::HealthCheckRequest;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct GrpcMonitor {
+    channel: Channel,
+    service: String,
+    timeout: Duration,
+}
+
+#[async_trait]
+impl MonitorTask for GrpcMonitor {
+    async fn survey(&self) -> Result<MonitorStatus, Error> {
+        let mut client = HealthClient::new(self.channel.clone());
+        let request = HealthCheckRequest {
+            service: self.service.clone(),
+        };
+
+        let started = Instant::now();
+        let result = tokio::time::timeout(self.timeout, client.check(request)).await;
+        let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+        let response = match result {
+            Ok(Ok(response)) => response.into_inner(),
+            Ok(Err(status)) => {
+                let error_reason = match status.code() {
+                    tonic::Code::NotFound => format!("service '{}' not found", self.service),
+                    _ => format!("health check failed: {status}"),
+                };
+                return Ok(MonitorStatus::Down {
+                    checked_at: Utc::now(),
+                    error_reason,
+                    details: None,
+                });
+            }
+            Err(_) => {
+                return Ok(MonitorStatus::Down {
+                    checked_at: Utc::now(),
+                    error_reason: format!("timed out after {:?}", self.timeout),
+                    details: None,
+                })
+            }
+        };
+
+        let serving_status =
+            ServingStatus::try_from(response.status).unwrap_or(ServingStatus::Unknown);
+        let details = Some(serde_json::json!({
+            "latency_ms": latency_ms,
+            "serving_status": serving_status.as_str_name(),
+        }));
+
+        match serving_status {
+            ServingStatus::Serving => Ok(MonitorStatus::Up {
+                checked_at: Utc::now(),
+                details,
+            }),
+            other => Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: format!("service reported {}", other.as_str_name()),
+                details,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct V1Alpha1GrpcTlsSpec {
+    /// PEM file with the CA used to verify the server, in addition to the system roots.
+    pub ca_certificate_file: Option<String>,
+    /// Overrides the name used for SNI and certificate verification.
+    pub domain_name: Option<String>,
+}
+
+#[monitor_kind(kind = "grpc", api_version = "v1alpha1")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct V1Alpha1GrpcMonitorSpec {
+    /// `http://` for plaintext, `https://` for TLS.
+    #[check(non_empty)]
+    pub uri: String,
+    /// Service name passed to `Check`; empty checks the server as a whole.
+    #[serde(default)]
+    pub service: String,
+    pub tls: Option<V1Alpha1GrpcTlsSpec>,
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+}
+
+#[async_trait]
+impl MonitorSpec for V1Alpha1GrpcMonitorSpec {
+    async fn build(self, _monitor: Monitor) -> Result<TaskPtr, Error> {
+        let timeout = self.timeout.unwrap_or(DEFAULT_TIMEOUT);
+
+        let mut endpoint = Endpoint::from_shared(self.uri.clone())?
+            .connect_timeout(timeout)
+            .timeout(timeout);
+
+        let use_tls = match endpoint.uri().scheme_str() {
+            Some("https") => true,
+            Some("http") => false,
+            _ => bail!("grpc uri '{}' must use http or https", self.uri),
+        };
+
+        if use_tls {
+            let mut tls = ClientTlsConfig::new().with_native_roots();
+            if let Some(tls_spec) = self.tls {
+                if let Some(path) = tls_spec.ca_certificate_file {
+                    let pem = tokio::fs::read(&path).await?;
+                    tls = tls.ca_certificate(Certificate::from_pem(pem));
+                }
+                if let Some(domain_name) = tls_spec.domain_name {
+                    tls = tls.domain_name(domain_name);
+                }
+            }
+            endpoint = endpoint.tls_config(tls)?;
+        } else if self.tls.is_some() {
+            bail!("spec.tls: requires an https uri");
+        }
+
+        Ok(Arc::new(GrpcMonitor {
+            channel: endpoint.connect_lazy(),
+            service: self.service,
+            timeout,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::tasks::{test_monitor, TaskBuilder};
+    use serde_json::json;
+    use std::net::SocketAddr;
+    use tempfile::NamedTempFile;
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::{Identity, Server, ServerTlsConfig};
+
+    async fn spawn_health_server(tls: Option<ServerTlsConfig>) -> SocketAddr {
+        let (reporter, service) = tonic_health::server::health_reporter();
+        reporter
+            .set_service_status("checkout", tonic_health::ServingStatus::Serving)
+            .await;
+        reporter
+            .set_service_status("payments", tonic_health::ServingStatus::NotServing)
+            .await;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut server = Server::builder();
+        if let Some(tls) = tls {
+            server = server.tls_config(tls).unwrap();
+        }
+        tokio::spawn(async move {
+            // keep the reporter alive for as long as the server runs
+            let _reporter = reporter;
+            server
+                .add_service(service)
+                .serve_with_incoming(TcpListenerStream::new(listener))
+                .await
+                .unwrap();
+        });
+
+        addr
+    }
+
+    async fn survey_spec(spec: serde_json::Value) -> MonitorStatus {
+        let monitor = test_monitor("grpc", "v1alpha1", spec);
+        let task = V1Alpha1GrpcMonitorBuilder {}.build(monitor).await.unwrap();
+        task.survey().await.unwrap()
+    }
+
+    async fn survey(addr: SocketAddr, service: &str) -> MonitorStatus {
+        survey_spec(json!({ "uri": format!("http://{addr}"), "service": service })).await
+    }
+
+    #[tokio::test]
+    async fn test_serving_services_are_up() {
+        let addr = spawn_health_server(None).await;
+
+        for service in ["", "checkout"] {
+            match survey(addr, service).await {
+                MonitorStatus::Up { details, .. } => {
+                    assert_eq!(details.unwrap()["serving_status"], "SERVING");
+                }
+                other => panic!("expected Up for '{service}', got {other:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_not_serving_and_unknown_services_are_down() {
+        let addr = spawn_health_server(None).await;
+
+        match survey(addr, "payments").await {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert!(error_reason.contains("NOT_SERVING"))
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+
+        match survey(addr, "missing").await {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert!(error_reason.contains("not found"))
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tls() {
+        let key = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let ca_file = NamedTempFile::new().unwrap();
+        std::fs::write(ca_file.path(), key.cert.pem()).unwrap();
+        let identity = Identity::from_pem(key.cert.pem(), key.signing_key.serialize_pem());
+        let addr = spawn_health_server(Some(ServerTlsConfig::new().identity(identity))).await;
+        let spec = |tls: serde_json::Value| json!({ "uri": format!("https://{addr}"), "service": "checkout", "tls": tls });
+
+        let trusted = spec(json!({
+            "ca_certificate_file": ca_file.path(),
+            "domain_name": "localhost",
+        }));
+        match survey_spec(trusted).await {
+            MonitorStatus::Up { details, .. } => {
+                assert_eq!(details.unwrap()["serving_status"], "SERVING")
+            }
+            other => panic!("expected Up, got {other:?}"),
+        }
+
+        // not valid for the address connected to, or not trusted at all
+        for tls in [
+            json!({ "ca_certificate_file": ca_file.path() }),
+            json!({ "domain_name": "localhost" }),
+        ] {
+            match survey_spec(spec(tls.clone())).await {
+                MonitorStatus::Down { error_reason, .. } => {
+                    assert!(error_reason.starts_with("health check failed"), "{tls}")
+                }
+                other => panic!("expected Down for {tls}, got {other:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejects_unknown_scheme() {
+        let monitor = test_monitor("grpc", "v1alpha1", json!({ "uri": "ftp://localhost:1" }));
+        assert!(V1Alpha1GrpcMonitorBuilder {}.build(monitor).await.is_err());
+
+        let monitor = test_monitor(
+            "grpc",
+            "v1alpha1",
+            json!({ "uri": "http://localhost:1", "tls": { "domain_name": "localhost" } }),
+        );
+        let error = V1Alpha1GrpcMonitorBuilder {}
+            .build(monitor)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "spec.tls: requires an https uri");
+    }
+}
@@ -0,0 +1,345 @@
+use crate::monitor::tasks::spec::StatefulMonitorSpec;
+use crate::monitor::tasks::{MonitorTask, TaskPtr};
+use anyhow::{bail, Error};
+use app::types::{Monitor, MonitorStatus};
+use chrono::TimeDelta;
+use macros::monitor_kind;
+use migration::async_trait::async_trait;
+use sea_orm::sqlx::types::chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+const DEFAULT_GRACE: Duration = Duration::from_secs(60);
+const MAX_MESSAGE_BYTES: usize = 1024;
+
+pub type HeartbeatRegistryPtr = Arc<HeartbeatRegistry>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeartbeatPing {
+    Start,
+    Success,
+    Fail,
+}
+
+#[derive(Debug, Clone)]
+struct HeartbeatState {
+    monitor: String,
+    registered_at: DateTime<Utc>,
+    last_start: Option<DateTime<Utc>>,
+    last_success: Option<DateTime<Utc>>,
+    last_failure: Option<(DateTime<Utc>, String)>,
+}
+
+/// Pings received on the heartbeat endpoints, keyed by token.
+///
+/// Shared between the http handlers that record pings and the heartbeat tasks
+/// that evaluate them. State is in-memory: after a restart every heartbeat
+/// gets a fresh period + grace before it can go down.
+#[derive(Debug, Default)]
+pub struct HeartbeatRegistry {
+    heartbeats: RwLock<HashMap<String, HeartbeatState>>,
+}
+
+impl HeartbeatRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    async fn register(&self, token: &str, monitor: &str) -> Result<(), Error> {
+        let mut guard = self.heartbeats.write().await;
+        match guard.get(token) {
+            Some(existing) if existing.monitor != monitor => {
+                bail!(
+                    "heartbeat token is already used by monitor '{}'",
+                    existing.monitor
+                )
+            }
+            Some(_) => {}
+            None => {
+                // a rebuilt monitor whose token changed must stop answering on the old one
+                guard.retain(|_, state| state.monitor != monitor);
+                guard.insert(
+                    token.to_string(),
+                    HeartbeatState {
+                        monitor: monitor.to_string(),
+                        registered_at: Utc::now(),
+                        last_start: None,
+                        last_success: None,
+                        last_failure: None,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Records a ping, returning false if no monitor owns the token.
+    pub async fn record(&self, token: &str, ping: HeartbeatPing, message: &str) -> bool {
+        let mut guard = self.heartbeats.write().await;
+        let Some(state) = guard.get_mut(token) else {
+            return false;
+        };
+
+        let now = Utc::now();
+        match ping {
+            HeartbeatPing::Start => state.last_start = Some(now),
+            HeartbeatPing::Success => state.last_success = Some(now),
+            HeartbeatPing::Fail => state.last_failure = Some((now, truncate(message))),
+        }
+        true
+    }
+
+    async fn get(&self, token: &str) -> Option<HeartbeatState> {
+        self.heartbeats.read().await.get(token).cloned()
+    }
+}
+
+fn truncate(message: &str) -> String {
+    let message = message.trim();
+    let mut end = message.len().min(MAX_MESSAGE_BYTES);
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    message[..end].to_string()
+}
+
+struct HeartbeatMonitor {
+    registry: HeartbeatRegistryPtr,
+    token: String,
+    period: Duration,
+    grace: Duration,
+}
+
+#[async_trait]
+impl MonitorTask for HeartbeatMonitor {
+    async fn survey(&self) -> Result<MonitorStatus, Error> {
+        let Some(state) = self.registry.get(&self.token).await else {
+            bail!("heartbeat token is not registered");
+        };
+
+        let now = Utc::now();
+        let details = Some(serde_json::json!({
+            "last_start": state.last_start,
+            "last_success": state.last_success,
+            "last_failure": state.last_failure.as_ref().map(|(at, _)| at),
+            "last_run_ms": match (state.last_start, state.last_success) {
+                (Some(start), Some(success)) if success >= start => {
+                    Some((success - start).num_milliseconds())
+                }
+                _ => None,
+            },
+        }));
+
+        if let Some((failed_at, message)) = &state.last_failure {
+            if state.last_success.is_none_or(|s| s < *failed_at) {
+                let error_reason = if message.is_empty() {
+                    format!("job reported failure at {failed_at}")
+                } else {
+                    format!("job reported failure at {failed_at}: {message}")
+                };
+                return Ok(MonitorStatus::Down {
+                    checked_at: now,
+                    error_reason,
+                    details,
+                });
+            }
+        }
+
+        let deadline = TimeDelta::from_std(self.period + self.grace)?;
+        let since = state.last_success.unwrap_or(state.registered_at);
+        if now - since > deadline {
+            let error_reason = match state.last_success {
+                Some(at) => format!("no ping received since {at}"),
+                None => "no ping received yet".to_string(),
+            };
+            return Ok(MonitorStatus::Down {
+                checked_at: now,
+                error_reason,
+                details,
+            });
+        }
+
+        Ok(MonitorStatus::Up {
+            checked_at: now,
+            details,
+        })
+    }
+}
+
+#[monitor_kind(kind = "heartbeat", api_version = "v1alpha1", state = HeartbeatRegistryPtr)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct V1Alpha1HeartbeatMonitorSpec {
+    /// Secret part of the ping url, `/api/heartbeat/{token}`.
+    #[check(non_empty, with = url_safe)]
+    pub token: String,
+    /// How often the job is expected to ping.
+    #[serde(with = "humantime_serde")]
+    pub period: Duration,
+    /// Extra time allowed on top of the period before the monitor goes down.
+    #[serde(default, with = "humantime_serde")]
+    pub grace: Option<Duration>,
+}
+
+fn url_safe(token: &str) -> Result<(), String> {
+    match token
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        true => Ok(()),
+        false => Err("must be url safe ([A-Za-z0-9_-])".to_string()),
+    }
+}
+
+#[async_trait]
+impl StatefulMonitorSpec<HeartbeatRegistryPtr> for V1Alpha1HeartbeatMonitorSpec {
+    async fn build(
+        self,
+        monitor: Monitor,
+        registry: &HeartbeatRegistryPtr,
+    ) -> Result<TaskPtr, Error> {
+        registry.register(&self.token, &monitor.name).await?;
+
+        Ok(Arc::new(HeartbeatMonitor {
+            registry: registry.clone(),
+            token: self.token,
+            period: self.period,
+            grace: self.grace.unwrap_or(DEFAULT_GRACE),
+        }))
+    }
+}
+
+/// Builds a heartbeat task with a 100ms period and grace.
+#[cfg(test)]
+pub(crate) async fn test_heartbeat_task(
+    registry: &HeartbeatRegistryPtr,
+    name: &str,
+    token: &str,
+) -> Result<TaskPtr, Error> {
+    use crate::monitor::tasks::{test_monitor, TaskBuilder};
+
+    let mut monitor = test_monitor(
+        "heartbeat",
+        "v1alpha1",
+        serde_json::json!({ "token": token, "period": "100ms", "grace": "100ms" }),
+    );
+    monitor.name = name.to_string();
+    V1Alpha1HeartbeatMonitorBuilder {
+        state: registry.clone(),
+    }
+    .build(monitor)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_goes_down_without_pings() {
+        let registry = Arc::new(HeartbeatRegistry::new());
+        let task = test_heartbeat_task(&registry, "backup", "nightly-backup")
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            task.survey().await.unwrap(),
+            MonitorStatus::Up { .. }
+        ));
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        match task.survey().await.unwrap() {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert_eq!(error_reason, "no ping received yet")
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+
+        assert!(
+            registry
+                .record("nightly-backup", HeartbeatPing::Success, "")
+                .await
+        );
+        assert!(matches!(
+            task.survey().await.unwrap(),
+            MonitorStatus::Up { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_failure_until_next_success() {
+        let registry = Arc::new(HeartbeatRegistry::new());
+        let task = test_heartbeat_task(&registry, "backup", "nightly-backup")
+            .await
+            .unwrap();
+
+        registry
+            .record("nightly-backup", HeartbeatPing::Start, "")
+            .await;
+        registry
+            .record("nightly-backup", HeartbeatPing::Fail, "disk full")
+            .await;
+        match task.survey().await.unwrap() {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert!(error_reason.ends_with("disk full"))
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+
+        registry
+            .record("nightly-backup", HeartbeatPing::Start, "")
+            .await;
+        registry
+            .record("nightly-backup", HeartbeatPing::Success, "")
+            .await;
+        match task.survey().await.unwrap() {
+            MonitorStatus::Up { details, .. } => assert!(details.unwrap()["last_run_ms"].is_i64()),
+            other => panic!("expected Up, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tokens_are_unique_per_monitor() {
+        let registry = Arc::new(HeartbeatRegistry::new());
+        test_heartbeat_task(&registry, "backup", "shared")
+            .await
+            .unwrap();
+
+        // rebuilding the same monitor keeps its registration
+        test_heartbeat_task(&registry, "backup", "shared")
+            .await
+            .unwrap();
+        assert!(test_heartbeat_task(&registry, "other", "shared")
+            .await
+            .is_err());
+        assert!(test_heartbeat_task(&registry, "other", "not/url/safe")
+            .await
+            .is_err());
+        assert!(!registry.record("unknown", HeartbeatPing::Success, "").await);
+    }
+
+    #[tokio::test]
+    async fn test_changed_token_replaces_the_old_one() {
+        let registry = Arc::new(HeartbeatRegistry::new());
+        test_heartbeat_task(&registry, "backup", "old")
+            .await
+            .unwrap();
+        test_heartbeat_task(&registry, "other", "other")
+            .await
+            .unwrap();
+
+        test_heartbeat_task(&registry, "backup", "new")
+            .await
+            .unwrap();
+        assert!(!registry.record("old", HeartbeatPing::Success, "").await);
+        assert!(registry.record("new", HeartbeatPing::Success, "").await);
+        assert!(registry.record("other", HeartbeatPing::Success, "").await);
+
+        // the old token is free for other monitors again
+        test_heartbeat_task(&registry, "nightly", "old")
+            .await
+            .unwrap();
+    }
+}
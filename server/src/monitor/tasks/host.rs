@@ -0,0 +1,426 @@
+use crate::monitor::tasks::spec::MonitorSpec;
+use crate::monitor::tasks::{MonitorTask, TaskPtr};
+use anyhow::{anyhow, bail, Error};
+use app::types::{Monitor, MonitorStatus};
+use macros::monitor_kind;
+use migration::async_trait::async_trait;
+use sea_orm::sqlx::types::chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct Limits {
+    pub degraded_above: Option<f64>,
+    pub down_above: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+struct Breaches {
+    down: Vec<String>,
+    degraded: Vec<String>,
+}
+
+impl Breaches {
+    fn check(&mut self, limits: Option<&Limits>, what: String, value: f64) {
+        let Some(limits) = limits else {
+            return;
+        };
+        if let Some(limit) = limits.down_above.filter(|l| value > *l) {
+            self.down
+                .push(format!("{what} is {value:.1}, above {limit}"));
+        } else if let Some(limit) = limits.degraded_above.filter(|l| value > *l) {
+            self.degraded
+                .push(format!("{what} is {value:.1}, above {limit}"));
+        }
+    }
+}
+
+fn percent(used: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        used as f64 * 100.0 / total as f64
+    }
+}
+
+/// Reads the `kB` values of `/proc/meminfo`, in bytes.
+fn read_meminfo(proc_root: &Path) -> Result<HashMap<String, u64>, Error> {
+    let text = std::fs::read_to_string(proc_root.join("meminfo"))?;
+    Ok(text
+        .lines()
+        .filter_map(|line| {
+            let (key, rest) = line.split_once(':')?;
+            let kb = rest.split_whitespace().next()?.parse::<u64>().ok()?;
+            Some((key.to_string(), kb * 1024))
+        })
+        .collect())
+}
+
+fn read_loadavg(proc_root: &Path) -> Result<[f64; 3], Error> {
+    let text = std::fs::read_to_string(proc_root.join("loadavg"))?;
+    let mut fields = text.split_whitespace().map(str::parse::<f64>);
+    let mut next = || {
+        fields
+            .next()
+            .ok_or_else(|| anyhow!("malformed loadavg"))?
+            .map_err(Error::from)
+    };
+    Ok([next()?, next()?, next()?])
+}
+
+/// Clears the flag once the blocking check returns, even by panicking.
+struct InFlight(Arc<AtomicBool>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+#[derive(Clone)]
+struct HostMonitor {
+    proc_root: PathBuf,
+    disks: BTreeMap<String, Limits>,
+    memory: Option<Limits>,
+    swap: Option<Limits>,
+    load: Option<Limits>,
+    timeout: Duration,
+    /// Set while a check runs on the blocking pool, including one that
+    /// outlived its timeout.
+    in_flight: Arc<AtomicBool>,
+}
+
+impl HostMonitor {
+    fn check(&self) -> Result<MonitorStatus, Error> {
+        let mut breaches = Breaches::default();
+        let mut details = serde_json::Map::new();
+
+        let meminfo = read_meminfo(&self.proc_root)?;
+        let field = |name: &str| {
+            meminfo
+                .get(name)
+                .copied()
+                .ok_or_else(|| anyhow!("meminfo has no {name}"))
+        };
+
+        let mem_total = field("MemTotal")?;
+        let mem_available = field("MemAvailable")?;
+        let mem_used = percent(mem_total.saturating_sub(mem_available), mem_total);
+        breaches.check(self.memory.as_ref(), "memory used %".to_string(), mem_used);
+        details.insert(
+            "memory".to_string(),
+            serde_json::json!({
+                "used_percent": mem_used,
+                "total_bytes": mem_total,
+                "available_bytes": mem_available,
+            }),
+        );
+
+        let swap_total = field("SwapTotal")?;
+        let swap_free = field("SwapFree")?;
+        let swap_used = percent(swap_total.saturating_sub(swap_free), swap_total);
+        breaches.check(self.swap.as_ref(), "swap used %".to_string(), swap_used);
+        details.insert(
+            "swap".to_string(),
+            serde_json::json!({
+                "used_percent": swap_used,
+                "total_bytes": swap_total,
+                "free_bytes": swap_free,
+            }),
+        );
+
+        let [load1, load5, load15] = read_loadavg(&self.proc_root)?;
+        let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+        breaches.check(
+            self.load.as_ref(),
+            "load per cpu".to_string(),
+            load1 / cpus as f64,
+        );
+        details.insert(
+            "load".to_string(),
+            serde_json::json!({ "load1": load1, "load5": load5, "load15": load15, "cpus": cpus }),
+        );
+
+        let mut disks = serde_json::Map::new();
+        for (mount, limits) in &self.disks {
+            let stat = nix::sys::statvfs::statvfs(mount.as_str())
+                .map_err(|e| anyhow!("can't stat {mount}: {e}"))?;
+            let fragment = stat.fragment_size() as u64;
+            let used = (stat.blocks() - stat.blocks_free()) as u64 * fragment;
+            let available = stat.blocks_available() as u64 * fragment;
+            // same as df, blocks reserved for root don't count as space
+            let used_percent = percent(used, used + available);
+            breaches.check(Some(limits), format!("disk {mount} used %"), used_percent);
+            disks.insert(
+                mount.clone(),
+                serde_json::json!({
+                    "used_percent": used_percent,
+                    "used_bytes": used,
+                    "available_bytes": available,
+                }),
+            );
+        }
+        details.insert("disks".to_string(), disks.into());
+
+        let details = Some(details.into());
+        if !breaches.down.is_empty() {
+            let mut reasons = breaches.down;
+            reasons.extend(breaches.degraded);
+            Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: reasons.join(", "),
+                details,
+            })
+        } else if !breaches.degraded.is_empty() {
+            Ok(MonitorStatus::Degraded {
+                checked_at: Utc::now(),
+                error_reason: breaches.degraded.join(", "),
+                details,
+            })
+        } else {
+            Ok(MonitorStatus::Up {
+                checked_at: Utc::now(),
+                details,
+            })
+        }
+    }
+}
+
+#[async_trait]
+impl MonitorTask for HostMonitor {
+    async fn survey(&self) -> Result<MonitorStatus, Error> {
+        // statvfs can hang on an unresponsive network mount, the stuck
+        // thread is left behind rather than holding up the monitor, but no
+        // other one joins it until it's done
+        let result = if self.in_flight.swap(true, Ordering::AcqRel) {
+            Err(anyhow!("previous check still running"))
+        } else {
+            let in_flight = InFlight(self.in_flight.clone());
+            let monitor = self.clone();
+            let check = tokio::task::spawn_blocking(move || {
+                let _in_flight = in_flight;
+                monitor.check()
+            });
+            match tokio::time::timeout(self.timeout, check).await {
+                Ok(result) => result?,
+                Err(_) => Err(anyhow!("timed out after {:?}", self.timeout)),
+            }
+        };
+
+        Ok(result.unwrap_or_else(|err| MonitorStatus::Down {
+            checked_at: Utc::now(),
+            error_reason: err.to_string(),
+            details: None,
+        }))
+    }
+}
+
+#[monitor_kind(kind = "host", api_version = "v1alpha1")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct V1Alpha1HostMonitorSpec {
+    /// Used space in percent, keyed by mount point.
+    #[serde(default)]
+    pub disks: BTreeMap<String, Limits>,
+    /// Used memory in percent, counting reclaimable caches as free.
+    pub memory: Option<Limits>,
+    /// Used swap in percent.
+    pub swap: Option<Limits>,
+    /// 1 minute load average divided by the number of cpus.
+    pub load: Option<Limits>,
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+}
+
+#[async_trait]
+impl MonitorSpec for V1Alpha1HostMonitorSpec {
+    async fn build(self, _monitor: Monitor) -> Result<TaskPtr, Error> {
+        if self.disks.is_empty()
+            && self.memory.is_none()
+            && self.swap.is_none()
+            && self.load.is_none()
+        {
+            bail!("spec: requires disks, memory, swap or load limits");
+        }
+
+        Ok(Arc::new(HostMonitor {
+            proc_root: PathBuf::from("/proc"),
+            disks: self.disks,
+            memory: self.memory,
+            swap: self.swap,
+            load: self.load,
+            timeout: self.timeout.unwrap_or(DEFAULT_TIMEOUT),
+            in_flight: Default::default(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::tasks::{test_monitor, TaskBuilder};
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    const MEMINFO: &str = "MemTotal:        8000000 kB
+MemFree:          500000 kB
+MemAvailable:    1000000 kB
+SwapTotal:       2000000 kB
+SwapFree:         500000 kB
+";
+
+    fn fake_proc(loadavg: &str) -> TempDir {
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.path().join("meminfo"), MEMINFO).unwrap();
+        std::fs::write(dir.path().join("loadavg"), loadavg).unwrap();
+        dir
+    }
+
+    /// A monitor reading the fake `/proc` tree, without any limits.
+    fn host(proc_root: &TempDir) -> HostMonitor {
+        HostMonitor {
+            proc_root: proc_root.path().to_path_buf(),
+            disks: BTreeMap::new(),
+            memory: None,
+            swap: None,
+            load: None,
+            timeout: DEFAULT_TIMEOUT,
+            in_flight: Default::default(),
+        }
+    }
+
+    fn limits(degraded_above: Option<f64>, down_above: Option<f64>) -> Option<Limits> {
+        Some(Limits {
+            degraded_above,
+            down_above,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_memory_and_swap() {
+        let proc_root = fake_proc("0.00 0.01 0.05 1/100 1234\n");
+        let monitor = HostMonitor {
+            memory: limits(Some(80.0), Some(95.0)),
+            swap: limits(Some(50.0), None),
+            load: limits(None, Some(1.0)),
+            ..host(&proc_root)
+        };
+
+        match monitor.survey().await.unwrap() {
+            MonitorStatus::Degraded {
+                error_reason,
+                details,
+                ..
+            } => {
+                assert_eq!(
+                    error_reason,
+                    "memory used % is 87.5, above 80, swap used % is 75.0, above 50"
+                );
+                let details = details.unwrap();
+                assert_eq!(details["memory"]["available_bytes"], 1_024_000_000u64);
+                assert_eq!(details["load"]["load15"], 0.05);
+            }
+            other => panic!("expected Degraded, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_load_and_disks() {
+        let cpus = std::thread::available_parallelism().unwrap().get();
+        let proc_root = fake_proc(&format!("{}.0 1.0 1.0 3/100 1234\n", cpus * 4));
+        let mount = std::env::temp_dir().to_string_lossy().into_owned();
+        let monitor = HostMonitor {
+            load: limits(Some(1.0), Some(2.0)),
+            disks: BTreeMap::from([(mount.clone(), limits(None, Some(100.0)).unwrap())]),
+            ..host(&proc_root)
+        };
+
+        match monitor.survey().await.unwrap() {
+            MonitorStatus::Down {
+                error_reason,
+                details,
+                ..
+            } => {
+                assert_eq!(error_reason, "load per cpu is 4.0, above 2");
+                assert!(details.unwrap()["disks"][&mount]["used_percent"].is_f64());
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+
+        let missing = HostMonitor {
+            disks: BTreeMap::from([(
+                "/does/not/exist".to_string(),
+                limits(None, Some(90.0)).unwrap(),
+            )]),
+            ..host(&proc_root)
+        };
+        match missing.survey().await.unwrap() {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert!(error_reason.starts_with("can't stat /does/not/exist"))
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hung_check_times_out() {
+        // opening a fifo blocks until a writer shows up, like a dead mount
+        let proc_root = TempDir::new().unwrap();
+        let meminfo = proc_root.path().join("meminfo");
+        nix::unistd::mkfifo(&meminfo, nix::sys::stat::Mode::S_IRWXU).unwrap();
+        let monitor = HostMonitor {
+            memory: limits(None, Some(90.0)),
+            timeout: Duration::from_millis(100),
+            ..host(&proc_root)
+        };
+
+        match monitor.survey().await.unwrap() {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert_eq!(error_reason, "timed out after 100ms")
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+        // the stuck thread isn't joined by another one
+        match monitor.survey().await.unwrap() {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert_eq!(error_reason, "previous check still running")
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+
+        // unblock the check so the runtime can shut down
+        drop(
+            std::fs::OpenOptions::new()
+                .write(true)
+                .open(&meminfo)
+                .unwrap(),
+        );
+        for _ in 0..100 {
+            if !monitor.in_flight.load(Ordering::Acquire) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("check is still in flight");
+    }
+
+    #[tokio::test]
+    async fn test_spec() {
+        let build =
+            |spec| V1Alpha1HostMonitorBuilder {}.build(test_monitor("host", "v1alpha1", spec));
+        assert!(
+            build(json!({ "memory": { "down_above": 90 }, "timeout": "5s" }))
+                .await
+                .is_ok()
+        );
+        assert_eq!(
+            build(json!({})).await.err().unwrap().to_string(),
+            "spec: requires disks, memory, swap or load limits"
+        );
+    }
+}
@@ -0,0 +1,459 @@
+use crate::monitor::tasks::spec::MonitorSpec;
+use crate::monitor::tasks::{MonitorTask, TaskPtr};
+use anyhow::{anyhow, bail, Error};
+use app::types::{Monitor, MonitorStatus};
+use macros::monitor_kind;
+use migration::async_trait::async_trait;
+use regex::Regex;
+use reqwest::header::HeaderMap;
+use reqwest::Method;
+use sea_orm::sqlx::types::chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+static TEMPLATE_VAR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{\s*([A-Za-z0-9_-]+)\s*\}\}").unwrap());
+
+#[derive(Debug, Clone)]
+enum Extractor {
+    JsonPath(Vec<PathSegment>),
+    Header(String),
+    Regex(Regex),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// Parses a simple JSON path such as `$.data.items[0].id`.
+fn parse_json_path(path: &str) -> Result<Vec<PathSegment>, Error> {
+    let path = path.strip_prefix('$').unwrap_or(path);
+    let mut segments = Vec::new();
+
+    for part in path.split('.').filter(|p| !p.is_empty()) {
+        let (key, mut indexes) = match part.find('[') {
+            Some(i) => (&part[..i], &part[i..]),
+            None => (part, ""),
+        };
+        if !key.is_empty() {
+            segments.push(PathSegment::Key(key.to_string()));
+        }
+        while let Some(rest) = indexes.strip_prefix('[') {
+            let end = rest
+                .find(']')
+                .ok_or_else(|| anyhow!("unclosed '[' in json path '{path}'"))?;
+            segments.push(PathSegment::Index(rest[..end].parse()?));
+            indexes = &rest[end + 1..];
+        }
+        if !indexes.is_empty() {
+            bail!("invalid json path '{path}'");
+        }
+    }
+
+    Ok(segments)
+}
+
+struct Response {
+    headers: HeaderMap,
+    body: String,
+}
+
+impl Extractor {
+    fn extract(&self, response: &Response) -> Result<String, Error> {
+        match self {
+            Extractor::JsonPath(path) => {
+                let json: serde_json::Value = serde_json::from_str(&response.body)
+                    .map_err(|e| anyhow!("response is not json: {e}"))?;
+                let mut current = &json;
+                for segment in path {
+                    current = match segment {
+                        PathSegment::Key(key) => current.get(key),
+                        PathSegment::Index(i) => current.get(i),
+                    }
+                    .ok_or_else(|| anyhow!("json path not found in response"))?;
+                }
+                Ok(match current {
+                    serde_json::Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                })
+            }
+            Extractor::Header(name) => response
+                .headers
+                .get(name)
+                .ok_or_else(|| anyhow!("header '{name}' not in response"))?
+                .to_str()
+                .map(str::to_string)
+                .map_err(|e| anyhow!("header '{name}' is not text: {e}")),
+            Extractor::Regex(regex) => {
+                let captures = regex
+                    .captures(&response.body)
+                    .ok_or_else(|| anyhow!("regex '{regex}' did not match response"))?;
+                let matched = captures.get(1).or_else(|| captures.get(0)).unwrap();
+                Ok(matched.as_str().to_string())
+            }
+        }
+    }
+}
+
+struct FlowStep {
+    name: String,
+    method: Method,
+    url: String,
+    headers: BTreeMap<String, String>,
+    body: Option<String>,
+    expect_status: Vec<u16>,
+    expect_body: Option<Regex>,
+    extract: Vec<(String, Extractor)>,
+}
+
+impl FlowStep {
+    async fn run(
+        &self,
+        client: &reqwest::Client,
+        variables: &mut HashMap<String, String>,
+    ) -> Result<u16, Error> {
+        let url = render(&self.url, variables)?;
+        let mut request = client.request(self.method.clone(), url);
+        for (name, value) in &self.headers {
+            request = request.header(name, render(value, variables)?);
+        }
+        if let Some(body) = &self.body {
+            request = request.body(render(body, variables)?);
+        }
+
+        let response = request.send().await?;
+        let status = response.status().as_u16();
+        let headers = response.headers().clone();
+        let body = response.text().await?;
+
+        let status_ok = if self.expect_status.is_empty() {
+            (200..300).contains(&status)
+        } else {
+            self.expect_status.contains(&status)
+        };
+        if !status_ok {
+            bail!("unexpected status {status}");
+        }
+
+        if let Some(expect_body) = &self.expect_body {
+            if !expect_body.is_match(&body) {
+                bail!("body did not match '{expect_body}'");
+            }
+        }
+
+        let response = Response { headers, body };
+        for (name, extractor) in &self.extract {
+            let value = extractor
+                .extract(&response)
+                .map_err(|e| anyhow!("extracting '{name}': {e}"))?;
+            variables.insert(name.clone(), value);
+        }
+
+        Ok(status)
+    }
+}
+
+/// Substitutes `{{name}}` placeholders from the flow's variables.
+fn render(template: &str, variables: &HashMap<String, String>) -> Result<String, Error> {
+    let mut missing = None;
+    let rendered = TEMPLATE_VAR.replace_all(template, |caps: &regex::Captures| {
+        match variables.get(&caps[1]) {
+            Some(value) => value.clone(),
+            None => {
+                missing.get_or_insert_with(|| caps[1].to_string());
+                String::new()
+            }
+        }
+    });
+
+    match missing {
+        Some(name) => Err(anyhow!("undefined variable '{name}'")),
+        None => Ok(rendered.into_owned()),
+    }
+}
+
+struct HttpFlowMonitor {
+    steps: Vec<FlowStep>,
+    variables: HashMap<String, String>,
+    timeout: Duration,
+}
+
+#[async_trait]
+impl MonitorTask for HttpFlowMonitor {
+    async fn survey(&self) -> Result<MonitorStatus, Error> {
+        // a fresh cookie jar per run, so a broken login can't ride on an old session
+        let client = reqwest::Client::builder()
+            .cookie_store(true)
+            .timeout(self.timeout)
+            .build()?;
+        let mut variables = self.variables.clone();
+        let mut timings = Vec::new();
+
+        for (i, step) in self.steps.iter().enumerate() {
+            let started = Instant::now();
+            let result = step.run(&client, &mut variables).await;
+            let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+            match result {
+                Ok(status) => timings.push(serde_json::json!({
+                    "name": step.name,
+                    "status": status,
+                    "latency_ms": latency_ms,
+                })),
+                Err(err) => {
+                    return Ok(MonitorStatus::Down {
+                        checked_at: Utc::now(),
+                        error_reason: format!("step {} ({}) failed: {err}", i + 1, step.name),
+                        details: Some(serde_json::json!({
+                            "failed_step": step.name,
+                            "steps": timings,
+                        })),
+                    })
+                }
+            }
+        }
+
+        Ok(MonitorStatus::Up {
+            checked_at: Utc::now(),
+            details: Some(serde_json::json!({ "steps": timings })),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct V1Alpha1ExtractSpec {
+    /// Variable name later steps can use as `{{name}}`.
+    pub name: String,
+    pub json_path: Option<String>,
+    pub header: Option<String>,
+    /// The first capture group is used if there is one, otherwise the whole match.
+    pub regex: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct V1Alpha1FlowStepSpec {
+    pub name: Option<String>,
+    pub method: Option<String>,
+    pub url: String,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub body: Option<String>,
+    /// Accepted status codes, any 2xx if empty.
+    #[serde(default)]
+    pub expect_status: Vec<u16>,
+    /// Regex the response body must match.
+    pub expect_body: Option<String>,
+    #[serde(default)]
+    pub extract: Vec<V1Alpha1ExtractSpec>,
+}
+
+#[monitor_kind(kind = "http-flow", api_version = "v1alpha1")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct V1Alpha1HttpFlowMonitorSpec {
+    #[check(non_empty)]
+    pub steps: Vec<V1Alpha1FlowStepSpec>,
+    /// Initial variables available to every step.
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+    /// Per request timeout.
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+}
+
+impl V1Alpha1ExtractSpec {
+    fn into_extractor(self) -> Result<(String, Extractor), Error> {
+        let extractor = match (self.json_path, self.header, self.regex) {
+            (Some(path), None, None) => Extractor::JsonPath(parse_json_path(&path)?),
+            (None, Some(header), None) => Extractor::Header(header),
+            (None, None, Some(regex)) => Extractor::Regex(Regex::new(&regex)?),
+            _ => bail!(
+                "extract '{}' needs exactly one of json_path, header or regex",
+                self.name
+            ),
+        };
+        Ok((self.name, extractor))
+    }
+}
+
+impl V1Alpha1FlowStepSpec {
+    fn into_step(self, index: usize) -> Result<FlowStep, Error> {
+        let method = match self.method {
+            Some(m) => Method::from_bytes(m.to_uppercase().as_bytes())?,
+            None => Method::GET,
+        };
+
+        Ok(FlowStep {
+            name: self.name.unwrap_or_else(|| format!("step-{}", index + 1)),
+            method,
+            url: self.url,
+            headers: self.headers,
+            body: self.body,
+            expect_status: self.expect_status,
+            expect_body: self.expect_body.as_deref().map(Regex::new).transpose()?,
+            extract: self
+                .extract
+                .into_iter()
+                .map(V1Alpha1ExtractSpec::into_extractor)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+#[async_trait]
+impl MonitorSpec for V1Alpha1HttpFlowMonitorSpec {
+    async fn build(self, _monitor: Monitor) -> Result<TaskPtr, Error> {
+        let steps = self
+            .steps
+            .into_iter()
+            .enumerate()
+            .map(|(i, step)| step.into_step(i))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Arc::new(HttpFlowMonitor {
+            steps,
+            variables: self.variables,
+            timeout: self.timeout.unwrap_or(DEFAULT_TIMEOUT),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::tasks::{test_monitor, TaskBuilder};
+    use axum::http::{header, HeaderMap as AxumHeaderMap, StatusCode};
+    use axum::response::IntoResponse;
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use serde_json::json;
+    use std::net::SocketAddr;
+    use tokio::net::TcpListener;
+
+    async fn login(Json(body): Json<serde_json::Value>) -> impl IntoResponse {
+        if body["user"] != "bob" {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+        (
+            [(header::SET_COOKIE, "session=s3cr3t; Path=/")],
+            Json(json!({ "data": { "tokens": [{ "value": "t-123" }] } })),
+        )
+            .into_response()
+    }
+
+    async fn me(headers: AxumHeaderMap) -> impl IntoResponse {
+        let cookie = headers.get(header::COOKIE).and_then(|v| v.to_str().ok());
+        let auth = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok());
+        if cookie == Some("session=s3cr3t") && auth == Some("Bearer t-123") {
+            "<p>hello bob</p>".into_response()
+        } else {
+            StatusCode::FORBIDDEN.into_response()
+        }
+    }
+
+    async fn spawn_app() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new()
+            .route("/login", post(login))
+            .route("/me", get(me));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    fn flow(addr: SocketAddr, user: &str) -> serde_json::Value {
+        json!({
+            "variables": { "base": format!("http://{addr}"), "user": user },
+            "steps": [
+                {
+                    "name": "login",
+                    "method": "post",
+                    "url": "{{base}}/login",
+                    "headers": { "Content-Type": "application/json" },
+                    "body": "{\"user\": \"{{ user }}\"}",
+                    "extract": [{ "name": "token", "json_path": "$.data.tokens[0].value" }],
+                },
+                {
+                    "name": "profile",
+                    "url": "{{base}}/me",
+                    "headers": { "Authorization": "Bearer {{token}}" },
+                    "expect_body": "hello (\\w+)",
+                },
+            ],
+        })
+    }
+
+    async fn survey(spec: serde_json::Value) -> MonitorStatus {
+        let monitor = test_monitor("http-flow", "v1alpha1", spec);
+        let task = V1Alpha1HttpFlowMonitorBuilder {}
+            .build(monitor)
+            .await
+            .unwrap();
+        task.survey().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_flow_shares_cookies_and_variables() {
+        let addr = spawn_app().await;
+
+        match survey(flow(addr, "bob")).await {
+            MonitorStatus::Up { details, .. } => {
+                let steps = details.unwrap()["steps"].clone();
+                assert_eq!(steps.as_array().unwrap().len(), 2);
+                assert_eq!(steps[1]["status"], 200);
+            }
+            other => panic!("expected Up, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reports_failing_step() {
+        let addr = spawn_app().await;
+
+        match survey(flow(addr, "mallory")).await {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert_eq!(error_reason, "step 1 (login) failed: unexpected status 401")
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_render_and_json_path() {
+        let vars = HashMap::from([("a".to_string(), "1".to_string())]);
+        assert_eq!(render("x{{a}}y{{ a }}", &vars).unwrap(), "x1y1");
+        assert!(render("{{b}}", &vars).is_err());
+
+        assert_eq!(
+            parse_json_path("$.items[2].id").unwrap(),
+            vec![
+                PathSegment::Key("items".to_string()),
+                PathSegment::Index(2),
+                PathSegment::Key("id".to_string()),
+            ]
+        );
+        assert!(parse_json_path("$.items[x]").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_extract_needs_one_source() {
+        let spec = json!({
+            "steps": [{
+                "url": "http://localhost",
+                "extract": [{ "name": "x", "header": "a", "regex": "b" }],
+            }],
+        });
+        let monitor = test_monitor("http-flow", "v1alpha1", spec);
+        assert!(V1Alpha1HttpFlowMonitorBuilder {}
+            .build(monitor)
+            .await
+            .is_err());
+    }
+}
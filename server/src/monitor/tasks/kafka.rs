@@ -0,0 +1,460 @@
+use crate::monitor::tasks::spec::MonitorSpec;
+use crate::monitor::tasks::{MonitorTask, TaskPtr};
+use anyhow::{bail, Error};
+use app::types::{Monitor, MonitorStatus};
+use macros::monitor_kind;
+use migration::async_trait::async_trait;
+use sea_orm::sqlx::types::chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_PORT: u16 = 9092;
+const CLIENT_ID: &str = "whoopsie";
+const MAX_RESPONSE_BYTES: usize = 16 * 1024 * 1024;
+
+const METADATA: i16 = 3;
+// v4 is the oldest version Kafka 4 still accepts and the newest without
+// tagged fields
+const METADATA_VERSION: i16 = 4;
+
+const NONE: i16 = 0;
+const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+const LEADER_NOT_AVAILABLE: i16 = 5;
+
+fn error_name(code: i16) -> String {
+    match code {
+        UNKNOWN_TOPIC_OR_PARTITION => "UNKNOWN_TOPIC_OR_PARTITION".to_string(),
+        LEADER_NOT_AVAILABLE => "LEADER_NOT_AVAILABLE".to_string(),
+        9 => "REPLICA_NOT_AVAILABLE".to_string(),
+        17 => "INVALID_TOPIC_EXCEPTION".to_string(),
+        29 => "TOPIC_AUTHORIZATION_FAILED".to_string(),
+        code => format!("error {code}"),
+    }
+}
+
+struct KafkaReader<'a>(&'a [u8]);
+
+impl<'a> KafkaReader<'a> {
+    fn raw(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < len {
+            bail!("truncated response");
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn i16(&mut self) -> Result<i16, Error> {
+        Ok(i16::from_be_bytes(self.raw(2)?.try_into()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, Error> {
+        Ok(i32::from_be_bytes(self.raw(4)?.try_into()?))
+    }
+
+    fn nullable_string(&mut self) -> Result<Option<String>, Error> {
+        let len = self.i16()?;
+        if len < 0 {
+            return Ok(None);
+        }
+        Ok(Some(
+            String::from_utf8_lossy(self.raw(len as usize)?).into_owned(),
+        ))
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        Ok(self.nullable_string()?.unwrap_or_default())
+    }
+
+    /// Array length, with null arrays read as empty.
+    fn array_len(&mut self) -> Result<usize, Error> {
+        Ok(self.i32()?.max(0) as usize)
+    }
+}
+
+fn put_string(buf: &mut Vec<u8>, value: &str) {
+    buf.extend_from_slice(&(value.len() as i16).to_be_bytes());
+    buf.extend_from_slice(value.as_bytes());
+}
+
+#[derive(Debug)]
+struct TopicMetadata {
+    error_code: i16,
+    name: String,
+    partitions: usize,
+    leaderless: usize,
+    under_replicated: usize,
+}
+
+#[derive(Debug)]
+struct Metadata {
+    brokers: usize,
+    cluster_id: Option<String>,
+    controller_id: i32,
+    topics: Vec<TopicMetadata>,
+}
+
+impl Metadata {
+    fn parse(body: &[u8]) -> Result<Metadata, Error> {
+        let mut reader = KafkaReader(body);
+        let _throttle_time_ms = reader.i32()?;
+
+        let brokers = reader.array_len()?;
+        for _ in 0..brokers {
+            let _node_id = reader.i32()?;
+            let _host = reader.string()?;
+            let _port = reader.i32()?;
+            let _rack = reader.nullable_string()?;
+        }
+        let cluster_id = reader.nullable_string()?;
+        let controller_id = reader.i32()?;
+
+        let mut topics = vec![];
+        for _ in 0..reader.array_len()? {
+            let error_code = reader.i16()?;
+            let name = reader.string()?;
+            let _is_internal = reader.raw(1)?;
+            let partitions = reader.array_len()?;
+            let (mut leaderless, mut under_replicated) = (0, 0);
+            for _ in 0..partitions {
+                let error_code = reader.i16()?;
+                let _partition_index = reader.i32()?;
+                let leader_id = reader.i32()?;
+                let replicas = reader.array_len()?;
+                reader.raw(replicas * 4)?;
+                let isr = reader.array_len()?;
+                reader.raw(isr * 4)?;
+                if leader_id < 0 || error_code == LEADER_NOT_AVAILABLE {
+                    leaderless += 1;
+                } else if isr < replicas {
+                    under_replicated += 1;
+                }
+            }
+            topics.push(TopicMetadata {
+                error_code,
+                name,
+                partitions,
+                leaderless,
+                under_replicated,
+            });
+        }
+
+        Ok(Metadata {
+            brokers,
+            cluster_id,
+            controller_id,
+            topics,
+        })
+    }
+}
+
+struct KafkaMonitor {
+    host: String,
+    port: u16,
+    topics: Vec<String>,
+    timeout: Duration,
+}
+
+impl KafkaMonitor {
+    async fn fetch_metadata(&self) -> Result<Metadata, Error> {
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+
+        let correlation_id = 1i32;
+        let mut request = vec![];
+        request.extend_from_slice(&METADATA.to_be_bytes());
+        request.extend_from_slice(&METADATA_VERSION.to_be_bytes());
+        request.extend_from_slice(&correlation_id.to_be_bytes());
+        put_string(&mut request, CLIENT_ID);
+        // an empty list asks for brokers only, never for every topic
+        request.extend_from_slice(&(self.topics.len() as i32).to_be_bytes());
+        for topic in &self.topics {
+            put_string(&mut request, topic);
+        }
+        // allow_auto_topic_creation, a check must not create what it looks for
+        request.push(0);
+
+        stream.write_u32(request.len() as u32).await?;
+        stream.write_all(&request).await?;
+
+        let size = stream.read_u32().await? as usize;
+        if size > MAX_RESPONSE_BYTES {
+            bail!("response of {size} bytes is too large");
+        }
+        let mut response = vec![0u8; size];
+        stream.read_exact(&mut response).await?;
+
+        let mut reader = KafkaReader(&response);
+        if reader.i32()? != correlation_id {
+            bail!("response doesn't match the metadata request");
+        }
+        Metadata::parse(reader.0)
+    }
+
+    async fn check(&self) -> Result<MonitorStatus, Error> {
+        let started = Instant::now();
+        let metadata = self.fetch_metadata().await?;
+        let metadata_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+        let mut down = vec![];
+        let mut degraded = vec![];
+        let mut topics = serde_json::Map::new();
+        if metadata.brokers == 0 {
+            down.push("no brokers in metadata".to_string());
+        }
+        let missing = metadata
+            .topics
+            .iter()
+            .filter(|t| t.error_code == UNKNOWN_TOPIC_OR_PARTITION)
+            .map(|t| t.name.as_str())
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            down.push(format!("topics not found: {}", missing.join(", ")));
+        }
+        for topic in &metadata.topics {
+            match topic.error_code {
+                NONE | LEADER_NOT_AVAILABLE => {}
+                UNKNOWN_TOPIC_OR_PARTITION => continue,
+                code => {
+                    down.push(format!("{}: {}", topic.name, error_name(code)));
+                    continue;
+                }
+            }
+            if topic.leaderless > 0 {
+                degraded.push(format!(
+                    "{} has {} of {} partitions without a leader",
+                    topic.name, topic.leaderless, topic.partitions
+                ));
+            }
+            if topic.under_replicated > 0 {
+                degraded.push(format!(
+                    "{} has {} under-replicated partitions",
+                    topic.name, topic.under_replicated
+                ));
+            }
+            topics.insert(
+                topic.name.clone(),
+                serde_json::json!({
+                    "partitions": topic.partitions,
+                    "leaderless": topic.leaderless,
+                    "under_replicated": topic.under_replicated,
+                }),
+            );
+        }
+
+        let details = Some(serde_json::json!({
+            "metadata_ms": metadata_ms,
+            "brokers": metadata.brokers,
+            "cluster_id": metadata.cluster_id,
+            "controller_id": metadata.controller_id,
+            "topics": topics,
+        }));
+
+        Ok(if !down.is_empty() {
+            down.extend(degraded);
+            MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: down.join(", "),
+                details,
+            }
+        } else if !degraded.is_empty() {
+            MonitorStatus::Degraded {
+                checked_at: Utc::now(),
+                error_reason: degraded.join(", "),
+                details,
+            }
+        } else {
+            MonitorStatus::Up {
+                checked_at: Utc::now(),
+                details,
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl MonitorTask for KafkaMonitor {
+    async fn survey(&self) -> Result<MonitorStatus, Error> {
+        match tokio::time::timeout(self.timeout, self.check()).await {
+            Ok(Ok(status)) => Ok(status),
+            Ok(Err(err)) => Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: err.to_string(),
+                details: None,
+            }),
+            Err(_) => Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: format!("timed out after {:?}", self.timeout),
+                details: None,
+            }),
+        }
+    }
+}
+
+#[monitor_kind(kind = "kafka", api_version = "v1alpha1")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct V1Alpha1KafkaMonitorSpec {
+    #[check(non_empty)]
+    pub host: String,
+    #[check(min = 1)]
+    pub port: Option<u16>,
+    /// Topics that must exist, every partition having a leader.
+    #[serde(default)]
+    #[check(with = valid_topics)]
+    pub topics: Vec<String>,
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+}
+
+fn valid_topics(topics: &[String]) -> Result<(), String> {
+    match topics.iter().find(|t| t.is_empty() || t.len() > 249) {
+        Some(topic) => Err(format!("'{topic}' isn't a valid topic name")),
+        None => Ok(()),
+    }
+}
+
+#[async_trait]
+impl MonitorSpec for V1Alpha1KafkaMonitorSpec {
+    async fn build(self, _monitor: Monitor) -> Result<TaskPtr, Error> {
+        Ok(Arc::new(KafkaMonitor {
+            host: self.host,
+            port: self.port.unwrap_or(DEFAULT_PORT),
+            topics: self.topics,
+            timeout: self.timeout.unwrap_or(DEFAULT_TIMEOUT),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::tasks::{test_monitor, TaskBuilder};
+    use serde_json::json;
+    use std::net::SocketAddr;
+    use tokio::net::TcpListener;
+
+    fn put_i32s(buf: &mut Vec<u8>, values: &[i32]) {
+        buf.extend_from_slice(&(values.len() as i32).to_be_bytes());
+        for value in values {
+            buf.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+
+    /// Single broker cluster with `orders` healthy, `payments` missing a
+    /// replica from its ISR and `audit` without a leader for partition 1.
+    async fn spawn_broker() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let size = stream.read_u32().await.unwrap() as usize;
+                    let mut request = vec![0u8; size];
+                    stream.read_exact(&mut request).await.unwrap();
+
+                    let mut reader = KafkaReader(&request);
+                    assert_eq!(reader.i16().unwrap(), METADATA);
+                    assert_eq!(reader.i16().unwrap(), METADATA_VERSION);
+                    let correlation_id = reader.i32().unwrap();
+                    assert_eq!(reader.string().unwrap(), CLIENT_ID);
+                    let requested = (0..reader.array_len().unwrap())
+                        .map(|_| reader.string().unwrap())
+                        .collect::<Vec<_>>();
+
+                    let mut body = correlation_id.to_be_bytes().to_vec();
+                    body.extend_from_slice(&0i32.to_be_bytes());
+                    body.extend_from_slice(&1i32.to_be_bytes());
+                    body.extend_from_slice(&1i32.to_be_bytes());
+                    put_string(&mut body, "localhost");
+                    body.extend_from_slice(&9092i32.to_be_bytes());
+                    body.extend_from_slice(&(-1i16).to_be_bytes());
+                    put_string(&mut body, "whoopsie-cluster");
+                    body.extend_from_slice(&1i32.to_be_bytes());
+
+                    body.extend_from_slice(&(requested.len() as i32).to_be_bytes());
+                    for topic in &requested {
+                        let partitions: &[(i32, &[i32])] = match topic.as_str() {
+                            "orders" => &[(1, &[1, 2]), (2, &[2, 1])],
+                            "payments" => &[(1, &[1])],
+                            "audit" => &[(1, &[1, 2]), (-1, &[])],
+                            _ => {
+                                body.extend_from_slice(&UNKNOWN_TOPIC_OR_PARTITION.to_be_bytes());
+                                put_string(&mut body, topic);
+                                body.push(0);
+                                body.extend_from_slice(&0i32.to_be_bytes());
+                                continue;
+                            }
+                        };
+                        body.extend_from_slice(&NONE.to_be_bytes());
+                        put_string(&mut body, topic);
+                        body.push(0);
+                        body.extend_from_slice(&(partitions.len() as i32).to_be_bytes());
+                        for (index, (leader, isr)) in partitions.iter().enumerate() {
+                            body.extend_from_slice(&NONE.to_be_bytes());
+                            body.extend_from_slice(&(index as i32).to_be_bytes());
+                            body.extend_from_slice(&leader.to_be_bytes());
+                            put_i32s(&mut body, &[1, 2]);
+                            put_i32s(&mut body, isr);
+                        }
+                    }
+
+                    stream.write_u32(body.len() as u32).await.unwrap();
+                    stream.write_all(&body).await.unwrap();
+                });
+            }
+        });
+        addr
+    }
+
+    async fn survey(addr: SocketAddr, topics: &[&str]) -> MonitorStatus {
+        let spec = json!({ "host": "127.0.0.1", "port": addr.port(), "topics": topics });
+        let monitor = test_monitor("kafka", "v1alpha1", spec);
+        let task = V1Alpha1KafkaMonitorBuilder {}.build(monitor).await.unwrap();
+        task.survey().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_expected_topics() {
+        let addr = spawn_broker().await;
+
+        match survey(addr, &[]).await {
+            MonitorStatus::Up { details, .. } => {
+                let details = details.unwrap();
+                assert_eq!(details["brokers"], 1);
+                assert_eq!(details["cluster_id"], "whoopsie-cluster");
+            }
+            other => panic!("expected Up, got {other:?}"),
+        }
+
+        match survey(addr, &["orders"]).await {
+            MonitorStatus::Up { details, .. } => {
+                assert_eq!(details.unwrap()["topics"]["orders"]["partitions"], 2)
+            }
+            other => panic!("expected Up, got {other:?}"),
+        }
+
+        match survey(addr, &["orders", "invoices", "refunds"]).await {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert_eq!(error_reason, "topics not found: invoices, refunds")
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unhealthy_partitions() {
+        let addr = spawn_broker().await;
+
+        match survey(addr, &["payments", "audit"]).await {
+            MonitorStatus::Degraded { error_reason, .. } => assert_eq!(
+                error_reason,
+                "payments has 1 under-replicated partitions, audit has 1 of 2 partitions without a leader"
+            ),
+            other => panic!("expected Degraded, got {other:?}"),
+        }
+    }
+}
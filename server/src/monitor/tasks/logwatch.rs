@@ -0,0 +1,416 @@
+use crate::monitor::tasks::spec::{valid_regex, MonitorSpec};
+use crate::monitor::tasks::{MonitorTask, TaskPtr};
+use anyhow::{bail, Error};
+use app::types::{Monitor, MonitorStatus};
+use macros::monitor_kind;
+use migration::async_trait::async_trait;
+use regex::Regex;
+use sea_orm::sqlx::types::chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const DEFAULT_WINDOW: Duration = Duration::from_secs(300);
+const MAX_SAMPLES: usize = 3;
+const MAX_SAMPLE_CHARS: usize = 200;
+/// Longer lines are cut here and the rest of them is skipped.
+const MAX_LINE_BYTES: usize = 64 * 1024;
+
+/// The file currently being followed.
+struct Tail {
+    reader: BufReader<File>,
+    /// (device, inode) of the open file, used to notice rotation.
+    id: (u64, u64),
+    offset: u64,
+    /// A trailing line that hasn't been terminated yet.
+    partial: Vec<u8>,
+    /// Set while skipping what's left of an over-long line.
+    skipping: bool,
+}
+
+impl Tail {
+    fn open(path: &str, from_end: bool) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        let mut reader = BufReader::new(file);
+        let offset = if from_end {
+            reader.seek(SeekFrom::End(0))?
+        } else {
+            0
+        };
+
+        Ok(Tail {
+            reader,
+            id: (metadata.dev(), metadata.ino()),
+            offset,
+            partial: vec![],
+            skipping: false,
+        })
+    }
+
+    /// Reads every complete line appended since the last call.
+    fn read_lines(&mut self, mut on_line: impl FnMut(&str)) -> Result<(), Error> {
+        if self.reader.get_ref().metadata()?.len() < self.offset {
+            // truncated in place (copytruncate), start over. A file that
+            // has already grown past the old offset again can't be told apart
+            // from one that was appended to.
+            self.reader.seek(SeekFrom::Start(0))?;
+            self.offset = 0;
+            self.partial.clear();
+            self.skipping = false;
+        }
+
+        loop {
+            let room = (MAX_LINE_BYTES - self.partial.len()) as u64;
+            let read = (&mut self.reader)
+                .take(room)
+                .read_until(b'\n', &mut self.partial)?;
+            if read == 0 {
+                return Ok(());
+            }
+            self.offset += read as u64;
+
+            let complete = self.partial.last() == Some(&b'\n');
+            if complete || self.partial.len() >= MAX_LINE_BYTES {
+                if !self.skipping {
+                    let line = String::from_utf8_lossy(&self.partial);
+                    on_line(line.trim_end_matches(['\r', '\n']));
+                }
+                self.skipping = !complete;
+                self.partial.clear();
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct WatchState {
+    tail: Option<Tail>,
+    /// Matching lines found by each survey, dropped once out of the window.
+    counts: VecDeque<(Instant, usize)>,
+    /// The last few matching lines, oldest first.
+    samples: VecDeque<(Instant, String)>,
+}
+
+#[derive(Clone)]
+struct LogWatchMonitor {
+    path: String,
+    pattern: Regex,
+    window: Duration,
+    degraded_above: Option<usize>,
+    down_above: Option<usize>,
+    state: Arc<Mutex<WatchState>>,
+}
+
+fn sample(line: &str) -> String {
+    match line.char_indices().nth(MAX_SAMPLE_CHARS) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+impl LogWatchMonitor {
+    fn collect(
+        path: &str,
+        pattern: &Regex,
+        window: Duration,
+        state: &mut WatchState,
+    ) -> Result<(), Error> {
+        let now = Instant::now();
+        let WatchState {
+            tail,
+            counts,
+            samples,
+        } = state;
+        let mut count = 0;
+        let mut on_line = |line: &str| {
+            if pattern.is_match(line) {
+                count += 1;
+                samples.push_back((now, sample(line)));
+                if samples.len() > MAX_SAMPLES {
+                    samples.pop_front();
+                }
+            }
+        };
+
+        match tail {
+            // only lines written after the monitor started are counted
+            None => *tail = Some(Tail::open(path, true)?),
+            Some(current) => {
+                // drain what was written before a rotation
+                current.read_lines(&mut on_line)?;
+
+                let replaced = std::fs::metadata(path)
+                    .map(|m| (m.dev(), m.ino()) != current.id)
+                    .unwrap_or(false);
+                if replaced {
+                    let mut next = Tail::open(path, false)?;
+                    next.read_lines(&mut on_line)?;
+                    *current = next;
+                }
+            }
+        }
+
+        if count > 0 {
+            counts.push_back((now, count));
+        }
+        while counts.front().is_some_and(|(at, _)| now - *at > window) {
+            counts.pop_front();
+        }
+        while samples.front().is_some_and(|(at, _)| now - *at > window) {
+            samples.pop_front();
+        }
+        Ok(())
+    }
+
+    fn check(&self) -> Result<MonitorStatus, Error> {
+        let mut state = self.state.lock().expect("logwatch state poisoned");
+        Self::collect(&self.path, &self.pattern, self.window, &mut state)?;
+
+        let count = state.counts.iter().map(|(_, count)| count).sum::<usize>();
+        let details = Some(serde_json::json!({
+            "matches": count,
+            "window_s": self.window.as_secs(),
+        }));
+        let reason = || {
+            let samples = state
+                .samples
+                .iter()
+                .rev()
+                .map(|(_, line)| line.as_str())
+                .collect::<Vec<_>>();
+            format!(
+                "{count} lines matching /{}/ in the last {:?}: {}",
+                self.pattern,
+                self.window,
+                samples.join(" | ")
+            )
+        };
+
+        if self.down_above.is_some_and(|limit| count > limit) {
+            Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: reason(),
+                details,
+            })
+        } else if self.degraded_above.is_some_and(|limit| count > limit) {
+            Ok(MonitorStatus::Degraded {
+                checked_at: Utc::now(),
+                error_reason: reason(),
+                details,
+            })
+        } else {
+            Ok(MonitorStatus::Up {
+                checked_at: Utc::now(),
+                details,
+            })
+        }
+    }
+}
+
+#[async_trait]
+impl MonitorTask for LogWatchMonitor {
+    async fn survey(&self) -> Result<MonitorStatus, Error> {
+        let monitor = self.clone();
+        let result = tokio::task::spawn_blocking(move || monitor.check()).await?;
+
+        Ok(result.unwrap_or_else(|err| MonitorStatus::Down {
+            checked_at: Utc::now(),
+            error_reason: format!("can't read {}: {err}", self.path),
+            details: None,
+        }))
+    }
+}
+
+#[monitor_kind(kind = "logwatch", api_version = "v1alpha1")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct V1Alpha1LogWatchMonitorSpec {
+    #[check(non_empty)]
+    pub path: String,
+    /// Regex a line has to match to be counted.
+    #[check(with = valid_regex)]
+    pub pattern: String,
+    /// How far back matching lines are counted.
+    #[serde(default, with = "humantime_serde")]
+    pub window: Option<Duration>,
+    /// Matching lines allowed within the window before changing status.
+    pub degraded_above: Option<usize>,
+    pub down_above: Option<usize>,
+}
+
+#[async_trait]
+impl MonitorSpec for V1Alpha1LogWatchMonitorSpec {
+    async fn build(self, _monitor: Monitor) -> Result<TaskPtr, Error> {
+        if self.degraded_above.is_none() && self.down_above.is_none() {
+            bail!("spec: requires degraded_above or down_above");
+        }
+
+        Ok(Arc::new(LogWatchMonitor {
+            path: self.path,
+            pattern: Regex::new(&self.pattern)?,
+            window: self.window.unwrap_or(DEFAULT_WINDOW),
+            degraded_above: self.degraded_above,
+            down_above: self.down_above,
+            state: Default::default(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::tasks::{test_monitor, TaskBuilder};
+    use serde_json::json;
+    use std::io::Write;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn append(path: &Path, lines: &str) {
+        File::options()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap()
+            .write_all(lines.as_bytes())
+            .unwrap();
+    }
+
+    async fn build(path: &Path, window: &str) -> TaskPtr {
+        let monitor = test_monitor(
+            "logwatch",
+            "v1alpha1",
+            json!({
+                "path": path,
+                "pattern": "ERROR",
+                "window": window,
+                "degraded_above": 0,
+                "down_above": 2,
+            }),
+        );
+        V1Alpha1LogWatchMonitorBuilder {}
+            .build(monitor)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_thresholds_and_samples() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("app.log");
+        append(&path, "ERROR from before the monitor started\n");
+        let task = build(&path, "1m").await;
+
+        assert!(matches!(
+            task.survey().await.unwrap(),
+            MonitorStatus::Up { .. }
+        ));
+
+        append(&path, "INFO ok\nERROR db timeout\nINFO ok\nERROR half a li");
+        match task.survey().await.unwrap() {
+            MonitorStatus::Degraded { error_reason, .. } => {
+                assert!(error_reason.starts_with("1 lines"), "{error_reason}");
+                assert!(error_reason.ends_with(": ERROR db timeout"));
+            }
+            other => panic!("expected Degraded, got {other:?}"),
+        }
+
+        append(&path, "ne\nERROR disk full\n");
+        match task.survey().await.unwrap() {
+            MonitorStatus::Down { error_reason, .. } => assert!(
+                error_reason.ends_with(": ERROR disk full | ERROR half a line | ERROR db timeout"),
+                "{error_reason}"
+            ),
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_long_lines_are_cut() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("app.log");
+        append(&path, "");
+        let task = build(&path, "1m").await;
+        task.survey().await.unwrap();
+
+        // the rest of the line isn't read as lines of its own
+        let long = format!("ERROR {} ERROR again\n", "x".repeat(3 * MAX_LINE_BYTES));
+        append(&path, &long);
+        append(&path, "ERROR short\n");
+        match task.survey().await.unwrap() {
+            MonitorStatus::Degraded {
+                error_reason,
+                details,
+                ..
+            } => {
+                assert_eq!(details.unwrap()["matches"], 2);
+                assert!(error_reason.contains(": ERROR short | ERROR xxx"));
+                assert!(error_reason.ends_with("x..."), "{error_reason}");
+            }
+            other => panic!("expected Degraded, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rotation_and_truncation() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("app.log");
+        append(&path, "INFO start\n");
+        let task = build(&path, "1m").await;
+        task.survey().await.unwrap();
+
+        // rename + create, lines written just before the rotation still count
+        append(&path, "ERROR before rotation\n");
+        std::fs::rename(&path, dir.path().join("app.log.1")).unwrap();
+        append(&path, "ERROR after rotation\n");
+        match task.survey().await.unwrap() {
+            MonitorStatus::Degraded { details, .. } => assert_eq!(details.unwrap()["matches"], 2),
+            other => panic!("expected Degraded, got {other:?}"),
+        }
+
+        // copytruncate, only noticed while the file is shorter than before
+        std::fs::write(&path, "").unwrap();
+        append(&path, "ERROR trunc\n");
+        match task.survey().await.unwrap() {
+            MonitorStatus::Down { details, .. } => assert_eq!(details.unwrap()["matches"], 3),
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_matches_leave_the_window() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("app.log");
+        append(&path, "");
+        let task = build(&path, "100ms").await;
+        task.survey().await.unwrap();
+
+        append(&path, "ERROR once\n");
+        assert!(matches!(
+            task.survey().await.unwrap(),
+            MonitorStatus::Degraded { .. }
+        ));
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(matches!(
+            task.survey().await.unwrap(),
+            MonitorStatus::Up { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_missing_file_is_down() {
+        let dir = TempDir::new().unwrap();
+        let task = build(&dir.path().join("missing.log"), "1m").await;
+
+        match task.survey().await.unwrap() {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert!(error_reason.starts_with("can't read"))
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,837 @@
+use crate::monitor::tasks::spec::{valid_regex, MonitorSpec};
+use crate::monitor::tasks::{MonitorTask, TaskPtr};
+use anyhow::{anyhow, bail, Error};
+use app::types::{Monitor, MonitorStatus};
+use base64::Engine;
+use macros::monitor_kind;
+use migration::async_trait::async_trait;
+use regex::Regex;
+use sea_orm::sqlx::types::chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_EHLO_NAME: &str = "localhost";
+const MAX_LINE_BYTES: u64 = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MailProtocol {
+    Smtp,
+    Imap,
+    Pop3,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MailSecurity {
+    #[default]
+    Plain,
+    /// Upgrade a plain connection with STARTTLS (STLS for POP3).
+    Starttls,
+    /// TLS from the first byte, e.g. SMTPS on 465 or IMAPS on 993.
+    Tls,
+}
+
+impl MailProtocol {
+    fn default_port(&self, security: MailSecurity) -> u16 {
+        match (self, security) {
+            (MailProtocol::Smtp, MailSecurity::Tls) => 465,
+            (MailProtocol::Smtp, _) => 25,
+            (MailProtocol::Imap, MailSecurity::Tls) => 993,
+            (MailProtocol::Imap, _) => 143,
+            (MailProtocol::Pop3, MailSecurity::Tls) => 995,
+            (MailProtocol::Pop3, _) => 110,
+        }
+    }
+
+    fn starttls_capability(&self) -> &'static str {
+        match self {
+            MailProtocol::Pop3 => "STLS",
+            _ => "STARTTLS",
+        }
+    }
+}
+
+trait MailStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> MailStream for T {}
+
+/// A line based connection that can be upgraded to TLS mid-session.
+struct Connection {
+    stream: BufReader<Box<dyn MailStream>>,
+}
+
+impl Connection {
+    fn new(stream: impl MailStream + 'static) -> Self {
+        Connection {
+            stream: BufReader::new(Box::new(stream)),
+        }
+    }
+
+    async fn read_line(&mut self) -> Result<String, Error> {
+        let mut line = String::new();
+        (&mut self.stream)
+            .take(MAX_LINE_BYTES)
+            .read_line(&mut line)
+            .await?;
+        if line.is_empty() {
+            bail!("connection closed");
+        }
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    async fn write_line(&mut self, line: &str) -> Result<(), Error> {
+        let stream = self.stream.get_mut();
+        stream.write_all(format!("{line}\r\n").as_bytes()).await?;
+        stream.flush().await?;
+        Ok(())
+    }
+
+    async fn upgrade(self, tls: &MailTls) -> Result<Self, Error> {
+        // anything buffered would have been sent before the handshake
+        if !self.stream.buffer().is_empty() {
+            bail!("server sent data before the tls handshake");
+        }
+        let stream = tls
+            .connector
+            .connect(tls.server_name.clone(), self.stream.into_inner())
+            .await?;
+        Ok(Connection::new(stream))
+    }
+}
+
+struct MailTls {
+    connector: TlsConnector,
+    server_name: ServerName<'static>,
+}
+
+/// One conversation with the server, speaking whichever protocol is
+/// configured.
+struct Session {
+    conn: Connection,
+    protocol: MailProtocol,
+    ehlo_name: String,
+    next_tag: usize,
+}
+
+impl Session {
+    /// Reads a possibly multi-line SMTP reply, returning its code and text
+    /// lines.
+    async fn smtp_reply(&mut self) -> Result<(u16, Vec<String>), Error> {
+        let mut lines = vec![];
+        loop {
+            let line = self.conn.read_line().await?;
+            let code = line
+                .get(..3)
+                .and_then(|c| c.parse::<u16>().ok())
+                .ok_or_else(|| anyhow!("malformed reply '{line}'"))?;
+            lines.push(line.get(4..).unwrap_or_default().to_string());
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return Ok((code, lines));
+            }
+        }
+    }
+
+    async fn smtp_expect(&mut self, command: &str, expected: u16) -> Result<Vec<String>, Error> {
+        self.conn.write_line(command).await?;
+        match self.smtp_reply().await? {
+            (code, lines) if code == expected => Ok(lines),
+            (code, lines) => bail!("expected {expected}, got {code} {}", lines.join(" ")),
+        }
+    }
+
+    /// Sends a tagged IMAP command, returning the untagged responses.
+    async fn imap_command(&mut self, command: &str) -> Result<Vec<String>, Error> {
+        self.next_tag += 1;
+        let tag = format!("a{}", self.next_tag);
+        self.conn.write_line(&format!("{tag} {command}")).await?;
+
+        let mut untagged = vec![];
+        loop {
+            let line = self.conn.read_line().await?;
+            match line.strip_prefix(&tag).map(str::trim_start) {
+                Some(status) if status.starts_with("OK") => return Ok(untagged),
+                Some(status) => bail!("{status}"),
+                None => untagged.push(line),
+            }
+        }
+    }
+
+    async fn pop3_expect(&mut self, command: &str) -> Result<(), Error> {
+        self.conn.write_line(command).await?;
+        let line = self.conn.read_line().await?;
+        if !line.starts_with("+OK") {
+            bail!("{line}");
+        }
+        Ok(())
+    }
+
+    async fn greeting(&mut self) -> Result<String, Error> {
+        match self.protocol {
+            MailProtocol::Smtp => match self.smtp_reply().await? {
+                (220, lines) => Ok(lines.join(" ")),
+                (code, lines) => bail!("expected 220, got {code} {}", lines.join(" ")),
+            },
+            MailProtocol::Imap => {
+                let line = self.conn.read_line().await?;
+                match line.strip_prefix("* ") {
+                    Some(text) if text.starts_with("OK") || text.starts_with("PREAUTH") => {
+                        Ok(text.to_string())
+                    }
+                    _ => bail!("unexpected greeting '{line}'"),
+                }
+            }
+            MailProtocol::Pop3 => {
+                let line = self.conn.read_line().await?;
+                match line.strip_prefix("+OK") {
+                    Some(text) => Ok(text.trim().to_string()),
+                    None => bail!("unexpected greeting '{line}'"),
+                }
+            }
+        }
+    }
+
+    async fn capabilities(&mut self) -> Result<Vec<String>, Error> {
+        match self.protocol {
+            MailProtocol::Smtp => {
+                let ehlo = format!("EHLO {}", self.ehlo_name);
+                let lines = self.smtp_expect(&ehlo, 250).await?;
+                // the first line is the server greeting us
+                Ok(lines.into_iter().skip(1).collect())
+            }
+            MailProtocol::Imap => Ok(self
+                .imap_command("CAPABILITY")
+                .await?
+                .iter()
+                .filter_map(|line| line.strip_prefix("* CAPABILITY "))
+                .flat_map(str::split_whitespace)
+                .map(str::to_string)
+                .collect()),
+            MailProtocol::Pop3 => {
+                // CAPA is optional, servers without it don't offer anything
+                self.conn.write_line("CAPA").await?;
+                let line = self.conn.read_line().await?;
+                if line.starts_with("-ERR") {
+                    return Ok(vec![]);
+                } else if !line.starts_with("+OK") {
+                    bail!("{line}");
+                }
+                let mut capabilities = vec![];
+                loop {
+                    let line = self.conn.read_line().await?;
+                    if line == "." {
+                        return Ok(capabilities);
+                    }
+                    capabilities.push(line);
+                }
+            }
+        }
+    }
+
+    async fn starttls(mut self, tls: &MailTls) -> Result<Self, Error> {
+        match self.protocol {
+            MailProtocol::Smtp => {
+                self.smtp_expect("STARTTLS", 220).await?;
+            }
+            MailProtocol::Imap => {
+                self.imap_command("STARTTLS").await?;
+            }
+            MailProtocol::Pop3 => self.pop3_expect("STLS").await?,
+        }
+        self.conn = self.conn.upgrade(tls).await?;
+        Ok(self)
+    }
+
+    async fn authenticate(&mut self, username: &str, password: &str) -> Result<(), Error> {
+        match self.protocol {
+            MailProtocol::Smtp => {
+                let plain = base64::engine::general_purpose::STANDARD
+                    .encode(format!("\0{username}\0{password}"));
+                self.smtp_expect(&format!("AUTH PLAIN {plain}"), 235)
+                    .await?;
+            }
+            MailProtocol::Imap => {
+                let quote =
+                    |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+                self.imap_command(&format!("LOGIN {} {}", quote(username), quote(password)))
+                    .await?;
+            }
+            MailProtocol::Pop3 => {
+                self.pop3_expect(&format!("USER {username}")).await?;
+                self.pop3_expect(&format!("PASS {password}")).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn quit(&mut self) -> Result<(), Error> {
+        match self.protocol {
+            MailProtocol::Smtp => self.smtp_expect("QUIT", 221).await.map(|_| ()),
+            MailProtocol::Imap => self.imap_command("LOGOUT").await.map(|_| ()),
+            MailProtocol::Pop3 => self.pop3_expect("QUIT").await,
+        }
+    }
+}
+
+/// Times each phase of the session, prefixing errors with the phase name.
+#[derive(Default)]
+struct Phases {
+    timings: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Phases {
+    async fn run<T>(
+        &mut self,
+        name: &str,
+        phase: impl Future<Output = Result<T, Error>>,
+    ) -> Result<T, Error> {
+        let started = Instant::now();
+        let result = phase.await.map_err(|e| anyhow!("{name}: {e}"))?;
+        self.timings.insert(
+            format!("{name}_ms"),
+            (started.elapsed().as_secs_f64() * 1000.0).into(),
+        );
+        Ok(result)
+    }
+}
+
+struct Credentials {
+    username: String,
+    password: String,
+}
+
+struct MailMonitor {
+    protocol: MailProtocol,
+    security: MailSecurity,
+    host: String,
+    port: u16,
+    tls: Option<MailTls>,
+    ehlo_name: String,
+    expect_banner: Option<Regex>,
+    capabilities: Vec<String>,
+    auth: Option<Credentials>,
+    timeout: Duration,
+}
+
+impl MailMonitor {
+    fn tls(&self) -> Result<&MailTls, Error> {
+        self.tls
+            .as_ref()
+            .ok_or_else(|| anyhow!("tls isn't configured"))
+    }
+
+    async fn check(&self) -> Result<serde_json::Value, Error> {
+        let mut phases = Phases::default();
+
+        let tcp = phases
+            .run("connect", async {
+                Ok(TcpStream::connect((self.host.as_str(), self.port)).await?)
+            })
+            .await?;
+        let mut conn = Connection::new(tcp);
+        if self.security == MailSecurity::Tls {
+            conn = phases.run("tls", conn.upgrade(self.tls()?)).await?;
+        }
+
+        let mut session = Session {
+            conn,
+            protocol: self.protocol,
+            ehlo_name: self.ehlo_name.clone(),
+            next_tag: 0,
+        };
+
+        let banner = phases.run("banner", session.greeting()).await?;
+        if let Some(expect) = &self.expect_banner {
+            if !expect.is_match(&banner) {
+                bail!("banner: '{banner}' doesn't match /{expect}/");
+            }
+        }
+
+        let mut capabilities = phases.run("capabilities", session.capabilities()).await?;
+        let offers = |capabilities: &[String], wanted: &str| {
+            capabilities.iter().any(|c| {
+                c.eq_ignore_ascii_case(wanted)
+                    || c.split_whitespace()
+                        .next()
+                        .is_some_and(|keyword| keyword.eq_ignore_ascii_case(wanted))
+            })
+        };
+
+        if self.security == MailSecurity::Starttls {
+            let keyword = self.protocol.starttls_capability();
+            if !offers(&capabilities, keyword) {
+                bail!("starttls: server doesn't offer {keyword}");
+            }
+            session = phases
+                .run("starttls", session.starttls(self.tls()?))
+                .await?;
+            // capabilities have to be asked for again over tls
+            capabilities = phases
+                .run("capabilities_tls", session.capabilities())
+                .await?;
+        }
+
+        let missing = self
+            .capabilities
+            .iter()
+            .filter(|wanted| !offers(&capabilities, wanted))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            bail!("capabilities: missing {}", missing.join(", "));
+        }
+
+        if let Some(auth) = &self.auth {
+            phases
+                .run("auth", session.authenticate(&auth.username, &auth.password))
+                .await?;
+        }
+
+        // the checks passed, a server hanging up early doesn't change that
+        let _ = session.quit().await;
+
+        let mut details = phases.timings;
+        details.insert("banner".to_string(), banner.into());
+        details.insert("capabilities".to_string(), capabilities.into());
+        Ok(details.into())
+    }
+}
+
+#[async_trait]
+impl MonitorTask for MailMonitor {
+    async fn survey(&self) -> Result<MonitorStatus, Error> {
+        match tokio::time::timeout(self.timeout, self.check()).await {
+            Ok(Ok(details)) => Ok(MonitorStatus::Up {
+                checked_at: Utc::now(),
+                details: Some(details),
+            }),
+            Ok(Err(err)) => Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: err.to_string(),
+                details: None,
+            }),
+            Err(_) => Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: format!("timed out after {:?}", self.timeout),
+                details: None,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct V1Alpha1MailTlsSpec {
+    /// PEM file with the CA used to verify the server, in addition to the system roots.
+    pub ca_certificate_file: Option<String>,
+    /// Overrides the name used for SNI and certificate verification.
+    pub domain_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct V1Alpha1MailAuthSpec {
+    pub username: String,
+    pub password: String,
+}
+
+#[monitor_kind(kind = "mail", api_version = "v1alpha1")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct V1Alpha1MailMonitorSpec {
+    /// `smtp`, `imap` or `pop3`.
+    pub protocol: MailProtocol,
+    #[check(non_empty)]
+    pub host: String,
+    /// Defaults to the well known port for the protocol and security.
+    #[check(min = 1)]
+    pub port: Option<u16>,
+    /// `plain`, `starttls` or `tls`.
+    #[serde(default)]
+    pub security: MailSecurity,
+    pub tls: Option<V1Alpha1MailTlsSpec>,
+    /// Name sent with SMTP `EHLO`.
+    pub ehlo_name: Option<String>,
+    /// Regex the greeting has to match.
+    #[check(with = valid_regex)]
+    pub expect_banner: Option<String>,
+    /// Capabilities the server has to advertise, e.g. `PIPELINING` or `IDLE`.
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    /// Only allowed with starttls or tls security.
+    pub auth: Option<V1Alpha1MailAuthSpec>,
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+}
+
+impl V1Alpha1MailTlsSpec {
+    async fn into_tls(self, host: &str) -> Result<MailTls, Error> {
+        let mut roots = RootCertStore::empty();
+        // unreadable system certificates are skipped like tonic does
+        for cert in rustls_native_certs::load_native_certs().certs {
+            let _ = roots.add(cert);
+        }
+        if let Some(path) = &self.ca_certificate_file {
+            let pem = tokio::fs::read(path).await?;
+            for cert in CertificateDer::pem_slice_iter(&pem) {
+                roots.add(cert?)?;
+            }
+        }
+
+        let provider = Arc::new(tokio_rustls::rustls::crypto::ring::default_provider());
+        let config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+
+        let name = self.domain_name.unwrap_or_else(|| host.to_string());
+        Ok(MailTls {
+            connector: TlsConnector::from(Arc::new(config)),
+            server_name: ServerName::try_from(name)?,
+        })
+    }
+}
+
+#[async_trait]
+impl MonitorSpec for V1Alpha1MailMonitorSpec {
+    async fn build(self, _monitor: Monitor) -> Result<TaskPtr, Error> {
+        if self.security == MailSecurity::Plain && self.auth.is_some() {
+            bail!("spec.auth: requires starttls or tls security");
+        }
+        let tls = match self.security {
+            MailSecurity::Plain if self.tls.is_some() => {
+                bail!("spec.tls: requires starttls or tls security")
+            }
+            MailSecurity::Plain => None,
+            _ => Some(self.tls.unwrap_or_default().into_tls(&self.host).await?),
+        };
+
+        Ok(Arc::new(MailMonitor {
+            protocol: self.protocol,
+            security: self.security,
+            port: self
+                .port
+                .unwrap_or_else(|| self.protocol.default_port(self.security)),
+            host: self.host,
+            tls,
+            ehlo_name: self
+                .ehlo_name
+                .unwrap_or_else(|| DEFAULT_EHLO_NAME.to_string()),
+            expect_banner: self.expect_banner.as_deref().map(Regex::new).transpose()?,
+            capabilities: self.capabilities,
+            auth: self.auth.map(|auth| Credentials {
+                username: auth.username,
+                password: auth.password,
+            }),
+            timeout: self.timeout.unwrap_or(DEFAULT_TIMEOUT),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::tasks::{test_monitor, TaskBuilder};
+    use serde_json::json;
+    use std::net::SocketAddr;
+    use tempfile::NamedTempFile;
+    use tokio::net::TcpListener;
+    use tokio_rustls::rustls::pki_types::PrivateKeyDer;
+    use tokio_rustls::rustls::ServerConfig;
+    use tokio_rustls::TlsAcceptor;
+
+    struct TestCa {
+        acceptor: TlsAcceptor,
+        ca_file: NamedTempFile,
+    }
+
+    fn test_ca() -> TestCa {
+        let key = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let ca_file = NamedTempFile::new().unwrap();
+        std::fs::write(ca_file.path(), key.cert.pem()).unwrap();
+
+        let provider = Arc::new(tokio_rustls::rustls::crypto::ring::default_provider());
+        let config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![key.cert.der().clone()],
+                PrivateKeyDer::Pkcs8(key.signing_key.serialize_der().into()),
+            )
+            .unwrap();
+
+        TestCa {
+            acceptor: TlsAcceptor::from(Arc::new(config)),
+            ca_file,
+        }
+    }
+
+    async fn accept_tls(acceptor: &TlsAcceptor, conn: Connection) -> Connection {
+        Connection::new(acceptor.accept(conn.stream.into_inner()).await.unwrap())
+    }
+
+    /// SMTP relay offering STARTTLS, AUTH only after the upgrade.
+    async fn smtp_server(acceptor: TlsAcceptor, mut conn: Connection) {
+        conn.write_line("220 mx.example.com ESMTP ready")
+            .await
+            .unwrap();
+        let mut tls = false;
+        while let Ok(line) = conn.read_line().await {
+            let reply = match line.as_str() {
+                l if l.starts_with("EHLO ") && tls => {
+                    "250-mx.example.com\r\n250-PIPELINING\r\n250 AUTH PLAIN LOGIN"
+                }
+                l if l.starts_with("EHLO ") => {
+                    "250-mx.example.com\r\n250-PIPELINING\r\n250 STARTTLS"
+                }
+                "STARTTLS" => {
+                    conn.write_line("220 go ahead").await.unwrap();
+                    conn = accept_tls(&acceptor, conn).await;
+                    tls = true;
+                    continue;
+                }
+                // base64 of "\0relay\0hunter2"
+                "AUTH PLAIN AHJlbGF5AGh1bnRlcjI=" if tls => "235 2.7.0 accepted",
+                l if l.starts_with("AUTH ") => "535 5.7.8 authentication failed",
+                "QUIT" => "221 bye",
+                _ => "500 unrecognised",
+            };
+            conn.write_line(reply).await.unwrap();
+        }
+    }
+
+    /// IMAP server meant to be reached over implicit TLS.
+    async fn imap_server(acceptor: TlsAcceptor, conn: Connection) {
+        let mut conn = accept_tls(&acceptor, conn).await;
+        conn.write_line("* OK IMAP4rev1 ready").await.unwrap();
+        while let Ok(line) = conn.read_line().await {
+            let (tag, command) = line.split_once(' ').unwrap();
+            let reply = match command {
+                "CAPABILITY" => format!("* CAPABILITY IMAP4rev1 IDLE AUTH=PLAIN\r\n{tag} OK done"),
+                "LOGIN \"archive\" \"s3cr\\\"t\"" => format!("{tag} OK logged in"),
+                c if c.starts_with("LOGIN ") => format!("{tag} NO [AUTHENTICATIONFAILED] nope"),
+                "LOGOUT" => format!("* BYE\r\n{tag} OK bye"),
+                _ => format!("{tag} BAD unknown"),
+            };
+            conn.write_line(&reply).await.unwrap();
+        }
+    }
+
+    async fn pop3_server(mut conn: Connection) {
+        conn.write_line("+OK POP3 ready").await.unwrap();
+        while let Ok(line) = conn.read_line().await {
+            let reply = match line.as_str() {
+                "CAPA" => "+OK\r\nUSER\r\nUIDL\r\n.",
+                "QUIT" => "+OK bye",
+                _ => "-ERR unknown",
+            };
+            conn.write_line(reply).await.unwrap();
+        }
+    }
+
+    /// A POP3 server from before CAPA.
+    async fn legacy_pop3_server(mut conn: Connection) {
+        conn.write_line("+OK POP3 ready").await.unwrap();
+        while let Ok(line) = conn.read_line().await {
+            let reply = match line.as_str() {
+                "QUIT" => "+OK bye",
+                _ => "-ERR unknown",
+            };
+            conn.write_line(reply).await.unwrap();
+        }
+    }
+
+    async fn spawn_server<F, Fut>(handler: F) -> SocketAddr
+    where
+        F: Fn(Connection) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                tokio::spawn(handler(Connection::new(socket)));
+            }
+        });
+        addr
+    }
+
+    async fn survey(spec: serde_json::Value) -> MonitorStatus {
+        let monitor = test_monitor("mail", "v1alpha1", spec);
+        let task = V1Alpha1MailMonitorBuilder {}.build(monitor).await.unwrap();
+        task.survey().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_smtp_starttls_and_auth() {
+        let ca = test_ca();
+        let acceptor = ca.acceptor.clone();
+        let addr = spawn_server(move |conn| smtp_server(acceptor.clone(), conn)).await;
+        let spec = |password: &str| {
+            json!({
+                "protocol": "smtp",
+                "host": "localhost",
+                "port": addr.port(),
+                "security": "starttls",
+                "tls": { "ca_certificate_file": ca.ca_file.path() },
+                "expect_banner": "ESMTP",
+                "capabilities": ["pipelining", "AUTH"],
+                "auth": { "username": "relay", "password": password },
+            })
+        };
+
+        match survey(spec("hunter2")).await {
+            MonitorStatus::Up { details, .. } => {
+                let details = details.unwrap();
+                for phase in [
+                    "connect_ms",
+                    "banner_ms",
+                    "capabilities_ms",
+                    "starttls_ms",
+                    "capabilities_tls_ms",
+                    "auth_ms",
+                ] {
+                    assert!(details[phase].is_f64(), "{phase}");
+                }
+                assert_eq!(
+                    details["capabilities"],
+                    json!(["PIPELINING", "AUTH PLAIN LOGIN"])
+                );
+            }
+            other => panic!("expected Up, got {other:?}"),
+        }
+
+        match survey(spec("wrong")).await {
+            MonitorStatus::Down { error_reason, .. } => assert_eq!(
+                error_reason,
+                "auth: expected 235, got 535 5.7.8 authentication failed"
+            ),
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_imap_implicit_tls() {
+        let ca = test_ca();
+        let acceptor = ca.acceptor.clone();
+        let addr = spawn_server(move |conn| imap_server(acceptor.clone(), conn)).await;
+
+        let status = survey(json!({
+            "protocol": "imap",
+            "host": "127.0.0.1",
+            "port": addr.port(),
+            "security": "tls",
+            "tls": { "ca_certificate_file": ca.ca_file.path(), "domain_name": "localhost" },
+            "capabilities": ["IDLE"],
+            "auth": { "username": "archive", "password": "s3cr\"t" },
+        }))
+        .await;
+        match status {
+            MonitorStatus::Up { details, .. } => assert!(details.unwrap()["tls_ms"].is_f64()),
+            other => panic!("expected Up, got {other:?}"),
+        }
+
+        // certificate isn't valid for the name we connect with
+        let status = survey(json!({
+            "protocol": "imap",
+            "host": "127.0.0.1",
+            "port": addr.port(),
+            "security": "tls",
+            "tls": { "ca_certificate_file": ca.ca_file.path() },
+        }))
+        .await;
+        match status {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert!(error_reason.starts_with("tls: "), "{error_reason}")
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pop3_capabilities() {
+        let addr = spawn_server(pop3_server).await;
+        let spec = |capabilities: Vec<&str>| {
+            json!({
+                "protocol": "pop3",
+                "host": "127.0.0.1",
+                "port": addr.port(),
+                "capabilities": capabilities,
+            })
+        };
+
+        assert!(matches!(
+            survey(spec(vec!["UIDL"])).await,
+            MonitorStatus::Up { .. }
+        ));
+        match survey(spec(vec!["UIDL", "SASL", "TOP"])).await {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert_eq!(error_reason, "capabilities: missing SASL, TOP")
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+
+        let mut starttls = spec(vec![]);
+        starttls["security"] = json!("starttls");
+        match survey(starttls).await {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert_eq!(error_reason, "starttls: server doesn't offer STLS")
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+    #[tokio::test]
+    async fn test_pop3_without_capa() {
+        let addr = spawn_server(legacy_pop3_server).await;
+        let spec = |capabilities: Vec<&str>| {
+            json!({
+                "protocol": "pop3",
+                "host": "127.0.0.1",
+                "port": addr.port(),
+                "capabilities": capabilities,
+            })
+        };
+
+        match survey(spec(vec![])).await {
+            MonitorStatus::Up { details, .. } => {
+                assert_eq!(details.unwrap()["capabilities"], json!([]))
+            }
+            other => panic!("expected Up, got {other:?}"),
+        }
+        match survey(spec(vec!["UIDL"])).await {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert_eq!(error_reason, "capabilities: missing UIDL")
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+    #[tokio::test]
+    async fn test_auth_requires_tls() {
+        let monitor = test_monitor(
+            "mail",
+            "v1alpha1",
+            json!({
+                "protocol": "smtp",
+                "host": "localhost",
+                "auth": { "username": "relay", "password": "hunter2" },
+            }),
+        );
+        let error = V1Alpha1MailMonitorBuilder {}
+            .build(monitor)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "spec.auth: requires starttls or tls security"
+        );
+    }
+}
@@ -1,14 +1,42 @@
+mod amqp;
+mod assert;
+mod composite;
+pub(crate) mod conversion;
 mod endpoint;
+mod exec;
+mod file;
+mod grpc;
+pub(crate) mod heartbeat;
+mod host;
+mod http_flow;
+mod kafka;
+mod logwatch;
+mod mail;
+mod mqtt;
+mod plugin;
+mod prometheus;
+mod redis;
+mod spec;
+mod ssh;
+mod udp;
+mod wasm;
+mod websocket;
 
+use crate::config::plugin_config::PluginConfigs;
+use crate::monitor::limits::CheckLimiterPtr;
+use crate::monitor::maintenance::MaintenanceRegistryPtr;
+use crate::monitor::schedule::Schedule;
+use crate::monitor::tasks::assert::Observation;
+use crate::monitor::tasks::heartbeat::HeartbeatRegistryPtr;
 use crate::signal::ExitSignal;
+use anyhow::bail;
 use app::types::{Monitor, MonitorStatus};
 use app::DbFactoryPointer;
 use migration::async_trait::async_trait;
-use sea_orm::sqlx::types::chrono::Utc;
+use sea_orm::sqlx::types::chrono::{DateTime, Utc};
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::sync::Arc;
-use std::time::Duration;
 use tokio::select;
 use tokio::sync::{RwLock, RwLockWriteGuard};
 
@@ -16,65 +44,78 @@ pub type TaskPtr = Arc<dyn MonitorTask + Send + Sync>;
 pub type TaskBuilderPtr = Arc<dyn TaskBuilder + Send + Sync>;
 pub type TaskFactoryPtr = Arc<TaskFactory>;
 
+/// The task a monitor is running, shared with the scheduler so checks on
+/// demand survey it instead of a freshly built one.
+#[derive(Clone, Default)]
+pub struct RunningTask(Arc<std::sync::Mutex<Option<TaskPtr>>>);
+
+impl RunningTask {
+    pub fn get(&self) -> Option<TaskPtr> {
+        self.0.lock().expect("running task poisoned").clone()
+    }
+
+    pub fn set(&self, task: TaskPtr) {
+        *self.0.lock().expect("running task poisoned") = Some(task);
+    }
+}
+
+impl Debug for RunningTask {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("RunningTask")
+            .field(&self.get().is_some())
+            .finish()
+    }
+}
+
 pub async fn monitor_task(
     monitor: Monitor,
     db: DbFactoryPointer,
     task_factory: TaskFactoryPtr,
+    limiter: CheckLimiterPtr,
+    maintenance: MaintenanceRegistryPtr,
+    running: RunningTask,
     exit_signal: ExitSignal,
 ) -> Result<(), anyhow::Error> {
-    let task = task_factory.construct_task(&monitor).await;
-
-    if let Err(e) = task {
-        log::error!("error constructing task: {e}");
-        let monitor_repo = db.get_monitor_repository();
-        let now = Utc::now();
-        let status = MonitorStatus::Down {
-            checked_at: now,
-            error_reason: e.to_string(),
-        };
-        monitor_repo
-            .log_status(monitor.name.clone(), status)
-            .await?;
-        return Err(e);
-    }
+    // failures are recorded by the scheduler, which restarts the task
+    let task = task_factory.construct_task(&monitor).await?;
+    running.set(task.clone());
 
-    let task = task.unwrap();
-
-    monitor_task_fn(monitor, task, db, exit_signal).await
+    monitor_task_fn(monitor, task, db, limiter, maintenance, exit_signal).await
 }
 
 async fn monitor_task_fn(
     monitor: Monitor,
     task: TaskPtr,
     db: DbFactoryPointer,
+    limiter: CheckLimiterPtr,
+    maintenance: MaintenanceRegistryPtr,
     mut exit_signal: ExitSignal,
 ) -> Result<(), anyhow::Error> {
-    let loop_interval: Duration;
-    if let Some(config) = monitor.configuration {
-        if let Some(interval) = config.check_interval {
-            loop_interval = interval;
-        } else {
-            panic!("check_interval not set");
+    let Some(config) = &monitor.configuration else {
+        bail!("configuration not set");
+    };
+    let schedule = Schedule::new(config)?;
+
+    // spread first runs so monitors on an interval don't all fire at once
+    let jitter = schedule
+        .interval()
+        .map(|interval| limiter.first_run_delay(&monitor.name, interval))
+        .unwrap_or_default();
+    select! {
+        _ = exit_signal.wait() => {
+            return Ok(())
         }
-    } else {
-        panic!("configuration not set");
+        _ = sleep_until(schedule.first_check(Utc::now(), jitter)) => {}
     }
 
     loop {
         let monitor_repo = db.get_monitor_repository();
-        let survey_result = task.survey().await;
-
-        let mut status: MonitorStatus = MonitorStatus::Unknown;
-        let now = Utc::now();
-        if let Err(e) = survey_result {
-            log::debug!("error surveying monitor {}: {}", monitor.name, e);
-            status = MonitorStatus::Down {
-                checked_at: now,
-                error_reason: e.to_string(),
-            };
-        } else if let Ok(s) = survey_result {
-            status = s
-        }
+        let status = select! {
+            status = check(&monitor, &task, &limiter, &maintenance) => status,
+            _ = exit_signal.wait() => {
+                return Ok(())
+            }
+        };
 
         let log_result = monitor_repo.log_status(monitor.name.clone(), status).await;
 
@@ -86,14 +127,56 @@ async fn monitor_task_fn(
             _ = exit_signal.wait() => {
                 return Ok(())
             }
-            _ = tokio::time::sleep(loop_interval) => {}
+            _ = sleep_until(schedule.next_check(Utc::now())) => {}
+        }
+    }
+}
+
+/// Surveys the monitor once, within the concurrency limits. A failed survey
+/// is the monitor being down.
+pub async fn check(
+    monitor: &Monitor,
+    task: &TaskPtr,
+    limiter: &CheckLimiterPtr,
+    maintenance: &MaintenanceRegistryPtr,
+) -> MonitorStatus {
+    let survey_result = {
+        let _permit = limiter.acquire(monitor).await;
+        task.survey().await
+    };
+
+    let now = Utc::now();
+    let status = match survey_result {
+        Ok(status) => status,
+        Err(e) => {
+            log::debug!("error surveying monitor {}: {}", monitor.name, e);
+            MonitorStatus::Down {
+                checked_at: now,
+                error_reason: e.to_string(),
+                details: None,
+            }
         }
+    };
+    match maintenance.window_for(monitor, now).await {
+        Some(window) => status.in_maintenance(window),
+        None => status,
     }
 }
 
+async fn sleep_until(at: DateTime<Utc>) {
+    let delay = (at - Utc::now()).to_std().unwrap_or_default();
+    tokio::time::sleep(delay).await
+}
+
 #[async_trait]
 pub trait MonitorTask {
     async fn survey(&self) -> Result<MonitorStatus, anyhow::Error>;
+
+    /// Surveys and keeps what the check saw for `assert` scripts. Tasks with
+    /// more to show than their status override this.
+    async fn observe(&self) -> Result<Observation, anyhow::Error> {
+        Ok(self.survey().await?.into())
+    }
 }
 
 #[async_trait]
@@ -115,9 +198,43 @@ impl TaskFactory {
         }
     }
 
-    pub async fn register_standard_builders(&self) {
-        let builders = endpoint::get_builders();
-        self.bulk_register(builders).await;
+    /// Registers the built-in kinds, every `#[monitor_kind]` spec in the crate.
+    pub async fn register_standard_builders(
+        &self,
+        db: DbFactoryPointer,
+        heartbeats: HeartbeatRegistryPtr,
+    ) {
+        let context = spec::BuildContext { db, heartbeats };
+        self.bulk_register(spec::registered_builders(&context))
+            .await;
+    }
+
+    /// Starts every configured plugin and registers the kinds it handles. A
+    /// plugin that fails to start keeps being restarted in the background and
+    /// its kinds are registered once it's up; a wasm plugin that fails to load
+    /// is logged and skipped.
+    pub async fn register_plugin_builders(self: &Arc<Self>, plugins: &PluginConfigs) {
+        for config in &plugins.executables {
+            match plugin::get_builders(config).await {
+                Ok(builders) => self.bulk_register(builders).await,
+                Err(e) => {
+                    log::error!("error starting plugin {}: {e:#}", config.name);
+                    let factory = self.clone();
+                    let config = config.clone();
+                    tokio::spawn(async move {
+                        let builders = plugin::retry_builders(&config).await;
+                        log::info!("plugin {} started", config.name);
+                        factory.bulk_register(builders).await;
+                    });
+                }
+            }
+        }
+        for config in &plugins.wasm {
+            match wasm::get_builders(config) {
+                Ok(builders) => self.bulk_register(builders).await,
+                Err(e) => log::error!("error loading wasm plugin {}: {e:#}", config.name),
+            }
+        }
     }
 
     #[allow(dead_code)]
@@ -145,15 +262,34 @@ impl TaskFactory {
         builder: TaskBuilderPtr,
     ) {
         let registration_name = self.builder_registration_name(&builder);
+        // first come first served, plugins can't take over built-in kinds
+        if write_guard.contains_key(&registration_name) {
+            log::error!("{registration_name} is already registered, ignoring {builder:?}");
+            return;
+        }
         write_guard.insert(registration_name, builder);
     }
 
     pub async fn construct_task(&self, monitor: &Monitor) -> Result<TaskPtr, anyhow::Error> {
-        let registration_name = self.monitor_registration_name(monitor);
-        let read_guard = self.registrations.read().await;
+        let mut monitor = monitor.clone();
+        conversion::upgrade(&mut monitor)?;
+        let registration_name = self.monitor_registration_name(&monitor);
+        // plugins build over a round-trip, registering doesn't wait on that
+        let builder = self
+            .registrations
+            .read()
+            .await
+            .get(&registration_name)
+            .cloned();
 
-        if let Some(builder) = read_guard.get(&registration_name) {
-            builder.build(monitor.clone()).await
+        if let Some(builder) = builder {
+            let script = assert::take_script(&mut monitor)?;
+            let name = monitor.name.clone();
+            let task = builder.build(monitor).await?;
+            match script {
+                Some(script) => assert::wrap(task, &name, &script),
+                None => Ok(task),
+            }
         } else {
             Err(anyhow::anyhow!(
                 "unknown monitor type: {}",
@@ -170,3 +306,17 @@ impl TaskFactory {
         format!("{}/{}", builder.get_kind(), builder.get_api_version())
     }
 }
+
+#[cfg(test)]
+pub(crate) fn test_monitor(kind: &str, api_version: &str, spec: serde_json::Value) -> Monitor {
+    Monitor {
+        name: format!("test-{kind}"),
+        current_status: None,
+        api_version: api_version.to_string(),
+        kind: kind.to_string(),
+        labels: Default::default(),
+        configuration: None,
+        spec,
+        paused: false,
+    }
+}
@@ -0,0 +1,389 @@
+use crate::monitor::tasks::spec::MonitorSpec;
+use crate::monitor::tasks::{MonitorTask, TaskPtr};
+use anyhow::{anyhow, bail, Error};
+use app::types::{Monitor, MonitorStatus};
+use macros::monitor_kind;
+use migration::async_trait::async_trait;
+use ring::rand::{SecureRandom, SystemRandom};
+use sea_orm::sqlx::types::chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_PORT: u16 = 1883;
+const KEEP_ALIVE_SECS: u16 = 30;
+const MAX_PACKET_BYTES: usize = 1024 * 1024;
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const SUBSCRIBE: u8 = 0x82;
+const SUBACK: u8 = 0x90;
+const DISCONNECT: u8 = 0xe0;
+
+fn nonce() -> Result<String, Error> {
+    let mut bytes = [0u8; 8];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| anyhow!("no randomness available"))?;
+    Ok(bytes.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+/// Appends an MQTT UTF-8 string, a u16 length followed by the bytes.
+fn put_string(buf: &mut Vec<u8>, value: &[u8]) {
+    buf.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    buf.extend_from_slice(value);
+}
+
+fn take_string<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8], Error> {
+    if buf.len() < 2 {
+        bail!("truncated packet");
+    }
+    let len = u16::from_be_bytes([buf[0], buf[1]]) as usize;
+    if buf.len() < 2 + len {
+        bail!("truncated packet");
+    }
+    let value = &buf[2..2 + len];
+    *buf = &buf[2 + len..];
+    Ok(value)
+}
+
+/// MQTT 3.1.1 control packets over a plain TCP stream.
+struct MqttConnection {
+    stream: TcpStream,
+}
+
+impl MqttConnection {
+    async fn write_packet(&mut self, header: u8, body: &[u8]) -> Result<(), Error> {
+        let mut packet = vec![header];
+        // remaining length, seven bits per byte
+        let mut len = body.len();
+        loop {
+            let mut byte = (len % 128) as u8;
+            len /= 128;
+            if len > 0 {
+                byte |= 0x80;
+            }
+            packet.push(byte);
+            if len == 0 {
+                break;
+            }
+        }
+        packet.extend_from_slice(body);
+        self.stream.write_all(&packet).await?;
+        Ok(())
+    }
+
+    async fn read_packet(&mut self) -> Result<(u8, Vec<u8>), Error> {
+        let header = self.stream.read_u8().await?;
+        let mut len = 0usize;
+        for shift in [0, 7, 14, 21] {
+            let byte = self.stream.read_u8().await?;
+            len |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        if len > MAX_PACKET_BYTES {
+            bail!("packet of {len} bytes is too large");
+        }
+        let mut body = vec![0u8; len];
+        self.stream.read_exact(&mut body).await?;
+        Ok((header, body))
+    }
+}
+
+struct MqttMonitor {
+    host: String,
+    port: u16,
+    client_id: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    round_trip_topic: Option<String>,
+    timeout: Duration,
+}
+
+impl MqttMonitor {
+    async fn connect(&self, conn: &mut MqttConnection) -> Result<(), Error> {
+        let client_id = match &self.client_id {
+            Some(id) => id.clone(),
+            None => format!("whoopsie-{}", nonce()?),
+        };
+
+        // clean session, so nothing is left behind on the broker
+        let mut flags = 0x02;
+        if self.username.is_some() {
+            flags |= 0x80;
+        }
+        if self.password.is_some() {
+            flags |= 0x40;
+        }
+
+        let mut body = vec![];
+        put_string(&mut body, b"MQTT");
+        body.push(4);
+        body.push(flags);
+        body.extend_from_slice(&KEEP_ALIVE_SECS.to_be_bytes());
+        put_string(&mut body, client_id.as_bytes());
+        if let Some(username) = &self.username {
+            put_string(&mut body, username.as_bytes());
+        }
+        if let Some(password) = &self.password {
+            put_string(&mut body, password.as_bytes());
+        }
+        conn.write_packet(CONNECT, &body).await?;
+
+        match conn.read_packet().await? {
+            (CONNACK, body) if body.len() == 2 => match body[1] {
+                0 => Ok(()),
+                1 => bail!("connection refused: unacceptable protocol version"),
+                2 => bail!("connection refused: client identifier rejected"),
+                3 => bail!("connection refused: server unavailable"),
+                4 => bail!("connection refused: bad username or password"),
+                5 => bail!("connection refused: not authorized"),
+                code => bail!("connection refused: return code {code}"),
+            },
+            (header, _) => bail!("expected CONNACK, got packet type {}", header >> 4),
+        }
+    }
+
+    /// Subscribes to `topic`, publishes a nonce to it and waits for it to be
+    /// delivered back.
+    async fn round_trip(&self, conn: &mut MqttConnection, topic: &str) -> Result<(), Error> {
+        let packet_id = 1u16;
+        let mut body = packet_id.to_be_bytes().to_vec();
+        put_string(&mut body, topic.as_bytes());
+        body.push(0);
+        conn.write_packet(SUBSCRIBE, &body).await?;
+
+        match conn.read_packet().await? {
+            (SUBACK, body) if body.len() >= 3 && body[..2] == packet_id.to_be_bytes() => {
+                if body[2] == 0x80 {
+                    bail!("subscription to '{topic}' was rejected");
+                }
+            }
+            (header, _) => bail!("expected SUBACK, got packet type {}", header >> 4),
+        }
+
+        let payload = nonce()?;
+        let mut body = vec![];
+        put_string(&mut body, topic.as_bytes());
+        body.extend_from_slice(payload.as_bytes());
+        conn.write_packet(PUBLISH, &body).await?;
+
+        loop {
+            let (header, body) = conn.read_packet().await?;
+            if header & 0xf0 != PUBLISH {
+                continue;
+            }
+            let mut rest = body.as_slice();
+            let received_topic = take_string(&mut rest)?;
+            // a packet id follows the topic for QoS 1 and 2
+            if header & 0x06 != 0 {
+                rest = rest.get(2..).ok_or_else(|| anyhow!("truncated packet"))?;
+            }
+            if received_topic == topic.as_bytes() && rest == payload.as_bytes() {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn check(&self) -> Result<serde_json::Value, Error> {
+        let started = Instant::now();
+        let stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+        let mut conn = MqttConnection { stream };
+
+        self.connect(&mut conn).await?;
+        let mut details = serde_json::json!({
+            "connect_ms": started.elapsed().as_secs_f64() * 1000.0,
+        });
+
+        if let Some(topic) = &self.round_trip_topic {
+            let started = Instant::now();
+            self.round_trip(&mut conn, topic).await?;
+            details["round_trip_ms"] = (started.elapsed().as_secs_f64() * 1000.0).into();
+        }
+
+        let _ = conn.write_packet(DISCONNECT, &[]).await;
+        Ok(details)
+    }
+}
+
+#[async_trait]
+impl MonitorTask for MqttMonitor {
+    async fn survey(&self) -> Result<MonitorStatus, Error> {
+        match tokio::time::timeout(self.timeout, self.check()).await {
+            Ok(Ok(details)) => Ok(MonitorStatus::Up {
+                checked_at: Utc::now(),
+                details: Some(details),
+            }),
+            Ok(Err(err)) => Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: err.to_string(),
+                details: None,
+            }),
+            Err(_) => Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: format!("timed out after {:?}", self.timeout),
+                details: None,
+            }),
+        }
+    }
+}
+
+#[monitor_kind(kind = "mqtt", api_version = "v1alpha1")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct V1Alpha1MqttMonitorSpec {
+    #[check(non_empty)]
+    pub host: String,
+    #[check(min = 1)]
+    pub port: Option<u16>,
+    /// Defaults to a random `whoopsie-...` id for every check.
+    pub client_id: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Topic used to publish a message and wait for it to come back.
+    #[check(with = plain_topic)]
+    pub round_trip_topic: Option<String>,
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+}
+
+fn plain_topic(topic: &str) -> Result<(), String> {
+    match topic.is_empty() || topic.contains(['+', '#']) {
+        true => Err("must be a topic name without wildcards".to_string()),
+        false => Ok(()),
+    }
+}
+
+#[async_trait]
+impl MonitorSpec for V1Alpha1MqttMonitorSpec {
+    async fn build(self, _monitor: Monitor) -> Result<TaskPtr, Error> {
+        if self.password.is_some() && self.username.is_none() {
+            bail!("spec.password: requires a username");
+        }
+
+        Ok(Arc::new(MqttMonitor {
+            host: self.host,
+            port: self.port.unwrap_or(DEFAULT_PORT),
+            client_id: self.client_id,
+            username: self.username,
+            password: self.password,
+            round_trip_topic: self.round_trip_topic,
+            timeout: self.timeout.unwrap_or(DEFAULT_TIMEOUT),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::tasks::{test_monitor, TaskBuilder};
+    use serde_json::json;
+    use std::net::SocketAddr;
+    use tokio::net::TcpListener;
+
+    /// Broker that accepts `sensor`/`secret`, delivers publishes back to the
+    /// same connection and rejects subscriptions to `forbidden/#` with a
+    /// SUBACK return code of 0x80.
+    async fn spawn_broker() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut conn = MqttConnection { stream };
+                    let mut subscriptions = vec![];
+                    while let Ok((header, body)) = conn.read_packet().await {
+                        match header & 0xf0 {
+                            CONNECT => {
+                                let accepted = body.ends_with(b"\0\x06sensor\0\x06secret");
+                                let code = if accepted { 0 } else { 4 };
+                                conn.write_packet(CONNACK, &[0, code]).await.unwrap();
+                            }
+                            0x80 => {
+                                let mut rest = &body[2..];
+                                let topic = take_string(&mut rest).unwrap().to_vec();
+                                let code = if topic.starts_with(b"forbidden/") {
+                                    0x80
+                                } else {
+                                    0
+                                };
+                                subscriptions.push(topic);
+                                conn.write_packet(SUBACK, &[body[0], body[1], code])
+                                    .await
+                                    .unwrap();
+                            }
+                            PUBLISH => {
+                                let mut rest = body.as_slice();
+                                let topic = take_string(&mut rest).unwrap();
+                                if subscriptions.iter().any(|s| s == topic) {
+                                    conn.write_packet(PUBLISH, &body).await.unwrap();
+                                }
+                            }
+                            _ => break,
+                        }
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    async fn survey(addr: SocketAddr, spec: serde_json::Value) -> MonitorStatus {
+        let mut spec = spec;
+        spec["host"] = json!("127.0.0.1");
+        spec["port"] = json!(addr.port());
+        let monitor = test_monitor("mqtt", "v1alpha1", spec);
+        let task = V1Alpha1MqttMonitorBuilder {}.build(monitor).await.unwrap();
+        task.survey().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_connect_and_round_trip() {
+        let addr = spawn_broker().await;
+
+        let status = survey(
+            addr,
+            json!({ "username": "sensor", "password": "secret", "round_trip_topic": "whoopsie/probe" }),
+        )
+        .await;
+        match status {
+            MonitorStatus::Up { details, .. } => {
+                assert!(details.unwrap()["round_trip_ms"].is_f64())
+            }
+            other => panic!("expected Up, got {other:?}"),
+        }
+
+        let rejected = survey(
+            addr,
+            json!({ "username": "sensor", "password": "secret", "round_trip_topic": "forbidden/probe" }),
+        )
+        .await;
+        match rejected {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert_eq!(
+                    error_reason,
+                    "subscription to 'forbidden/probe' was rejected"
+                )
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bad_credentials() {
+        let addr = spawn_broker().await;
+
+        match survey(addr, json!({ "username": "sensor", "password": "wrong" })).await {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert_eq!(error_reason, "connection refused: bad username or password")
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+}
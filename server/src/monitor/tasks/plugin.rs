@@ -0,0 +1,710 @@
+use crate::config::plugin_config::PluginConfig;
+use crate::monitor::tasks::{MonitorTask, TaskBuilder, TaskBuilderPtr, TaskPtr};
+use anyhow::{anyhow, bail, Error};
+use app::types::{Monitor, MonitorStatus};
+use migration::async_trait::async_trait;
+use sea_orm::sqlx::types::chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::select;
+use tokio::sync::{oneshot, Mutex};
+
+// Plugins talk JSON lines over stdin/stdout, one request or response per
+// line. Every request carries an id that's echoed back in its response and
+// responses may arrive in any order:
+//
+// > {"id":1,"method":"handshake","params":{"protocol_version":1}}
+// < {"id":1,"result":{"protocol_version":1,"kinds":[{"kind":"ftp","api_version":"v1alpha1"}]}}
+// > {"id":2,"method":"build","params":{"task_id":"ftp-main","monitor":{"name":"ftp-main",...}}}
+// < {"id":2,"result":{}}
+// > {"id":3,"method":"survey","params":{"task_id":"ftp-main"}}
+// < {"id":3,"result":{"status":"down","reason":"530 login incorrect","details":null}}
+//
+// A failed request is answered with {"id":N,"error":{"message":"..."}}.
+// Anything written to stderr ends up in the server log. After a restart
+// every task is built again before its next survey.
+const PROTOCOL_VERSION: u32 = 1;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(300);
+/// A plugin that ran at least this long before exiting restarts without
+/// backoff from earlier crashes.
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/// Starts the plugin and returns a builder for every kind it advertises.
+pub async fn get_builders(config: &PluginConfig) -> Result<Vec<TaskBuilderPtr>, Error> {
+    let plugin = Plugin::new(config);
+    let process = plugin.process().await?;
+
+    Ok(plugin.builders(&process))
+}
+
+/// Keeps restarting a plugin that failed to start, with the backoff of a
+/// crashed one, and returns its builders once it's up.
+pub async fn retry_builders(config: &PluginConfig) -> Vec<TaskBuilderPtr> {
+    let plugin = Plugin::new(config);
+    {
+        let mut state = plugin.state.lock().expect("plugin state poisoned");
+        state.failures = 1;
+        state.restart_at = Some(Instant::now() + plugin.restart_delay(1));
+    }
+
+    loop {
+        let restart_at = plugin
+            .state
+            .lock()
+            .expect("plugin state poisoned")
+            .restart_at;
+        if let Some(restart_at) = restart_at {
+            tokio::time::sleep_until(restart_at.into()).await;
+        }
+        match plugin.process().await {
+            Ok(process) => return plugin.builders(&process),
+            Err(e) => log::error!("error restarting plugin {}: {e:#}", config.name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct PluginKind {
+    kind: String,
+    api_version: String,
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    id: u64,
+    method: &'a str,
+    params: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    id: u64,
+    #[serde(default)]
+    result: Option<serde_json::Value>,
+    #[serde(default)]
+    error: Option<ResponseError>,
+}
+
+#[derive(Deserialize)]
+struct ResponseError {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct Handshake {
+    protocol_version: u32,
+    kinds: Vec<PluginKind>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SurveyStatus {
+    Up,
+    Degraded,
+    Down,
+}
+
+/// Result of a survey as reported by a plugin, shared with the wasm host.
+#[derive(Deserialize)]
+pub(super) struct SurveyResult {
+    status: SurveyStatus,
+    #[serde(default)]
+    reason: Option<String>,
+    #[serde(default)]
+    details: Option<serde_json::Value>,
+}
+
+impl SurveyResult {
+    pub(super) fn into_status(self) -> MonitorStatus {
+        let checked_at = Utc::now();
+        let error_reason = self.reason.unwrap_or_default();
+        match self.status {
+            SurveyStatus::Up => MonitorStatus::Up {
+                checked_at,
+                details: self.details,
+            },
+            SurveyStatus::Degraded => MonitorStatus::Degraded {
+                checked_at,
+                error_reason,
+                details: self.details,
+            },
+            SurveyStatus::Down => MonitorStatus::Down {
+                checked_at,
+                error_reason,
+                details: self.details,
+            },
+        }
+    }
+}
+
+type PendingRequests =
+    Arc<std::sync::Mutex<HashMap<u64, oneshot::Sender<Result<serde_json::Value, String>>>>>;
+
+/// One run of the plugin executable.
+struct PluginProcess {
+    name: String,
+    generation: u64,
+    started: Instant,
+    kinds: Vec<PluginKind>,
+    stdin: Mutex<ChildStdin>,
+    pending: PendingRequests,
+    next_id: AtomicU64,
+    exited: Arc<OnceLock<Instant>>,
+    // dropping the sender kills the process
+    kill: std::sync::Mutex<Option<oneshot::Sender<()>>>,
+}
+
+impl PluginProcess {
+    async fn spawn(config: &PluginConfig, generation: u64) -> Result<PluginProcess, Error> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .envs(&config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| anyhow!("can't start {}: {e}", config.command.display()))?;
+
+        let (Some(stdin), Some(stdout), Some(stderr)) =
+            (child.stdin.take(), child.stdout.take(), child.stderr.take())
+        else {
+            bail!("plugin {} has no stdio pipes", config.name);
+        };
+
+        let name = config.name.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                log::warn!("plugin {name}: {line}");
+            }
+        });
+
+        let pending = PendingRequests::default();
+        let exited = Arc::new(OnceLock::new());
+        let (kill, killed) = oneshot::channel();
+        tokio::spawn(read_responses(
+            config.name.clone(),
+            child,
+            stdout,
+            pending.clone(),
+            exited.clone(),
+            killed,
+        ));
+
+        let mut process = PluginProcess {
+            name: config.name.clone(),
+            generation,
+            started: Instant::now(),
+            kinds: vec![],
+            stdin: Mutex::new(stdin),
+            pending,
+            next_id: AtomicU64::new(1),
+            exited,
+            kill: std::sync::Mutex::new(Some(kill)),
+        };
+
+        let params = serde_json::json!({ "protocol_version": PROTOCOL_VERSION });
+        let timeout = config.timeout.unwrap_or(DEFAULT_TIMEOUT);
+        let handshake = process.request("handshake", params, timeout).await?;
+        let handshake = serde_json::from_value::<Handshake>(handshake)
+            .map_err(|e| anyhow!("plugin {} sent an invalid handshake: {e}", config.name))?;
+        if handshake.protocol_version != PROTOCOL_VERSION {
+            bail!(
+                "plugin {} speaks protocol version {}, expected {PROTOCOL_VERSION}",
+                config.name,
+                handshake.protocol_version
+            );
+        }
+        process.kinds = handshake.kinds;
+
+        Ok(process)
+    }
+
+    fn has_exited(&self) -> bool {
+        self.exited.get().is_some()
+    }
+
+    async fn request(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+        timeout: Duration,
+    ) -> Result<serde_json::Value, Error> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+        // the reader marks the process as exited before failing what's pending
+        if self.has_exited() {
+            self.pending.lock().unwrap().remove(&id);
+            bail!("plugin {} isn't running", self.name);
+        }
+
+        let mut line = serde_json::to_vec(&Request { id, method, params })?;
+        line.push(b'\n');
+        let written = async {
+            let mut stdin = self.stdin.lock().await;
+            stdin.write_all(&line).await?;
+            stdin.flush().await
+        };
+        if let Err(e) = written.await {
+            self.pending.lock().unwrap().remove(&id);
+            bail!("plugin {} isn't reading requests: {e}", self.name);
+        }
+
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(Ok(result))) => Ok(result),
+            Ok(Ok(Err(message))) => Err(anyhow!(message)),
+            Ok(Err(_)) => bail!("plugin {} dropped the request", self.name),
+            Err(_) => {
+                self.pending.lock().unwrap().remove(&id);
+                // a hung plugin is restarted like a crashed one
+                self.kill.lock().unwrap().take();
+                bail!(
+                    "plugin {} didn't answer {method} within {timeout:?}",
+                    self.name
+                )
+            }
+        }
+    }
+}
+
+async fn read_responses(
+    name: String,
+    mut child: Child,
+    stdout: ChildStdout,
+    pending: PendingRequests,
+    exited: Arc<OnceLock<Instant>>,
+    mut killed: oneshot::Receiver<()>,
+) {
+    let mut lines = BufReader::new(stdout).lines();
+    loop {
+        let line = select! {
+            line = lines.next_line() => line,
+            _ = &mut killed => break,
+        };
+        let Ok(Some(line)) = line else {
+            break;
+        };
+
+        let response = match serde_json::from_str::<Response>(&line) {
+            Ok(response) => response,
+            Err(e) => {
+                log::warn!("plugin {name} wrote an invalid response: {e}");
+                continue;
+            }
+        };
+        if let Some(tx) = pending.lock().unwrap().remove(&response.id) {
+            let _ = tx.send(match response.error {
+                Some(error) => Err(error.message),
+                None => Ok(response.result.unwrap_or_default()),
+            });
+        }
+    }
+
+    let _ = child.start_kill();
+    let reason = match child.wait().await {
+        Ok(status) => format!("plugin {name} exited: {status}"),
+        Err(e) => format!("plugin {name} exited: {e}"),
+    };
+    log::warn!("{reason}");
+
+    let _ = exited.set(Instant::now());
+    for (_, tx) in pending.lock().unwrap().drain() {
+        let _ = tx.send(Err(reason.clone()));
+    }
+}
+
+#[derive(Default)]
+struct PluginState {
+    process: Option<Arc<PluginProcess>>,
+    generation: u64,
+    /// A task is starting the process, outside the lock.
+    starting: bool,
+    failures: u32,
+    restart_at: Option<Instant>,
+}
+
+/// Lets the next task start the plugin if this start is abandoned midway.
+struct Starting<'a> {
+    plugin: &'a Plugin,
+    generation: u64,
+}
+
+impl Drop for Starting<'_> {
+    fn drop(&mut self) {
+        let mut state = self.plugin.state.lock().expect("plugin state poisoned");
+        if state.generation == self.generation {
+            state.starting = false;
+        }
+    }
+}
+
+struct Plugin {
+    config: PluginConfig,
+    state: std::sync::Mutex<PluginState>,
+}
+
+impl Debug for Plugin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Plugin")
+            .field("name", &self.config.name)
+            .field("command", &self.config.command)
+            .finish()
+    }
+}
+
+impl Plugin {
+    fn new(config: &PluginConfig) -> Arc<Plugin> {
+        Arc::new(Plugin {
+            config: config.clone(),
+            state: std::sync::Mutex::new(PluginState::default()),
+        })
+    }
+
+    fn builders(self: &Arc<Self>, process: &PluginProcess) -> Vec<TaskBuilderPtr> {
+        process
+            .kinds
+            .iter()
+            .map(|kind| {
+                Arc::new(PluginTaskBuilder {
+                    plugin: self.clone(),
+                    kind: kind.clone(),
+                }) as TaskBuilderPtr
+            })
+            .collect()
+    }
+
+    fn timeout(&self) -> Duration {
+        self.config.timeout.unwrap_or(DEFAULT_TIMEOUT)
+    }
+
+    fn restart_delay(&self, failures: u32) -> Duration {
+        let delay = self.config.restart_delay.unwrap_or(DEFAULT_RESTART_DELAY);
+        delay
+            .saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+            .min(MAX_RESTART_DELAY)
+    }
+
+    /// Returns the running process, restarting it once the backoff after a
+    /// crash has passed. Other tasks fail fast rather than wait while one of
+    /// them starts the process.
+    async fn process(&self) -> Result<Arc<PluginProcess>, Error> {
+        let generation = {
+            let mut state = self.state.lock().expect("plugin state poisoned");
+            if let Some(process) = &state.process {
+                match process.exited.get() {
+                    None => return Ok(process.clone()),
+                    Some(exited_at) => {
+                        let exited_at = *exited_at;
+                        let stable = exited_at - process.started >= STABLE_AFTER;
+                        state.failures = if stable { 1 } else { state.failures + 1 };
+                        state.restart_at = Some(exited_at + self.restart_delay(state.failures));
+                        state.process = None;
+                    }
+                }
+            }
+
+            if state.starting {
+                bail!("plugin {} is starting", self.config.name);
+            }
+            if let Some(restart_at) = state.restart_at {
+                let now = Instant::now();
+                if now < restart_at {
+                    bail!(
+                        "plugin {} crashed, restarting in {:?}",
+                        self.config.name,
+                        restart_at - now
+                    );
+                }
+            }
+
+            state.starting = true;
+            state.generation += 1;
+            state.generation
+        };
+
+        let _starting = Starting {
+            plugin: self,
+            generation,
+        };
+        let spawned = PluginProcess::spawn(&self.config, generation).await;
+        let mut state = self.state.lock().expect("plugin state poisoned");
+        match spawned {
+            Ok(process) => {
+                let process = Arc::new(process);
+                state.process = Some(process.clone());
+                state.restart_at = None;
+                Ok(process)
+            }
+            Err(e) => {
+                state.failures += 1;
+                state.restart_at = Some(Instant::now() + self.restart_delay(state.failures));
+                Err(e)
+            }
+        }
+    }
+}
+
+struct PluginTask {
+    plugin: Arc<Plugin>,
+    monitor: Monitor,
+    // generation of the process that last built this task
+    built_for: Mutex<u64>,
+}
+
+impl PluginTask {
+    async fn ensure_built(&self) -> Result<Arc<PluginProcess>, Error> {
+        let process = self.plugin.process().await?;
+        let mut built_for = self.built_for.lock().await;
+        if *built_for != process.generation {
+            let params = serde_json::json!({
+                "task_id": self.monitor.name,
+                "monitor": {
+                    "name": self.monitor.name,
+                    "kind": self.monitor.kind,
+                    "api_version": self.monitor.api_version,
+                    "labels": self.monitor.labels,
+                    "spec": self.monitor.spec,
+                },
+            });
+            process
+                .request("build", params, self.plugin.timeout())
+                .await?;
+            *built_for = process.generation;
+        }
+        Ok(process)
+    }
+
+    async fn check(&self) -> Result<MonitorStatus, Error> {
+        let process = self.ensure_built().await?;
+        let params = serde_json::json!({ "task_id": self.monitor.name });
+        let result = process
+            .request("survey", params, self.plugin.timeout())
+            .await?;
+        let result = serde_json::from_value::<SurveyResult>(result)
+            .map_err(|e| anyhow!("plugin {} sent an invalid survey result: {e}", process.name))?;
+
+        Ok(result.into_status())
+    }
+}
+
+#[async_trait]
+impl MonitorTask for PluginTask {
+    async fn survey(&self) -> Result<MonitorStatus, Error> {
+        match self.check().await {
+            Ok(status) => Ok(status),
+            Err(err) => Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: err.to_string(),
+                details: None,
+            }),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PluginTaskBuilder {
+    plugin: Arc<Plugin>,
+    kind: PluginKind,
+}
+
+#[async_trait]
+impl TaskBuilder for PluginTaskBuilder {
+    fn get_api_version(&self) -> String {
+        self.kind.api_version.clone()
+    }
+
+    fn get_kind(&self) -> String {
+        self.kind.kind.clone()
+    }
+
+    async fn build(&self, monitor: Monitor) -> Result<TaskPtr, Error> {
+        let task = PluginTask {
+            plugin: self.plugin.clone(),
+            monitor,
+            built_for: Mutex::new(0),
+        };
+        // the plugin validates the spec
+        task.ensure_built().await?;
+
+        Ok(Arc::new(task))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::plugin_config::PluginConfigs;
+    use crate::monitor::tasks::{spec, test_monitor, TaskFactory};
+    use serde_json::json;
+    use std::collections::BTreeMap;
+    use tempfile::TempDir;
+
+    const ECHO_PLUGIN: &str = r#"
+echo "echo plugin starting" >&2
+built=""
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed 's/^{"id":\([0-9]*\).*/\1/')
+  task=$(printf '%s' "$line" | sed -n 's/.*"task_id":"\([^"]*\)".*/\1/p')
+  case "$line" in
+    *'"method":"handshake"'*)
+      echo "{\"id\":$id,\"result\":{\"protocol_version\":1,\"kinds\":[{\"kind\":\"echo\",\"api_version\":\"v1alpha1\"}]}}" ;;
+    *'"method":"build"'*'"spec":{}'*)
+      echo "{\"id\":$id,\"error\":{\"message\":\"spec.message is required\"}}" ;;
+    *'"method":"build"'*)
+      built="$built $task"
+      echo "{\"id\":$id,\"result\":{}}" ;;
+    *'"method":"survey"'*)
+      case "$task" in crash) exit 3 ;; esac
+      case " $built " in
+        *" $task "*) echo "{\"id\":$id,\"result\":{\"status\":\"up\",\"details\":{\"pid\":$$,\"greeting\":\"$GREETING\"}}}" ;;
+        *) echo "{\"id\":$id,\"error\":{\"message\":\"unknown task $task\"}}" ;;
+      esac ;;
+  esac
+done
+"#;
+
+    fn plugin_config(dir: &TempDir, script: &str) -> PluginConfig {
+        let path = dir.path().join("plugin.sh");
+        std::fs::write(&path, script).unwrap();
+        PluginConfig {
+            name: "echo".to_string(),
+            command: "/bin/sh".into(),
+            args: vec![path.to_string_lossy().into_owned()],
+            env: BTreeMap::from([("GREETING".to_string(), "hello".to_string())]),
+            timeout: Some(Duration::from_millis(500)),
+            restart_delay: Some(Duration::from_millis(10)),
+        }
+    }
+
+    fn pid(status: MonitorStatus) -> u64 {
+        match status {
+            MonitorStatus::Up { details, .. } => {
+                let details = details.unwrap();
+                assert_eq!(details["greeting"], "hello");
+                details["pid"].as_u64().unwrap()
+            }
+            other => panic!("expected Up, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_survey_and_restart() {
+        let dir = TempDir::new().unwrap();
+        let builders = get_builders(&plugin_config(&dir, ECHO_PLUGIN))
+            .await
+            .unwrap();
+        assert_eq!(builders.len(), 1);
+        let builder = &builders[0];
+        assert_eq!(builder.get_kind(), "echo");
+        assert_eq!(builder.get_api_version(), "v1alpha1");
+
+        let invalid = builder
+            .build(test_monitor("echo", "v1alpha1", json!({})))
+            .await;
+        assert_eq!(
+            invalid.err().unwrap().to_string(),
+            "spec.message is required"
+        );
+
+        let task = builder
+            .build(test_monitor("echo", "v1alpha1", json!({ "message": "hi" })))
+            .await
+            .unwrap();
+        let first_pid = pid(task.survey().await.unwrap());
+
+        let mut crasher = test_monitor("echo", "v1alpha1", json!({ "message": "bye" }));
+        crasher.name = "crash".to_string();
+        let crasher = builder.build(crasher).await.unwrap();
+        match crasher.survey().await.unwrap() {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert_eq!(error_reason, "plugin echo exited: exit status: 3")
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+
+        // the task is built again in the restarted process
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_ne!(pid(task.survey().await.unwrap()), first_pid);
+    }
+
+    #[tokio::test]
+    async fn test_retry_failed_start() {
+        let dir = TempDir::new().unwrap();
+        let marker = dir.path().join("started-once");
+        let script = format!(
+            "[ -e {0} ] || {{ touch {0}; exit 1; }}\n{ECHO_PLUGIN}",
+            marker.display()
+        );
+        let config = plugin_config(&dir, &script);
+        assert!(get_builders(&config).await.is_err());
+
+        let builders = retry_builders(&config).await;
+        assert_eq!(builders.len(), 1);
+        let task = builders[0]
+            .build(test_monitor("echo", "v1alpha1", json!({ "message": "hi" })))
+            .await
+            .unwrap();
+        pid(task.survey().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_builtin_kinds_are_not_replaced() {
+        let dir = TempDir::new().unwrap();
+        let script = ECHO_PLUGIN.replace(r#"\"kind\":\"echo\""#, r#"\"kind\":\"endpoint\""#);
+        let factory = Arc::new(TaskFactory::new());
+        factory
+            .bulk_register(spec::registered_builders(&spec::test_build_context().await))
+            .await;
+        let plugins = PluginConfigs {
+            executables: vec![plugin_config(&dir, &script)],
+            wasm: vec![],
+        };
+        factory.register_plugin_builders(&plugins).await;
+
+        // the plugin would accept this spec, the built-in endpoint doesn't
+        let monitor = test_monitor("endpoint", "v1alpha1", json!({ "message": "hi" }));
+        let error = factory.construct_task(&monitor).await.err().unwrap();
+        assert!(error.to_string().contains("uri"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_tasks_dont_wait_on_a_start() {
+        let dir = TempDir::new().unwrap();
+        let plugin = Plugin::new(&plugin_config(&dir, "sleep 10"));
+        let starting = tokio::spawn({
+            let plugin = plugin.clone();
+            async move { plugin.process().await.map(|_| ()) }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let started = Instant::now();
+        assert_eq!(
+            plugin.process().await.err().unwrap().to_string(),
+            "plugin echo is starting"
+        );
+        assert!(started.elapsed() < Duration::from_millis(100));
+        assert!(starting.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unresponsive_plugin() {
+        let dir = TempDir::new().unwrap();
+        let result = get_builders(&plugin_config(&dir, "sleep 10")).await;
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "plugin echo didn't answer handshake within 500ms"
+        );
+    }
+}
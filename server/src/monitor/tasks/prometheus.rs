@@ -0,0 +1,375 @@
+use crate::monitor::tasks::spec::MonitorSpec;
+use crate::monitor::tasks::{MonitorTask, TaskPtr};
+use anyhow::{anyhow, bail, Error};
+use app::types::{Monitor, MonitorStatus};
+use macros::monitor_kind;
+use migration::async_trait::async_trait;
+use sea_orm::sqlx::types::chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq)]
+struct Sample {
+    name: String,
+    labels: BTreeMap<String, String>,
+    value: f64,
+}
+
+impl std::fmt::Display for Sample {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let labels = self
+            .labels
+            .iter()
+            .map(|(k, v)| format!("{k}=\"{v}\""))
+            .collect::<Vec<_>>()
+            .join(",");
+        write!(f, "{}{{{labels}}}", self.name)
+    }
+}
+
+/// Parses the Prometheus text exposition format, ignoring comments and
+/// sample timestamps.
+fn parse_exposition(text: &str) -> Result<Vec<Sample>, Error> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| parse_sample(line).map_err(|e| anyhow!("invalid sample '{line}': {e}")))
+        .collect()
+}
+
+fn parse_sample(line: &str) -> Result<Sample, Error> {
+    let name_end = line
+        .find(|c: char| c == '{' || c.is_whitespace())
+        .ok_or_else(|| anyhow!("missing value"))?;
+    let name = line[..name_end].to_string();
+    let mut rest = &line[name_end..];
+
+    let mut labels = BTreeMap::new();
+    if let Some(stripped) = rest.strip_prefix('{') {
+        rest = stripped;
+        loop {
+            rest = rest.trim_start_matches([' ', ',']);
+            if let Some(stripped) = rest.strip_prefix('}') {
+                rest = stripped;
+                break;
+            }
+
+            let eq = rest.find('=').ok_or_else(|| anyhow!("malformed label"))?;
+            let key = rest[..eq].trim().to_string();
+            rest = rest[eq + 1..]
+                .trim_start()
+                .strip_prefix('"')
+                .ok_or_else(|| anyhow!("label value must be quoted"))?;
+
+            let mut value = String::new();
+            let mut chars = rest.char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((i, '"')) => break i,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, 'n')) => value.push('\n'),
+                        Some((_, c)) => value.push(c),
+                        None => bail!("unterminated label value"),
+                    },
+                    Some((_, c)) => value.push(c),
+                    None => bail!("unterminated label value"),
+                }
+            };
+            rest = &rest[end + 1..];
+            labels.insert(key, value);
+        }
+    }
+
+    let value = rest
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| anyhow!("missing value"))?;
+    let value = match value {
+        "+Inf" => f64::INFINITY,
+        "-Inf" => f64::NEG_INFINITY,
+        "NaN" => f64::NAN,
+        v => v.parse()?,
+    };
+
+    Ok(Sample {
+        name,
+        labels,
+        value,
+    })
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct Thresholds {
+    pub degraded_above: Option<f64>,
+    pub degraded_below: Option<f64>,
+    pub down_above: Option<f64>,
+    pub down_below: Option<f64>,
+}
+
+impl Thresholds {
+    fn is_empty(&self) -> bool {
+        self == &Thresholds::default()
+    }
+
+    fn breach(above: Option<f64>, below: Option<f64>, value: f64) -> Option<String> {
+        match (above, below) {
+            (Some(limit), _) if value > limit => Some(format!("above {limit}")),
+            (_, Some(limit)) if value < limit => Some(format!("below {limit}")),
+            _ => None,
+        }
+    }
+}
+
+struct PrometheusMonitor {
+    client: reqwest::Client,
+    uri: String,
+    metric: String,
+    labels: BTreeMap<String, String>,
+    thresholds: Thresholds,
+}
+
+impl PrometheusMonitor {
+    async fn select(&self) -> Result<Sample, Error> {
+        let body = self
+            .client
+            .get(&self.uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        let mut matching = parse_exposition(&body)?
+            .into_iter()
+            .filter(|s| {
+                s.name == self.metric
+                    && self
+                        .labels
+                        .iter()
+                        .all(|(k, v)| s.labels.get(k).is_some_and(|l| l == v))
+            })
+            .collect::<Vec<_>>();
+
+        match matching.len() {
+            0 => bail!("no series matched {}", self.metric),
+            1 => Ok(matching.remove(0)),
+            n => bail!(
+                "{n} series matched {}, add label matchers to select one",
+                self.metric
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl MonitorTask for PrometheusMonitor {
+    async fn survey(&self) -> Result<MonitorStatus, Error> {
+        let sample = match self.select().await {
+            Ok(sample) => sample,
+            Err(err) => {
+                return Ok(MonitorStatus::Down {
+                    checked_at: Utc::now(),
+                    error_reason: err.to_string(),
+                    details: None,
+                })
+            }
+        };
+
+        let details = Some(serde_json::json!({
+            "series": sample.to_string(),
+            // NaN and infinities aren't valid json numbers
+            "value": if sample.value.is_finite() {
+                serde_json::json!(sample.value)
+            } else {
+                serde_json::json!(sample.value.to_string())
+            },
+        }));
+
+        let t = &self.thresholds;
+        if sample.value.is_nan() {
+            return Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: format!("{sample} is NaN"),
+                details,
+            });
+        }
+        if let Some(breach) = Thresholds::breach(t.down_above, t.down_below, sample.value) {
+            return Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: format!("{sample} = {} is {breach}", sample.value),
+                details,
+            });
+        }
+        if let Some(breach) = Thresholds::breach(t.degraded_above, t.degraded_below, sample.value) {
+            return Ok(MonitorStatus::Degraded {
+                checked_at: Utc::now(),
+                error_reason: format!("{sample} = {} is {breach}", sample.value),
+                details,
+            });
+        }
+
+        Ok(MonitorStatus::Up {
+            checked_at: Utc::now(),
+            details,
+        })
+    }
+}
+
+#[monitor_kind(kind = "prometheus", api_version = "v1alpha1")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct V1Alpha1PrometheusMonitorSpec {
+    #[check(non_empty)]
+    pub uri: String,
+    #[check(non_empty)]
+    pub metric: String,
+    /// Exact label values the series must carry.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    #[check(non_empty)]
+    pub thresholds: Thresholds,
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+}
+
+#[async_trait]
+impl MonitorSpec for V1Alpha1PrometheusMonitorSpec {
+    async fn build(self, _monitor: Monitor) -> Result<TaskPtr, Error> {
+        let client = reqwest::Client::builder()
+            .timeout(self.timeout.unwrap_or(DEFAULT_TIMEOUT))
+            .build()?;
+
+        Ok(Arc::new(PrometheusMonitor {
+            client,
+            uri: self.uri,
+            metric: self.metric,
+            labels: self.labels,
+            thresholds: self.thresholds,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::tasks::{test_monitor, TaskBuilder};
+    use axum::routing::get;
+    use axum::Router;
+    use serde_json::json;
+    use std::net::SocketAddr;
+    use tokio::net::TcpListener;
+
+    const FIXTURE: &str = r#"
+# HELP http_requests_total Total requests.
+# TYPE http_requests_total counter
+http_requests_total{method="get",code="200"} 1027 1395066363000
+http_requests_total{method="post",code="500"} 3
+# HELP queue_depth Jobs waiting.
+# TYPE queue_depth gauge
+queue_depth{queue="emails"} 12
+queue_depth{queue="reports",note="a \"quoted\", value"} 250
+replication_lag_seconds +Inf
+"#;
+
+    async fn spawn_metrics_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/metrics", get(|| async { FIXTURE }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    async fn survey(addr: SocketAddr, spec: serde_json::Value) -> MonitorStatus {
+        let mut spec = spec;
+        spec["uri"] = json!(format!("http://{addr}/metrics"));
+        let monitor = test_monitor("prometheus", "v1alpha1", spec);
+        let task = V1Alpha1PrometheusMonitorBuilder {}
+            .build(monitor)
+            .await
+            .unwrap();
+        task.survey().await.unwrap()
+    }
+
+    #[test]
+    fn test_parse_exposition() {
+        let samples = parse_exposition(FIXTURE).unwrap();
+
+        assert_eq!(samples.len(), 5);
+        assert_eq!(samples[0].value, 1027.0);
+        assert_eq!(samples[3].labels["note"], "a \"quoted\", value");
+        assert_eq!(samples[3].value, 250.0);
+        assert!(samples[4].labels.is_empty());
+        assert_eq!(samples[4].value, f64::INFINITY);
+        assert!(parse_exposition("broken{label=unquoted} 1").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_thresholds() {
+        let addr = spawn_metrics_server().await;
+        let thresholds = json!({ "degraded_above": 100, "down_above": 1000 });
+
+        let up = survey(
+            addr,
+            json!({ "metric": "queue_depth", "labels": { "queue": "emails" }, "thresholds": thresholds }),
+        )
+        .await;
+        match up {
+            MonitorStatus::Up { details, .. } => assert_eq!(details.unwrap()["value"], 12.0),
+            other => panic!("expected Up, got {other:?}"),
+        }
+
+        let degraded = survey(
+            addr,
+            json!({ "metric": "queue_depth", "labels": { "queue": "reports" }, "thresholds": thresholds }),
+        )
+        .await;
+        match degraded {
+            MonitorStatus::Degraded { error_reason, .. } => {
+                assert!(
+                    error_reason.ends_with("= 250 is above 100"),
+                    "{error_reason}"
+                )
+            }
+            other => panic!("expected Degraded, got {other:?}"),
+        }
+
+        let down = survey(
+            addr,
+            json!({ "metric": "replication_lag_seconds", "thresholds": { "down_above": 30 } }),
+        )
+        .await;
+        assert!(matches!(down, MonitorStatus::Down { .. }), "{down:?}");
+    }
+
+    #[tokio::test]
+    async fn test_series_selection_errors() {
+        let addr = spawn_metrics_server().await;
+
+        let ambiguous = survey(
+            addr,
+            json!({ "metric": "queue_depth", "thresholds": { "down_above": 1 } }),
+        )
+        .await;
+        match ambiguous {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert!(error_reason.starts_with("2 series matched"))
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+
+        let missing = survey(
+            addr,
+            json!({ "metric": "does_not_exist", "thresholds": { "down_above": 1 } }),
+        )
+        .await;
+        match missing {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert!(error_reason.starts_with("no series matched"))
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,484 @@
+use crate::monitor::tasks::spec::MonitorSpec;
+use crate::monitor::tasks::{MonitorTask, TaskPtr};
+use anyhow::{anyhow, bail, Error};
+use app::types::{Monitor, MonitorStatus};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use macros::monitor_kind;
+use migration::async_trait::async_trait;
+use sea_orm::sqlx::types::chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+const DEFAULT_PORT: u16 = 6379;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_LINE_BYTES: u64 = 64 * 1024;
+const MAX_REPLY_BYTES: usize = 16 * 1024 * 1024;
+const MAX_ARRAY_LEN: usize = 64 * 1024;
+
+struct RedisMonitor {
+    address: String,
+    username: Option<String>,
+    password: Option<String>,
+    database: Option<u32>,
+    key: Option<String>,
+    expected_value: Option<String>,
+    timeout: Duration,
+}
+
+impl RedisMonitor {
+    /// Runs the full check and returns the PING round-trip latency.
+    async fn check(&self) -> Result<Duration, Error> {
+        let mut conn = RespConnection::connect(&self.address).await?;
+
+        if let Some(password) = &self.password {
+            let reply = match &self.username {
+                Some(username) => conn.command(&["AUTH", username, password]).await?,
+                None => conn.command(&["AUTH", password]).await?,
+            };
+            reply.expect_ok("AUTH")?;
+        }
+
+        if let Some(database) = self.database {
+            conn.command(&["SELECT", &database.to_string()])
+                .await?
+                .expect_ok("SELECT")?;
+        }
+
+        let ping_start = Instant::now();
+        let reply = conn.command(&["PING"]).await?;
+        let latency = ping_start.elapsed();
+
+        match reply {
+            RespValue::SimpleString(s) if s == "PONG" => {}
+            RespValue::Error(e) => bail!("PING failed: {e}"),
+            other => bail!("unexpected reply to PING: {other:?}"),
+        }
+
+        if let Some(key) = &self.key {
+            self.check_key(&mut conn, key).await?;
+        }
+
+        Ok(latency)
+    }
+
+    async fn check_key(&self, conn: &mut RespConnection, key: &str) -> Result<(), Error> {
+        let value = match conn.command(&["GET", key]).await? {
+            RespValue::BulkString(Some(value)) => String::from_utf8_lossy(&value).into_owned(),
+            RespValue::BulkString(None) => bail!("key '{key}' does not exist"),
+            RespValue::Error(e) => bail!("GET {key} failed: {e}"),
+            other => bail!("unexpected reply to GET {key}: {other:?}"),
+        };
+
+        if let Some(expected) = &self.expected_value {
+            if &value != expected {
+                bail!("key '{key}' has value '{value}', expected '{expected}'");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MonitorTask for RedisMonitor {
+    async fn survey(&self) -> Result<MonitorStatus, Error> {
+        let result = tokio::time::timeout(self.timeout, self.check()).await;
+
+        match result {
+            Ok(Ok(latency)) => Ok(MonitorStatus::Up {
+                checked_at: Utc::now(),
+                details: Some(serde_json::json!({
+                    "latency_ms": latency.as_secs_f64() * 1000.0,
+                })),
+            }),
+            Ok(Err(err)) => Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: err.to_string(),
+                details: None,
+            }),
+            Err(_) => Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: format!("timed out after {:?}", self.timeout),
+                details: None,
+            }),
+        }
+    }
+}
+
+/// A single RESP2 reply.
+#[derive(Debug, Clone, PartialEq)]
+enum RespValue {
+    SimpleString(String),
+    Error(String),
+    Integer(i64),
+    BulkString(Option<Vec<u8>>),
+    Array(Option<Vec<RespValue>>),
+}
+
+impl RespValue {
+    fn expect_ok(self, command: &str) -> Result<(), Error> {
+        match self {
+            RespValue::SimpleString(s) if s == "OK" => Ok(()),
+            RespValue::Error(e) => Err(anyhow!("{command} failed: {e}")),
+            other => Err(anyhow!("unexpected reply to {command}: {other:?}")),
+        }
+    }
+}
+
+/// Minimal RESP client, just enough to issue commands and decode their replies.
+struct RespConnection {
+    stream: BufReader<TcpStream>,
+}
+
+impl RespConnection {
+    async fn connect(address: &str) -> Result<Self, Error> {
+        let stream = TcpStream::connect(address).await?;
+        Ok(Self::from_stream(stream))
+    }
+
+    fn from_stream(stream: TcpStream) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+        }
+    }
+
+    async fn command(&mut self, args: &[&str]) -> Result<RespValue, Error> {
+        let mut buf = format!("*{}\r\n", args.len()).into_bytes();
+        for arg in args {
+            buf.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            buf.extend_from_slice(arg.as_bytes());
+            buf.extend_from_slice(b"\r\n");
+        }
+
+        self.write_raw(&buf).await?;
+        self.read_value().await
+    }
+
+    async fn write_raw(&mut self, buf: &[u8]) -> Result<(), Error> {
+        let stream = self.stream.get_mut();
+        stream.write_all(buf).await?;
+        stream.flush().await?;
+        Ok(())
+    }
+
+    fn read_value(&mut self) -> BoxFuture<'_, Result<RespValue, Error>> {
+        async move {
+            let line = self.read_line().await?;
+            let (prefix, rest) = match line.as_bytes().first() {
+                Some(&b @ (b'+' | b'-' | b':' | b'$' | b'*')) => (b, &line[1..]),
+                _ => bail!("invalid RESP reply: {line}"),
+            };
+
+            match prefix {
+                b'+' => Ok(RespValue::SimpleString(rest.to_string())),
+                b'-' => Ok(RespValue::Error(rest.to_string())),
+                b':' => Ok(RespValue::Integer(rest.parse()?)),
+                b'$' => {
+                    let Some(len) = Self::parse_length(rest, MAX_REPLY_BYTES)? else {
+                        return Ok(RespValue::BulkString(None));
+                    };
+
+                    let mut data = vec![0u8; len + 2];
+                    self.stream.read_exact(&mut data).await?;
+                    data.truncate(len);
+                    Ok(RespValue::BulkString(Some(data)))
+                }
+                b'*' => {
+                    let Some(len) = Self::parse_length(rest, MAX_ARRAY_LEN)? else {
+                        return Ok(RespValue::Array(None));
+                    };
+
+                    let mut items = Vec::with_capacity(len);
+                    for _ in 0..len {
+                        items.push(self.read_value().await?);
+                    }
+                    Ok(RespValue::Array(Some(items)))
+                }
+                _ => unreachable!(),
+            }
+        }
+        .boxed()
+    }
+
+    /// Parses a bulk string or array length, `None` meaning a null reply.
+    fn parse_length(raw: &str, max: usize) -> Result<Option<usize>, Error> {
+        let len: i64 = raw.parse()?;
+        if len < 0 {
+            return Ok(None);
+        }
+        if len as u64 > max as u64 {
+            bail!("reply length {len} is too large");
+        }
+        Ok(Some(len as usize))
+    }
+
+    async fn read_line(&mut self) -> Result<String, Error> {
+        let mut line = String::new();
+        let read = (&mut self.stream)
+            .take(MAX_LINE_BYTES)
+            .read_line(&mut line)
+            .await?;
+        if read == 0 {
+            bail!("connection closed by server");
+        }
+        if !line.ends_with('\n') {
+            if read as u64 >= MAX_LINE_BYTES {
+                bail!("RESP reply line exceeds {MAX_LINE_BYTES} bytes");
+            }
+            bail!("connection closed mid-reply");
+        }
+
+        let line = line.trim_end_matches(['\r', '\n']).to_string();
+        if line.is_empty() {
+            bail!("empty RESP reply");
+        }
+
+        Ok(line)
+    }
+}
+
+#[monitor_kind(kind = "redis", api_version = "v1alpha1")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct V1Alpha1RedisMonitorSpec {
+    #[check(non_empty)]
+    pub host: String,
+    #[check(min = 1)]
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub database: Option<u32>,
+    #[check(non_empty)]
+    pub key: Option<String>,
+    pub expected_value: Option<String>,
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+}
+
+#[async_trait]
+impl MonitorSpec for V1Alpha1RedisMonitorSpec {
+    async fn build(self, _monitor: Monitor) -> Result<TaskPtr, Error> {
+        if self.expected_value.is_some() && self.key.is_none() {
+            bail!("spec.expected_value: requires a key");
+        }
+
+        Ok(Arc::new(RedisMonitor {
+            address: format!("{}:{}", self.host, self.port.unwrap_or(DEFAULT_PORT)),
+            username: self.username,
+            password: self.password,
+            database: self.database,
+            key: self.key,
+            expected_value: self.expected_value,
+            timeout: self.timeout.unwrap_or(DEFAULT_TIMEOUT),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::tasks::{test_monitor, TaskBuilder};
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+    use tokio::net::TcpListener;
+
+    /// Tiny RESP server understanding AUTH, SELECT, PING and GET.
+    async fn spawn_stand_in(
+        password: Option<&'static str>,
+        data: HashMap<&'static str, &'static str>,
+    ) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let data = data.clone();
+                tokio::spawn(async move {
+                    let mut conn = RespConnection::from_stream(stream);
+                    let mut authed = password.is_none();
+
+                    while let Ok(RespValue::Array(Some(args))) = conn.read_value().await {
+                        let args = args
+                            .into_iter()
+                            .map(|a| match a {
+                                RespValue::BulkString(Some(b)) => String::from_utf8(b).unwrap(),
+                                _ => String::new(),
+                            })
+                            .collect::<Vec<_>>();
+
+                        let reply = match args[0].as_str() {
+                            "AUTH" if Some(args.last().unwrap().as_str()) == password => {
+                                authed = true;
+                                "+OK\r\n".to_string()
+                            }
+                            "AUTH" => "-WRONGPASS invalid password\r\n".to_string(),
+                            _ if !authed => "-NOAUTH Authentication required.\r\n".to_string(),
+                            "SELECT" => "+OK\r\n".to_string(),
+                            "PING" => "+PONG\r\n".to_string(),
+                            "GET" => match data.get(args[1].as_str()) {
+                                Some(v) => format!("${}\r\n{}\r\n", v.len(), v),
+                                None => "$-1\r\n".to_string(),
+                            },
+                            _ => "-ERR unknown command\r\n".to_string(),
+                        };
+
+                        if conn.write_raw(reply.as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        addr
+    }
+
+    async fn survey(spec: serde_json::Value) -> MonitorStatus {
+        let monitor = test_monitor("redis", "v1alpha1", spec);
+        let task = V1Alpha1RedisMonitorBuilder {}.build(monitor).await.unwrap();
+        task.survey().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_ping_reports_latency() {
+        let addr = spawn_stand_in(None, HashMap::new()).await;
+
+        let status = survey(json!({ "host": "127.0.0.1", "port": addr.port() })).await;
+
+        match status {
+            MonitorStatus::Up { details, .. } => {
+                assert!(details.unwrap()["latency_ms"].is_number());
+            }
+            other => panic!("expected Up, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_auth_select_and_key_match() {
+        let addr = spawn_stand_in(Some("hunter2"), HashMap::from([("health", "ok")])).await;
+
+        let status = survey(json!({
+            "host": "127.0.0.1",
+            "port": addr.port(),
+            "username": "default",
+            "password": "hunter2",
+            "database": 2,
+            "key": "health",
+            "expected_value": "ok",
+        }))
+        .await;
+
+        assert!(matches!(status, MonitorStatus::Up { .. }), "{status:?}");
+    }
+
+    #[tokio::test]
+    async fn test_wrong_password_is_down() {
+        let addr = spawn_stand_in(Some("hunter2"), HashMap::new()).await;
+
+        let status = survey(json!({
+            "host": "127.0.0.1",
+            "port": addr.port(),
+            "password": "nope",
+        }))
+        .await;
+
+        match status {
+            MonitorStatus::Down { error_reason, .. } => assert!(error_reason.contains("AUTH")),
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_key_mismatch_and_missing_key_are_down() {
+        let addr = spawn_stand_in(None, HashMap::from([("health", "degraded")])).await;
+
+        let mismatch = survey(json!({
+            "host": "127.0.0.1",
+            "port": addr.port(),
+            "key": "health",
+            "expected_value": "ok",
+        }))
+        .await;
+        match mismatch {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert!(error_reason.contains("expected 'ok'"))
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+
+        let missing = survey(json!({
+            "host": "127.0.0.1",
+            "port": addr.port(),
+            "key": "missing",
+        }))
+        .await;
+        match missing {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert!(error_reason.contains("does not exist"))
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    /// Accepts one connection and answers the first command with `reply`.
+    async fn spawn_raw_reply(reply: Vec<u8>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut conn = RespConnection::from_stream(stream);
+            let _ = conn.read_value().await;
+            let _ = conn.write_raw(&reply).await;
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        });
+
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_oversized_and_malformed_replies_are_down() {
+        let cases = [
+            (b"$99999999999\r\n".to_vec(), "too large"),
+            (b"*99999999999\r\n".to_vec(), "too large"),
+            ("\u{e9}OK\r\n".as_bytes().to_vec(), "invalid RESP reply"),
+            (vec![b'+'; MAX_LINE_BYTES as usize + 1], "exceeds"),
+        ];
+
+        for (reply, expected) in cases {
+            let addr = spawn_raw_reply(reply).await;
+            let status = survey(json!({ "host": "127.0.0.1", "port": addr.port() })).await;
+
+            match status {
+                MonitorStatus::Down { error_reason, .. } => {
+                    assert!(error_reason.contains(expected), "{error_reason}")
+                }
+                other => panic!("expected Down, got {other:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unresponsive_server_times_out() {
+        // accepts the connection but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let status = survey(json!({
+            "host": "127.0.0.1",
+            "port": port,
+            "timeout": "100ms",
+        }))
+        .await;
+
+        match status {
+            MonitorStatus::Down { error_reason, .. } => assert!(error_reason.contains("timed out")),
+            other => panic!("expected Down, got {other:?}"),
+        }
+        drop(listener);
+    }
+}
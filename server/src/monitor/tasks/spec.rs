@@ -0,0 +1,228 @@
+use crate::monitor::tasks::heartbeat::HeartbeatRegistryPtr;
+use crate::monitor::tasks::{TaskBuilderPtr, TaskPtr};
+use anyhow::{anyhow, bail, Error};
+use app::types::Monitor;
+use app::DbFactoryPointer;
+use serde::de::DeserializeOwned;
+
+pub use inventory;
+pub use migration::async_trait::async_trait;
+
+/// A spec declared with `#[monitor_kind]`, building the task once parsed and
+/// validated.
+#[async_trait]
+pub trait MonitorSpec: DeserializeOwned + Send + Sized {
+    async fn build(self, monitor: Monitor) -> Result<TaskPtr, Error>;
+}
+
+/// A spec declared with `#[monitor_kind(state = T)]`, built with the `T` its
+/// builder holds.
+#[async_trait]
+pub trait StatefulMonitorSpec<T>: DeserializeOwned + Send + Sized {
+    async fn build(self, monitor: Monitor, state: &T) -> Result<TaskPtr, Error>;
+}
+
+/// Shared state handed to every `#[monitor_kind]` builder on registration.
+#[derive(Clone)]
+pub struct BuildContext {
+    pub db: DbFactoryPointer,
+    pub heartbeats: HeartbeatRegistryPtr,
+}
+
+/// State a `#[monitor_kind(state = T)]` builder takes from the context.
+pub trait KindState {
+    fn from_context(context: &BuildContext) -> Self;
+}
+
+impl KindState for DbFactoryPointer {
+    fn from_context(context: &BuildContext) -> Self {
+        context.db.clone()
+    }
+}
+
+impl KindState for HeartbeatRegistryPtr {
+    fn from_context(context: &BuildContext) -> Self {
+        context.heartbeats.clone()
+    }
+}
+
+/// Generated by `#[monitor_kind]` from the fields' `#[check(...)]`s.
+pub trait ValidateSpec {
+    fn validate(&self) -> Vec<FieldError>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Check for fields holding a regex, `#[check(with = valid_regex)]`.
+pub fn valid_regex(pattern: &str) -> Result<(), String> {
+    regex::Regex::new(pattern)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Deserializes and validates a spec, naming the offending field in errors
+/// (`spec.port: must be at least 1`).
+pub fn parse_spec<S: DeserializeOwned + ValidateSpec>(spec: serde_json::Value) -> Result<S, Error> {
+    let spec: S = serde_path_to_error::deserialize(spec).map_err(|e| {
+        let path = e.path().to_string();
+        match path.as_str() {
+            "." => anyhow!("spec: {}", e.inner()),
+            _ => anyhow!("spec.{path}: {}", e.inner()),
+        }
+    })?;
+
+    let errors = spec.validate();
+    if !errors.is_empty() {
+        let errors = errors
+            .iter()
+            .map(|e| format!("spec.{}: {}", e.field, e.message))
+            .collect::<Vec<_>>();
+        bail!("{}", errors.join(", "));
+    }
+    Ok(spec)
+}
+
+/// Submitted by every `#[monitor_kind]`.
+pub struct KindRegistration {
+    pub builder: fn(&BuildContext) -> TaskBuilderPtr,
+}
+
+inventory::collect!(KindRegistration);
+
+pub fn registered_builders(context: &BuildContext) -> Vec<TaskBuilderPtr> {
+    inventory::iter::<KindRegistration>
+        .into_iter()
+        .map(|registration| (registration.builder)(context))
+        .collect()
+}
+
+#[cfg(test)]
+pub(crate) async fn test_build_context() -> BuildContext {
+    BuildContext {
+        db: crate::db::get_db_factory(&None).await.unwrap(),
+        heartbeats: std::sync::Arc::new(crate::monitor::HeartbeatRegistry::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::tasks::{test_monitor, MonitorTask, TaskFactory};
+    use app::types::MonitorStatus;
+    use macros::monitor_kind;
+    use serde::Deserialize;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn no_spaces(value: &str) -> Result<(), String> {
+        match value.contains(' ') {
+            true => Err("must not contain spaces".to_string()),
+            false => Ok(()),
+        }
+    }
+
+    #[monitor_kind(kind = "spec-test", api_version = "v1alpha1")]
+    #[derive(Debug, Deserialize)]
+    struct V1Alpha1SpecTestMonitorSpec {
+        #[check(non_empty, with = no_spaces)]
+        name: String,
+        #[check(min = 1, max = 10)]
+        retries: u16,
+        #[serde(rename = "maxLag")]
+        #[check(max = 60)]
+        max_lag: Option<u64>,
+        #[serde(default)]
+        #[check(non_empty)]
+        tags: Vec<String>,
+    }
+
+    struct Noop;
+
+    #[async_trait]
+    impl MonitorTask for Noop {
+        async fn survey(&self) -> Result<MonitorStatus, Error> {
+            Ok(MonitorStatus::Unknown)
+        }
+    }
+
+    #[async_trait]
+    impl MonitorSpec for V1Alpha1SpecTestMonitorSpec {
+        async fn build(self, _monitor: Monitor) -> Result<TaskPtr, Error> {
+            Ok(Arc::new(Noop))
+        }
+    }
+
+    fn parse(spec: serde_json::Value) -> Result<V1Alpha1SpecTestMonitorSpec, String> {
+        parse_spec(spec).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_field_errors() {
+        let valid = parse(json!({ "name": "a", "retries": 3, "tags": ["x"] })).unwrap();
+        assert_eq!(valid.max_lag, None);
+
+        assert_eq!(
+            parse(json!({ "retries": 3 })).unwrap_err(),
+            "spec: missing field `name`"
+        );
+        assert!(parse(json!({ "name": "a", "retries": "three" }))
+            .unwrap_err()
+            .starts_with("spec.retries: invalid type"));
+        assert_eq!(
+            parse(json!({ "name": "", "retries": 0, "maxLag": 61, "tags": [] })).unwrap_err(),
+            "spec.name: must not be empty, spec.retries: must be at least 1, \
+             spec.maxLag: must be at most 60, spec.tags: must not be empty"
+        );
+        assert_eq!(
+            parse(json!({ "name": "a b", "retries": 11, "tags": ["x"] })).unwrap_err(),
+            "spec.name: must not contain spaces, spec.retries: must be at most 10"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_registration() {
+        let factory = TaskFactory::new();
+        factory
+            .bulk_register(registered_builders(&test_build_context().await))
+            .await;
+
+        let registrations = factory.list_registrations().await;
+        for kind in ["composite", "heartbeat", "host", "redis", "ssh"] {
+            assert!(
+                registrations.contains(&format!("{kind}/v1alpha1")),
+                "{kind}"
+            );
+        }
+
+        let monitor = test_monitor(
+            "spec-test",
+            "v1alpha1",
+            json!({ "name": "a", "retries": 1, "tags": ["x"] }),
+        );
+        assert!(factory.construct_task(&monitor).await.is_ok());
+
+        let monitor = test_monitor(
+            "spec-test",
+            "v1alpha1",
+            json!({ "name": "a", "retries": 0 }),
+        );
+        let error = factory.construct_task(&monitor).await.err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "spec.retries: must be at least 1, spec.tags: must not be empty"
+        );
+    }
+}
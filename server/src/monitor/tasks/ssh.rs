@@ -0,0 +1,734 @@
+use crate::monitor::tasks::spec::{valid_regex, MonitorSpec};
+use crate::monitor::tasks::{MonitorTask, TaskPtr};
+use anyhow::{anyhow, bail, Error};
+use app::types::{Monitor, MonitorStatus};
+use base64::Engine;
+use macros::monitor_kind;
+use migration::async_trait::async_trait;
+use regex::Regex;
+use ring::agreement::{self, EphemeralPrivateKey, UnparsedPublicKey, X25519};
+use ring::digest::{self, SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::signature::{self, EcdsaVerificationAlgorithm, RsaPublicKeyComponents};
+use sea_orm::sqlx::types::chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const CLIENT_BANNER: &str = "SSH-2.0-whoopsie";
+/// Servers may send other lines before the banner (RFC 4253 4.2).
+const MAX_PRE_BANNER_LINES: usize = 32;
+const MAX_BANNER_BYTES: u64 = 255;
+const MAX_PACKET_BYTES: usize = 35000;
+
+const MSG_DISCONNECT: u8 = 1;
+const MSG_IGNORE: u8 = 2;
+const MSG_DEBUG: u8 = 4;
+const MSG_KEXINIT: u8 = 20;
+const MSG_KEX_ECDH_INIT: u8 = 30;
+const MSG_KEX_ECDH_REPLY: u8 = 31;
+const DISCONNECT_BY_APPLICATION: u32 = 11;
+
+const KEX_ALGORITHMS: &[&str] = &["curve25519-sha256", "curve25519-sha256@libssh.org"];
+const HOST_KEY_ALGORITHMS: &[&str] = &[
+    "ssh-ed25519",
+    "ecdsa-sha2-nistp256",
+    "ecdsa-sha2-nistp384",
+    "rsa-sha2-512",
+    "rsa-sha2-256",
+];
+// never used, the connection is closed before NEWKEYS, but the server has to
+// find something in common to continue the exchange
+const CIPHERS: &[&str] = &[
+    "chacha20-poly1305@openssh.com",
+    "aes128-gcm@openssh.com",
+    "aes256-gcm@openssh.com",
+    "aes128-ctr",
+    "aes256-ctr",
+];
+const MACS: &[&str] = &["hmac-sha2-256-etm@openssh.com", "hmac-sha2-256"];
+
+/// Encodes the SSH wire types from RFC 4251 section 5.
+#[derive(Default)]
+struct SshWriter(Vec<u8>);
+
+impl SshWriter {
+    fn u8(mut self, value: u8) -> Self {
+        self.0.push(value);
+        self
+    }
+
+    fn u32(mut self, value: u32) -> Self {
+        self.0.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+
+    fn raw(mut self, bytes: &[u8]) -> Self {
+        self.0.extend_from_slice(bytes);
+        self
+    }
+
+    fn string(self, bytes: &[u8]) -> Self {
+        self.u32(bytes.len() as u32).raw(bytes)
+    }
+
+    fn name_list(self, names: &[&str]) -> Self {
+        self.string(names.join(",").as_bytes())
+    }
+
+    /// An unsigned big-endian integer as mpint.
+    fn mpint(self, bytes: &[u8]) -> Self {
+        let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+        let bytes = &bytes[start..];
+        if bytes.first().is_some_and(|b| b & 0x80 != 0) {
+            let mut padded = vec![0];
+            padded.extend_from_slice(bytes);
+            self.string(&padded)
+        } else {
+            self.string(bytes)
+        }
+    }
+}
+
+struct SshReader<'a>(&'a [u8]);
+
+impl<'a> SshReader<'a> {
+    fn raw(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < len {
+            bail!("truncated packet");
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_be_bytes(self.raw(4)?.try_into()?))
+    }
+
+    fn string(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.u32()? as usize;
+        self.raw(len)
+    }
+
+    fn name_list(&mut self) -> Result<Vec<&'a str>, Error> {
+        let list = std::str::from_utf8(self.string()?)?;
+        Ok(list.split(',').filter(|n| !n.is_empty()).collect())
+    }
+
+    /// An mpint as unsigned big-endian bytes without leading zeros.
+    fn mpint(&mut self) -> Result<&'a [u8], Error> {
+        let bytes = self.string()?;
+        let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+        Ok(&bytes[start..])
+    }
+}
+
+struct SshConnection {
+    stream: BufReader<TcpStream>,
+}
+
+impl SshConnection {
+    async fn read_banner(&mut self) -> Result<String, Error> {
+        for _ in 0..MAX_PRE_BANNER_LINES {
+            let mut line = String::new();
+            (&mut self.stream)
+                .take(MAX_BANNER_BYTES)
+                .read_line(&mut line)
+                .await?;
+            if line.is_empty() {
+                bail!("connection closed before the ssh banner");
+            }
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.starts_with("SSH-") {
+                if !line.starts_with("SSH-2.0-") && !line.starts_with("SSH-1.99-") {
+                    bail!("unsupported protocol version '{line}'");
+                }
+                return Ok(line.to_string());
+            }
+        }
+        bail!("no ssh banner received")
+    }
+
+    async fn write_banner(&mut self, banner: &str) -> Result<(), Error> {
+        let stream = self.stream.get_mut();
+        stream.write_all(format!("{banner}\r\n").as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Reads an unencrypted binary packet, skipping ignore and debug messages.
+    async fn read_packet(&mut self) -> Result<Vec<u8>, Error> {
+        loop {
+            let len = self.stream.read_u32().await? as usize;
+            if !(5..=MAX_PACKET_BYTES).contains(&len) {
+                bail!("invalid packet length {len}");
+            }
+            let mut packet = vec![0u8; len];
+            self.stream.read_exact(&mut packet).await?;
+
+            let padding = packet[0] as usize;
+            if padding + 1 >= len {
+                bail!("invalid packet padding {padding}");
+            }
+            let payload = packet[1..len - padding].to_vec();
+            match payload[0] {
+                MSG_IGNORE | MSG_DEBUG => continue,
+                MSG_DISCONNECT => {
+                    let mut reader = SshReader(&payload[1..]);
+                    let reason = reader.u32()?;
+                    let message = String::from_utf8_lossy(reader.string()?).into_owned();
+                    bail!("server disconnected ({reason}): {message}")
+                }
+                _ => return Ok(payload),
+            }
+        }
+    }
+
+    async fn write_packet(&mut self, payload: &[u8]) -> Result<(), Error> {
+        let mut padding = 8 - (5 + payload.len()) % 8;
+        if padding < 4 {
+            padding += 8;
+        }
+        let packet = SshWriter::default()
+            .u32((1 + payload.len() + padding) as u32)
+            .u8(padding as u8)
+            .raw(payload)
+            .raw(&vec![0u8; padding]);
+
+        let stream = self.stream.get_mut();
+        stream.write_all(&packet.0).await?;
+        stream.flush().await?;
+        Ok(())
+    }
+}
+
+fn kexinit(rng: &SystemRandom, host_key_algorithms: &[&str]) -> Result<Vec<u8>, Error> {
+    let mut cookie = [0u8; 16];
+    rng.fill(&mut cookie)
+        .map_err(|_| anyhow!("no randomness available"))?;
+    Ok(SshWriter::default()
+        .u8(MSG_KEXINIT)
+        .raw(&cookie)
+        .name_list(KEX_ALGORITHMS)
+        .name_list(host_key_algorithms)
+        .name_list(CIPHERS)
+        .name_list(CIPHERS)
+        .name_list(MACS)
+        .name_list(MACS)
+        .name_list(&["none"])
+        .name_list(&["none"])
+        .name_list(&[])
+        .name_list(&[])
+        .u8(0)
+        .u32(0)
+        .0)
+}
+
+/// Picks the first client algorithm the server also supports.
+fn negotiate<'a>(what: &str, client: &[&'a str], server: &[&str]) -> Result<&'a str, Error> {
+    client
+        .iter()
+        .find(|c| server.contains(c))
+        .copied()
+        .ok_or_else(|| anyhow!("no common {what}, server offers {}", server.join(",")))
+}
+
+/// Verifies the host key's signature of the exchange hash.
+fn verify_host_key(algorithm: &str, host_key: &[u8], hash: &[u8], sig: &[u8]) -> Result<(), Error> {
+    let mut sig = SshReader(sig);
+    let sig_algorithm = std::str::from_utf8(sig.string()?)?;
+    if sig_algorithm != algorithm {
+        bail!("signature uses {sig_algorithm}, expected {algorithm}");
+    }
+    let sig = sig.string()?;
+
+    let mut key = SshReader(host_key);
+    let key_type = std::str::from_utf8(key.string()?)?;
+    let verified = match algorithm {
+        "ssh-ed25519" if key_type == algorithm => {
+            signature::UnparsedPublicKey::new(&signature::ED25519, key.string()?).verify(hash, sig)
+        }
+        "ecdsa-sha2-nistp256" | "ecdsa-sha2-nistp384" if key_type == algorithm => {
+            let (verifier, width): (&'static EcdsaVerificationAlgorithm, usize) =
+                if algorithm == "ecdsa-sha2-nistp256" {
+                    (&signature::ECDSA_P256_SHA256_FIXED, 32)
+                } else {
+                    (&signature::ECDSA_P384_SHA384_FIXED, 48)
+                };
+            let _curve = key.string()?;
+            let point = key.string()?;
+
+            // the ssh signature is two mpints, ring wants r || s
+            let mut rs = SshReader(sig);
+            let mut fixed = vec![];
+            for part in [rs.mpint()?, rs.mpint()?] {
+                if part.len() > width {
+                    bail!("malformed ecdsa signature");
+                }
+                fixed.extend(std::iter::repeat_n(0, width - part.len()));
+                fixed.extend_from_slice(part);
+            }
+            signature::UnparsedPublicKey::new(verifier, point).verify(hash, &fixed)
+        }
+        "rsa-sha2-256" | "rsa-sha2-512" if key_type == "ssh-rsa" => {
+            let e = key.mpint()?;
+            let n = key.mpint()?;
+            let params = if algorithm == "rsa-sha2-256" {
+                &signature::RSA_PKCS1_2048_8192_SHA256
+            } else {
+                &signature::RSA_PKCS1_2048_8192_SHA512
+            };
+            RsaPublicKeyComponents { n, e }.verify(params, hash, sig)
+        }
+        _ => bail!("{key_type} host key doesn't match {algorithm}"),
+    };
+    verified.map_err(|_| anyhow!("host key signature is invalid"))
+}
+
+fn fingerprint(host_key: &[u8]) -> String {
+    let hash = digest::digest(&SHA256, host_key);
+    format!(
+        "SHA256:{}",
+        base64::engine::general_purpose::STANDARD_NO_PAD.encode(hash)
+    )
+}
+
+struct HostKey {
+    banner: String,
+    kex: String,
+    algorithm: String,
+    fingerprint: String,
+}
+
+struct SshMonitor {
+    host: String,
+    port: u16,
+    expect_banner: Option<Regex>,
+    fingerprint: Option<String>,
+    host_key_algorithms: Vec<String>,
+    timeout: Duration,
+}
+
+impl SshMonitor {
+    async fn fetch_host_key(&self) -> Result<HostKey, Error> {
+        let rng = SystemRandom::new();
+        let tcp = TcpStream::connect((self.host.as_str(), self.port)).await?;
+        let mut conn = SshConnection {
+            stream: BufReader::new(tcp),
+        };
+
+        conn.write_banner(CLIENT_BANNER).await?;
+        let banner = conn.read_banner().await?;
+
+        let host_key_algorithms = self
+            .host_key_algorithms
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        let client_kexinit = kexinit(&rng, &host_key_algorithms)?;
+        conn.write_packet(&client_kexinit).await?;
+
+        let server_kexinit = conn.read_packet().await?;
+        if server_kexinit[0] != MSG_KEXINIT {
+            bail!("expected KEXINIT, got message {}", server_kexinit[0]);
+        }
+        let mut reader = SshReader(&server_kexinit[1..]);
+        reader.raw(16)?;
+        let kex = negotiate("key exchange", KEX_ALGORITHMS, &reader.name_list()?)?;
+        let algorithm = negotiate(
+            "host key algorithm",
+            &host_key_algorithms,
+            &reader.name_list()?,
+        )?;
+
+        let private_key = EphemeralPrivateKey::generate(&X25519, &rng)
+            .map_err(|_| anyhow!("can't generate a key exchange key"))?;
+        let client_public = private_key
+            .compute_public_key()
+            .map_err(|_| anyhow!("can't generate a key exchange key"))?;
+        conn.write_packet(
+            &SshWriter::default()
+                .u8(MSG_KEX_ECDH_INIT)
+                .string(client_public.as_ref())
+                .0,
+        )
+        .await?;
+
+        let reply = conn.read_packet().await?;
+        if reply[0] != MSG_KEX_ECDH_REPLY {
+            bail!("expected KEX_ECDH_REPLY, got message {}", reply[0]);
+        }
+        let mut reader = SshReader(&reply[1..]);
+        let host_key = reader.string()?;
+        let server_public = reader.string()?;
+        let sig = reader.string()?;
+
+        let shared = agreement::agree_ephemeral(
+            private_key,
+            &UnparsedPublicKey::new(&X25519, server_public),
+            |secret| secret.to_vec(),
+        )
+        .map_err(|_| anyhow!("invalid key exchange reply"))?;
+        let exchange = SshWriter::default()
+            .string(CLIENT_BANNER.as_bytes())
+            .string(banner.as_bytes())
+            .string(&client_kexinit)
+            .string(&server_kexinit)
+            .string(host_key)
+            .string(client_public.as_ref())
+            .string(server_public)
+            .mpint(&shared);
+        let hash = digest::digest(&SHA256, &exchange.0);
+        verify_host_key(algorithm, host_key, hash.as_ref(), sig)?;
+
+        let _ = conn
+            .write_packet(
+                &SshWriter::default()
+                    .u8(MSG_DISCONNECT)
+                    .u32(DISCONNECT_BY_APPLICATION)
+                    .string(b"host key check done")
+                    .string(b"")
+                    .0,
+            )
+            .await;
+
+        Ok(HostKey {
+            banner,
+            kex: kex.to_string(),
+            algorithm: algorithm.to_string(),
+            fingerprint: fingerprint(host_key),
+        })
+    }
+
+    async fn check(&self) -> Result<MonitorStatus, Error> {
+        let started = Instant::now();
+        let key = self.fetch_host_key().await?;
+        let details = Some(serde_json::json!({
+            "banner": key.banner,
+            "kex": key.kex,
+            "host_key_algorithm": key.algorithm,
+            "fingerprint": key.fingerprint,
+            "handshake_ms": started.elapsed().as_secs_f64() * 1000.0,
+        }));
+
+        if let Some(expect) = &self.expect_banner {
+            if !expect.is_match(&key.banner) {
+                return Ok(MonitorStatus::Down {
+                    checked_at: Utc::now(),
+                    error_reason: format!("banner '{}' doesn't match /{expect}/", key.banner),
+                    details,
+                });
+            }
+        }
+        if let Some(pinned) = &self.fingerprint {
+            if pinned != &key.fingerprint {
+                return Ok(MonitorStatus::Down {
+                    checked_at: Utc::now(),
+                    error_reason: format!(
+                        "{} host key {} doesn't match the pinned {pinned}",
+                        key.algorithm, key.fingerprint
+                    ),
+                    details,
+                });
+            }
+        }
+
+        Ok(MonitorStatus::Up {
+            checked_at: Utc::now(),
+            details,
+        })
+    }
+}
+
+#[async_trait]
+impl MonitorTask for SshMonitor {
+    async fn survey(&self) -> Result<MonitorStatus, Error> {
+        match tokio::time::timeout(self.timeout, self.check()).await {
+            Ok(Ok(status)) => Ok(status),
+            Ok(Err(err)) => Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: err.to_string(),
+                details: None,
+            }),
+            Err(_) => Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: format!("timed out after {:?}", self.timeout),
+                details: None,
+            }),
+        }
+    }
+}
+
+#[monitor_kind(kind = "ssh", api_version = "v1alpha1")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct V1Alpha1SshMonitorSpec {
+    #[check(non_empty)]
+    pub host: String,
+    #[check(min = 1)]
+    pub port: Option<u16>,
+    /// Regex the server's `SSH-2.0-...` banner has to match.
+    #[check(with = valid_regex)]
+    pub expect_banner: Option<String>,
+    /// Pinned host key, as printed by `ssh-keygen -lf`, e.g. `SHA256:...`.
+    pub fingerprint: Option<String>,
+    /// Host key algorithms to ask for, in order of preference. Pin the
+    /// fingerprint of the key type the server will pick from this list.
+    #[check(non_empty, with = supported_algorithms)]
+    pub host_key_algorithms: Option<Vec<String>>,
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+}
+
+fn supported_algorithms(algorithms: &[String]) -> Result<(), String> {
+    match algorithms
+        .iter()
+        .find(|a| !HOST_KEY_ALGORITHMS.contains(&a.as_str()))
+    {
+        Some(unsupported) => Err(format!(
+            "unsupported host key algorithm '{unsupported}', expected one of {}",
+            HOST_KEY_ALGORITHMS.join(", ")
+        )),
+        None => Ok(()),
+    }
+}
+
+#[async_trait]
+impl MonitorSpec for V1Alpha1SshMonitorSpec {
+    async fn build(self, _monitor: Monitor) -> Result<TaskPtr, Error> {
+        let fingerprint = self.fingerprint.map(|f| {
+            let f = f.trim();
+            match f.strip_prefix("SHA256:") {
+                Some(_) => f.to_string(),
+                None => format!("SHA256:{f}"),
+            }
+        });
+        let host_key_algorithms = self
+            .host_key_algorithms
+            .unwrap_or_else(|| HOST_KEY_ALGORITHMS.iter().map(|a| a.to_string()).collect());
+
+        Ok(Arc::new(SshMonitor {
+            host: self.host,
+            port: self.port.unwrap_or(22),
+            expect_banner: self.expect_banner.as_deref().map(Regex::new).transpose()?,
+            fingerprint,
+            host_key_algorithms,
+            timeout: self.timeout.unwrap_or(DEFAULT_TIMEOUT),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::tasks::{test_monitor, TaskBuilder};
+    use ring::signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+    use serde_json::json;
+    use std::net::SocketAddr;
+    use tokio::net::TcpListener;
+
+    #[derive(Clone, Copy)]
+    enum TestKey {
+        Ed25519,
+        EcdsaP256,
+        /// Signs with a different key than the one it presents.
+        Forged,
+    }
+
+    struct TestServer {
+        addr: SocketAddr,
+        fingerprint: String,
+    }
+
+    fn ed25519_pair(rng: &SystemRandom) -> Ed25519KeyPair {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(rng).unwrap();
+        Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+    }
+
+    /// Minimal server side of the curve25519-sha256 exchange.
+    async fn spawn_ssh_server(key: TestKey) -> TestServer {
+        let rng = SystemRandom::new();
+        let ed25519 = Arc::new(ed25519_pair(&rng));
+        let forger = Arc::new(ed25519_pair(&rng));
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let ecdsa = Arc::new(
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+                .unwrap(),
+        );
+
+        let (algorithm, host_key) = match key {
+            TestKey::Ed25519 | TestKey::Forged => (
+                "ssh-ed25519",
+                SshWriter::default()
+                    .string(b"ssh-ed25519")
+                    .string(ed25519.public_key().as_ref())
+                    .0,
+            ),
+            TestKey::EcdsaP256 => (
+                "ecdsa-sha2-nistp256",
+                SshWriter::default()
+                    .string(b"ecdsa-sha2-nistp256")
+                    .string(b"nistp256")
+                    .string(ecdsa.public_key().as_ref())
+                    .0,
+            ),
+        };
+        let fingerprint = fingerprint(&host_key);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                let mut conn = SshConnection {
+                    stream: BufReader::new(socket),
+                };
+                let rng = SystemRandom::new();
+
+                let server_banner = "SSH-2.0-OpenSSH_9.6 test";
+                conn.write_banner("pre-banner noise").await.unwrap();
+                conn.write_banner(server_banner).await.unwrap();
+                let client_banner = conn.read_banner().await.unwrap();
+
+                let server_kexinit = kexinit(&rng, &[algorithm]).unwrap();
+                conn.write_packet(&server_kexinit).await.unwrap();
+                let client_kexinit = conn.read_packet().await.unwrap();
+
+                let init = conn.read_packet().await.unwrap();
+                let client_public = SshReader(&init[1..]).string().unwrap().to_vec();
+                let private_key = EphemeralPrivateKey::generate(&X25519, &rng).unwrap();
+                let server_public = private_key.compute_public_key().unwrap();
+                let shared = agreement::agree_ephemeral(
+                    private_key,
+                    &UnparsedPublicKey::new(&X25519, &client_public),
+                    |s| s.to_vec(),
+                )
+                .unwrap();
+
+                let exchange = SshWriter::default()
+                    .string(client_banner.as_bytes())
+                    .string(server_banner.as_bytes())
+                    .string(&client_kexinit)
+                    .string(&server_kexinit)
+                    .string(&host_key)
+                    .string(&client_public)
+                    .string(server_public.as_ref())
+                    .mpint(&shared);
+                let hash = digest::digest(&SHA256, &exchange.0);
+
+                let sig = match key {
+                    TestKey::Ed25519 => ed25519.sign(hash.as_ref()).as_ref().to_vec(),
+                    TestKey::Forged => forger.sign(hash.as_ref()).as_ref().to_vec(),
+                    TestKey::EcdsaP256 => {
+                        let fixed = ecdsa.sign(&rng, hash.as_ref()).unwrap();
+                        let (r, s) = fixed.as_ref().split_at(32);
+                        SshWriter::default().mpint(r).mpint(s).0
+                    }
+                };
+                let reply = SshWriter::default()
+                    .u8(MSG_KEX_ECDH_REPLY)
+                    .string(&host_key)
+                    .string(server_public.as_ref())
+                    .string(
+                        &SshWriter::default()
+                            .string(algorithm.as_bytes())
+                            .string(&sig)
+                            .0,
+                    );
+                conn.write_packet(&reply.0).await.unwrap();
+            }
+        });
+
+        TestServer { addr, fingerprint }
+    }
+
+    async fn survey(addr: SocketAddr, spec: serde_json::Value) -> MonitorStatus {
+        let mut spec = spec;
+        spec["host"] = json!("127.0.0.1");
+        spec["port"] = json!(addr.port());
+        let monitor = test_monitor("ssh", "v1alpha1", spec);
+        let task = V1Alpha1SshMonitorBuilder {}.build(monitor).await.unwrap();
+        task.survey().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_pinned_host_keys() {
+        for key in [TestKey::Ed25519, TestKey::EcdsaP256] {
+            let server = spawn_ssh_server(key).await;
+
+            let status = survey(
+                server.addr,
+                json!({ "fingerprint": server.fingerprint, "expect_banner": "^SSH-2.0-OpenSSH_9" }),
+            )
+            .await;
+            match status {
+                MonitorStatus::Up { details, .. } => {
+                    let details = details.unwrap();
+                    assert_eq!(details["fingerprint"], server.fingerprint);
+                    assert_eq!(details["kex"], "curve25519-sha256");
+                }
+                other => panic!("expected Up, got {other:?}"),
+            }
+
+            let pinned = "SHA256:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+            match survey(server.addr, json!({ "fingerprint": pinned })).await {
+                MonitorStatus::Down { error_reason, .. } => assert!(
+                    error_reason.ends_with(&format!(
+                        "host key {} doesn't match the pinned {pinned}",
+                        server.fingerprint
+                    )),
+                    "{error_reason}"
+                ),
+                other => panic!("expected Down, got {other:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forged_signature_is_down() {
+        let server = spawn_ssh_server(TestKey::Forged).await;
+
+        match survey(server.addr, json!({ "fingerprint": server.fingerprint })).await {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert_eq!(error_reason, "host key signature is invalid")
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_missing_banner_is_down() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut conn = SshConnection {
+                stream: BufReader::new(socket),
+            };
+            // consume the client banner so closing doesn't reset the connection
+            let _ = conn.read_banner().await;
+            conn.write_banner("HTTP/1.1 400 Bad Request\r\n")
+                .await
+                .unwrap();
+        });
+
+        match survey(addr, json!({})).await {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert_eq!(error_reason, "connection closed before the ssh banner")
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_mpint_encoding() {
+        assert_eq!(SshWriter::default().mpint(&[0, 0]).0, [0, 0, 0, 0]);
+        assert_eq!(
+            SshWriter::default().mpint(&[0, 0x80, 1]).0,
+            [0, 0, 0, 3, 0, 0x80, 1]
+        );
+        assert_eq!(SshReader(&[0, 0, 0, 2, 0, 0x7f]).mpint().unwrap(), [0x7f]);
+    }
+}
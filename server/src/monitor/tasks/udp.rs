@@ -0,0 +1,440 @@
+use crate::monitor::tasks::spec::{valid_regex, MonitorSpec};
+use crate::monitor::tasks::{MonitorTask, TaskPtr};
+use anyhow::{anyhow, bail, Error};
+use app::types::{Monitor, MonitorStatus};
+use macros::monitor_kind;
+use migration::async_trait::async_trait;
+use regex::bytes::Regex;
+use sea_orm::sqlx::types::chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::net::UdpSocket;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_DATAGRAM: usize = 65535;
+const MAX_PREVIEW_BYTES: usize = 64;
+/// Seconds between the NTP era (1900) and the unix epoch.
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum UdpPreset {
+    /// SNTP client request, the server has to answer with a synchronised
+    /// stratum.
+    Ntp,
+    /// Recursive `NS` query for `query`, the server has to answer NOERROR.
+    Dns,
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>, Error> {
+    let digits = hex
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<Vec<_>>();
+    if digits.len() % 2 != 0 {
+        bail!("hex payload has an odd number of digits");
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let byte = pair.iter().collect::<String>();
+            u8::from_str_radix(&byte, 16).map_err(|_| anyhow!("invalid hex byte '{byte}'"))
+        })
+        .collect()
+}
+
+fn preview(bytes: &[u8]) -> String {
+    let end = bytes.len().min(MAX_PREVIEW_BYTES);
+    let mut text = String::from_utf8_lossy(&bytes[..end])
+        .escape_debug()
+        .to_string();
+    if bytes.len() > end {
+        text.push_str("...");
+    }
+    text
+}
+
+fn ntp_request() -> Vec<u8> {
+    let mut packet = vec![0u8; 48];
+    // leap indicator 0, version 4, mode 3 (client)
+    packet[0] = 0x23;
+    packet
+}
+
+/// Checks an NTP reply, returning the server's stratum and its clock offset
+/// from ours in milliseconds.
+fn check_ntp(reply: &[u8]) -> Result<(u8, f64), Error> {
+    if reply.len() < 48 {
+        bail!("ntp reply is {} bytes, expected 48", reply.len());
+    }
+    if reply[0] & 0x07 != 4 {
+        bail!(
+            "ntp reply has mode {}, expected 4 (server)",
+            reply[0] & 0x07
+        );
+    }
+    let stratum = reply[1];
+    if stratum == 0 || stratum > 15 {
+        let code = String::from_utf8_lossy(&reply[12..16]).into_owned();
+        bail!("ntp server is unsynchronised (stratum {stratum}, {code})");
+    }
+
+    let seconds = u32::from_be_bytes(reply[40..44].try_into()?) as u64;
+    let fraction = u32::from_be_bytes(reply[44..48].try_into()?) as f64 / u32::MAX as f64;
+    let transmit = seconds.saturating_sub(NTP_UNIX_OFFSET) as f64 + fraction;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64();
+
+    Ok((stratum, (transmit - now) * 1000.0))
+}
+
+fn dns_query(id: u16, name: &str) -> Result<Vec<u8>, Error> {
+    let mut packet = id.to_be_bytes().to_vec();
+    // recursion desired, one question
+    packet.extend_from_slice(&[0x01, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0]);
+    for label in name.split('.').filter(|l| !l.is_empty()) {
+        if label.len() > 63 {
+            bail!("dns label '{label}' is longer than 63 bytes");
+        }
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    // root, type NS, class IN
+    packet.extend_from_slice(&[0, 0x00, 0x02, 0x00, 0x01]);
+    Ok(packet)
+}
+
+/// Checks a DNS reply, returning the number of answers.
+fn check_dns(id: u16, reply: &[u8]) -> Result<u16, Error> {
+    if reply.len() < 12 {
+        bail!("dns reply is {} bytes, expected at least 12", reply.len());
+    }
+    if reply[0..2] != id.to_be_bytes() {
+        bail!("dns reply has a different id");
+    }
+    if reply[2] & 0x80 == 0 {
+        bail!("dns reply isn't a response");
+    }
+    let rcode = match reply[3] & 0x0f {
+        0 => return Ok(u16::from_be_bytes([reply[6], reply[7]])),
+        1 => "FORMERR",
+        2 => "SERVFAIL",
+        3 => "NXDOMAIN",
+        4 => "NOTIMP",
+        5 => "REFUSED",
+        _ => "unknown error",
+    };
+    bail!("dns server answered {rcode}")
+}
+
+struct UdpMonitor {
+    address: String,
+    preset: Option<UdpPreset>,
+    query: String,
+    payload: Vec<u8>,
+    expect: Option<Regex>,
+    timeout: Duration,
+}
+
+impl UdpMonitor {
+    async fn exchange(&self, payload: &[u8]) -> Result<(Vec<u8>, Duration), Error> {
+        let target = tokio::net::lookup_host(&self.address)
+            .await?
+            .next()
+            .ok_or_else(|| anyhow!("{} didn't resolve", self.address))?;
+        let local = if target.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+
+        let socket = UdpSocket::bind(local).await?;
+        // a connected socket only accepts datagrams from the target and
+        // surfaces icmp port unreachable as an error
+        socket.connect(target).await?;
+
+        let started = Instant::now();
+        socket.send(payload).await?;
+        let mut buf = vec![0u8; MAX_DATAGRAM];
+        let len = socket.recv(&mut buf).await?;
+        buf.truncate(len);
+
+        Ok((buf, started.elapsed()))
+    }
+
+    async fn check(&self) -> Result<serde_json::Value, Error> {
+        let dns_id = rand_id();
+        let payload = match self.preset {
+            Some(UdpPreset::Ntp) => ntp_request(),
+            Some(UdpPreset::Dns) => dns_query(dns_id, &self.query)?,
+            None => self.payload.clone(),
+        };
+
+        let (reply, rtt) = self.exchange(&payload).await?;
+        let mut details = serde_json::json!({
+            "rtt_ms": rtt.as_secs_f64() * 1000.0,
+            "bytes": reply.len(),
+        });
+
+        match self.preset {
+            Some(UdpPreset::Ntp) => {
+                let (stratum, offset_ms) = check_ntp(&reply)?;
+                details["stratum"] = stratum.into();
+                details["offset_ms"] = offset_ms.into();
+            }
+            Some(UdpPreset::Dns) => {
+                details["answers"] = check_dns(dns_id, &reply)?.into();
+            }
+            None => {}
+        }
+
+        if let Some(expect) = &self.expect {
+            if !expect.is_match(&reply) {
+                bail!(
+                    "reply \"{}\" doesn't match /{}/",
+                    preview(&reply),
+                    expect.as_str()
+                );
+            }
+        }
+
+        Ok(details)
+    }
+}
+
+/// Query ids only have to differ between probes, not be unpredictable.
+fn rand_id() -> u16 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos() as u16)
+}
+
+#[async_trait]
+impl MonitorTask for UdpMonitor {
+    async fn survey(&self) -> Result<MonitorStatus, Error> {
+        // a lost datagram would otherwise be waited on forever
+        match tokio::time::timeout(self.timeout, self.check()).await {
+            Ok(Ok(details)) => Ok(MonitorStatus::Up {
+                checked_at: Utc::now(),
+                details: Some(details),
+            }),
+            Ok(Err(err)) => Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: err.to_string(),
+                details: None,
+            }),
+            Err(_) => Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: format!("no reply within {:?}", self.timeout),
+                details: None,
+            }),
+        }
+    }
+}
+
+#[monitor_kind(kind = "udp", api_version = "v1alpha1")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct V1Alpha1UdpMonitorSpec {
+    /// `host:port` to send the probe to.
+    #[check(non_empty)]
+    pub address: String,
+    /// A built-in probe, used instead of `payload` or `payload_hex`.
+    pub preset: Option<UdpPreset>,
+    /// Name queried by the `dns` preset, the root zone by default.
+    pub query: Option<String>,
+    pub payload: Option<String>,
+    /// Payload as hex digits, whitespace is ignored.
+    #[check(with = hex_payload)]
+    pub payload_hex: Option<String>,
+    /// Regex the reply has to match.
+    #[check(with = valid_regex)]
+    pub expect: Option<String>,
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+}
+
+fn hex_payload(hex: &str) -> Result<(), String> {
+    decode_hex(hex).map(|_| ()).map_err(|e| e.to_string())
+}
+
+#[async_trait]
+impl MonitorSpec for V1Alpha1UdpMonitorSpec {
+    async fn build(self, _monitor: Monitor) -> Result<TaskPtr, Error> {
+        let payload = match (&self.preset, self.payload, self.payload_hex) {
+            (Some(_), None, None) => vec![],
+            (None, Some(text), None) => text.into_bytes(),
+            (None, None, Some(hex)) => decode_hex(&hex)?,
+            _ => bail!("spec: requires exactly one of preset, payload or payload_hex"),
+        };
+        if payload.len() > MAX_DATAGRAM {
+            bail!("spec: payload doesn't fit in a datagram");
+        }
+        if self.query.is_some() && self.preset != Some(UdpPreset::Dns) {
+            bail!("spec.query: is only used by the dns preset");
+        }
+
+        Ok(Arc::new(UdpMonitor {
+            address: self.address,
+            preset: self.preset,
+            query: self.query.unwrap_or_default(),
+            payload,
+            expect: self.expect.as_deref().map(Regex::new).transpose()?,
+            timeout: self.timeout.unwrap_or(DEFAULT_TIMEOUT),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::tasks::{test_monitor, TaskBuilder};
+    use serde_json::json;
+    use std::net::SocketAddr;
+
+    /// Answers every datagram with whatever `respond` returns for it.
+    async fn spawn_responder(respond: fn(&[u8]) -> Option<Vec<u8>>) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; MAX_DATAGRAM];
+            loop {
+                let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+                if let Some(reply) = respond(&buf[..len]) {
+                    socket.send_to(&reply, peer).await.unwrap();
+                }
+            }
+        });
+        addr
+    }
+
+    async fn survey(addr: SocketAddr, spec: serde_json::Value) -> MonitorStatus {
+        let mut spec = spec;
+        spec["address"] = json!(addr.to_string());
+        let monitor = test_monitor("udp", "v1alpha1", spec);
+        let task = V1Alpha1UdpMonitorBuilder {}.build(monitor).await.unwrap();
+        task.survey().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_text_and_hex_payloads() {
+        let addr = spawn_responder(|req| match req {
+            b"status" => Some(b"OK players=3".to_vec()),
+            [0xff, 0xff, 0xff, 0xff, ..] => Some(b"\xff\xff\xff\xffinfoResponse".to_vec()),
+            _ => None,
+        })
+        .await;
+
+        let text = survey(
+            addr,
+            json!({ "payload": "status", "expect": "^OK players=\\d+" }),
+        )
+        .await;
+        assert!(matches!(text, MonitorStatus::Up { .. }), "{text:?}");
+
+        let hex = survey(
+            addr,
+            json!({ "payload_hex": "ff ff ff ff 67 65 74 69 6e 66 6f", "expect": "infoResponse" }),
+        )
+        .await;
+        assert!(matches!(hex, MonitorStatus::Up { .. }), "{hex:?}");
+
+        let mismatch = survey(addr, json!({ "payload": "status", "expect": "^ERR" })).await;
+        match mismatch {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert_eq!(error_reason, "reply \"OK players=3\" doesn't match /^ERR/")
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+
+        let silent = survey(addr, json!({ "payload": "hello", "timeout": "200ms" })).await;
+        match silent {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert!(error_reason.starts_with("no reply within"))
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ntp_preset() {
+        let addr = spawn_responder(|req| {
+            assert_eq!(req[0] & 0x07, 3);
+            let mut reply = vec![0u8; 48];
+            reply[0] = 0x24;
+            reply[1] = 2;
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+            let seconds = (now.as_secs() + NTP_UNIX_OFFSET) as u32;
+            reply[40..44].copy_from_slice(&seconds.to_be_bytes());
+            Some(reply)
+        })
+        .await;
+
+        match survey(addr, json!({ "preset": "ntp" })).await {
+            MonitorStatus::Up { details, .. } => {
+                let details = details.unwrap();
+                assert_eq!(details["stratum"], 2);
+                assert!(details["offset_ms"].as_f64().unwrap().abs() < 2000.0);
+            }
+            other => panic!("expected Up, got {other:?}"),
+        }
+
+        let unsynced = spawn_responder(|_| {
+            let mut reply = vec![0u8; 48];
+            reply[0] = 0x24;
+            reply[12..16].copy_from_slice(b"INIT");
+            Some(reply)
+        })
+        .await;
+        match survey(unsynced, json!({ "preset": "ntp" })).await {
+            MonitorStatus::Down { error_reason, .. } => assert_eq!(
+                error_reason,
+                "ntp server is unsynchronised (stratum 0, INIT)"
+            ),
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dns_preset() {
+        let addr = spawn_responder(|req| {
+            let mut reply = req.to_vec();
+            reply[2] |= 0x80;
+            // example.com is served, everything else is refused
+            if req.windows(7).any(|w| w == b"example") {
+                reply[7] = 1;
+            } else {
+                reply[3] = 5;
+            }
+            Some(reply)
+        })
+        .await;
+
+        match survey(addr, json!({ "preset": "dns", "query": "example.com" })).await {
+            MonitorStatus::Up { details, .. } => assert_eq!(details.unwrap()["answers"], 1),
+            other => panic!("expected Up, got {other:?}"),
+        }
+        match survey(addr, json!({ "preset": "dns" })).await {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert_eq!(error_reason, "dns server answered REFUSED")
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_specs_are_rejected() {
+        for spec in [
+            json!({ "address": "127.0.0.1:1" }),
+            json!({ "address": "127.0.0.1:1", "preset": "ntp", "payload": "x" }),
+            json!({ "address": "127.0.0.1:1", "payload_hex": "abc" }),
+            json!({ "address": "127.0.0.1:1", "payload_hex": "zz" }),
+            json!({ "address": "127.0.0.1:1", "preset": "ntp", "query": "example.com" }),
+        ] {
+            let monitor = test_monitor("udp", "v1alpha1", spec.clone());
+            assert!(
+                V1Alpha1UdpMonitorBuilder {}.build(monitor).await.is_err(),
+                "{spec}"
+            );
+        }
+    }
+}
@@ -0,0 +1,583 @@
+use crate::config::plugin_config::WasmPluginConfig;
+use crate::monitor::tasks::plugin::SurveyResult;
+use crate::monitor::tasks::{MonitorTask, TaskBuilder, TaskBuilderPtr, TaskPtr};
+use anyhow::{anyhow, bail, Error};
+use app::types::{Monitor, MonitorStatus};
+use migration::async_trait::async_trait;
+use sea_orm::sqlx::types::chrono::Utc;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use wasmtime::{
+    Caller, Config, Engine, Extern, InstancePre, Linker, Memory, Module, Store, StoreLimits,
+    StoreLimitsBuilder, Trap,
+};
+use wasmtime_wasi::p1::WasiP1Ctx;
+use wasmtime_wasi::p2::pipe::MemoryOutputPipe;
+use wasmtime_wasi::WasiCtxBuilder;
+
+// A plugin is a core wasm module built for wasm32-wasip1 exporting:
+//
+//   memory
+//   whoopsie_alloc(len: i32) -> i32
+//   whoopsie_survey(ptr: i32, len: i32) -> i64
+//
+// Every survey runs in a fresh instance. The host allocates room for a JSON
+// `{"name": ..., "labels": ..., "spec": ...}` object, writes it there and
+// calls whoopsie_survey, which returns the pointer (high 32 bits) and length
+// (low 32 bits) of a JSON survey result, the same one executable plugins
+// answer with.
+//
+// WASI comes without files, environment or arguments, stdout and stderr end
+// up in the server log. Network access goes through the functions imported
+// from the `whoopsie` module and is limited to the configured allowed_hosts.
+// All of them return -1 on failure, leaving a message for last_error:
+//
+//   log(ptr, len)
+//   tcp_connect(ptr, len) -> handle, address as "host:port"
+//   tcp_read(handle, ptr, len) -> bytes read, 0 at end of stream
+//   tcp_write(handle, ptr, len) -> bytes written
+//   tcp_close(handle)
+//   last_error(ptr, len) -> full length of the message, copying what fits
+const HOST_MODULE: &str = "whoopsie";
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_MAX_MEMORY: usize = 64 * 1024 * 1024;
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+const MAX_OUTPUT_BYTES: usize = 64 * 1024;
+const MAX_RESULT_BYTES: usize = 1024 * 1024;
+const MAX_READ_BYTES: usize = 64 * 1024;
+
+/// Loads the module and returns a builder for the kind it's configured as.
+pub fn get_builders(config: &WasmPluginConfig) -> Result<Vec<TaskBuilderPtr>, Error> {
+    if let Some(host) = config.allowed_hosts.iter().find(|h| {
+        h.rsplit_once(':')
+            .is_none_or(|(_, port)| port.parse::<u16>().is_err())
+    }) {
+        bail!("allowed host '{host}' isn't a host:port pair");
+    }
+
+    let engine = engine()?;
+    let module = Module::from_file(engine, &config.module)
+        .map_err(|e| anyhow!("can't load {}: {e:#}", config.module.display()))?;
+
+    let mut linker = Linker::<Sandbox>::new(engine);
+    wasmtime_wasi::p1::add_to_linker_sync(&mut linker, |sandbox| &mut sandbox.wasi)?;
+    add_host_functions(&mut linker)?;
+    // fails for imports that aren't granted
+    let instance_pre = linker.instantiate_pre(&module)?;
+
+    for export in ["memory", "whoopsie_alloc", "whoopsie_survey"] {
+        if module.get_export(export).is_none() {
+            bail!("{} doesn't export {export}", config.module.display());
+        }
+    }
+
+    let plugin = Arc::new(WasmPlugin {
+        config: config.clone(),
+        instance_pre,
+    });
+    Ok(vec![Arc::new(WasmTaskBuilder { plugin })])
+}
+
+/// The engine shared by all plugins, with a thread advancing its epoch so
+/// runaway modules can be interrupted.
+fn engine() -> Result<&'static Engine, Error> {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    if let Some(engine) = ENGINE.get() {
+        return Ok(engine);
+    }
+
+    let engine = Engine::new(Config::new().epoch_interruption(true))?;
+    Ok(ENGINE.get_or_init(|| {
+        let ticker = engine.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(EPOCH_TICK);
+            ticker.increment_epoch();
+        });
+        engine
+    }))
+}
+
+/// What a module is allowed to do besides computing.
+struct Capabilities {
+    allowed_hosts: Vec<String>,
+    deadline: Instant,
+    sockets: HashMap<i32, TcpStream>,
+    next_socket: i32,
+}
+
+impl Capabilities {
+    fn remaining(&self) -> Result<Duration, String> {
+        self.deadline
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| !remaining.is_zero())
+            .ok_or_else(|| "out of time".to_string())
+    }
+
+    fn connect(&mut self, address: &str) -> Result<i32, String> {
+        if !self.allowed_hosts.iter().any(|host| host == address) {
+            return Err(format!("{address} isn't in allowed_hosts"));
+        }
+
+        let remaining = self.remaining()?;
+        let addrs = address
+            .to_socket_addrs()
+            .map_err(|e| format!("can't resolve {address}: {e}"))?;
+        let mut last_error = format!("{address} has no addresses");
+        for addr in addrs {
+            match TcpStream::connect_timeout(&addr, remaining) {
+                Ok(stream) => {
+                    let handle = self.next_socket;
+                    self.next_socket += 1;
+                    self.sockets.insert(handle, stream);
+                    return Ok(handle);
+                }
+                Err(e) => last_error = format!("can't connect to {address}: {e}"),
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Returns the socket with its timeouts cut to what's left of the survey.
+    fn socket(&mut self, handle: i32) -> Result<&mut TcpStream, String> {
+        let remaining = self.remaining()?;
+        let socket = self
+            .sockets
+            .get_mut(&handle)
+            .ok_or_else(|| format!("no socket {handle}"))?;
+        socket
+            .set_read_timeout(Some(remaining))
+            .and_then(|_| socket.set_write_timeout(Some(remaining)))
+            .map_err(|e| e.to_string())?;
+        Ok(socket)
+    }
+
+    fn read(&mut self, handle: i32, len: usize) -> Result<Vec<u8>, String> {
+        let mut buf = vec![0u8; len.min(MAX_READ_BYTES)];
+        let read = self
+            .socket(handle)?
+            .read(&mut buf)
+            .map_err(|e| e.to_string())?;
+        buf.truncate(read);
+        Ok(buf)
+    }
+
+    fn write(&mut self, handle: i32, data: &[u8]) -> Result<usize, String> {
+        self.socket(handle)?.write(data).map_err(|e| e.to_string())
+    }
+}
+
+struct Sandbox {
+    wasi: WasiP1Ctx,
+    limits: StoreLimits,
+    name: String,
+    capabilities: Capabilities,
+    last_error: String,
+}
+
+impl Sandbox {
+    fn fallible<T>(&mut self, result: Result<T, String>) -> Option<T> {
+        result.map_err(|e| self.last_error = e).ok()
+    }
+}
+
+fn memory(caller: &mut Caller<'_, Sandbox>) -> wasmtime::Result<Memory> {
+    caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| wasmtime::format_err!("module doesn't export memory"))
+}
+
+fn read_guest(caller: &mut Caller<'_, Sandbox>, ptr: u32, len: u32) -> wasmtime::Result<Vec<u8>> {
+    let memory = memory(caller)?;
+    // checked before allocating, the length comes from the guest
+    if ptr as usize + len as usize > memory.data_size(&caller) {
+        wasmtime::bail!("out of bounds memory access");
+    }
+    let mut buf = vec![0u8; len as usize];
+    memory.read(&caller, ptr as usize, &mut buf)?;
+    Ok(buf)
+}
+
+fn write_guest(caller: &mut Caller<'_, Sandbox>, ptr: u32, data: &[u8]) -> wasmtime::Result<()> {
+    let memory = memory(caller)?;
+    memory.write(caller, ptr as usize, data)?;
+    Ok(())
+}
+
+fn add_host_functions(linker: &mut Linker<Sandbox>) -> wasmtime::Result<()> {
+    linker.func_wrap(
+        HOST_MODULE,
+        "log",
+        |mut caller: Caller<'_, Sandbox>, ptr: u32, len: u32| {
+            let message = read_guest(&mut caller, ptr, len)?;
+            log::info!(
+                "wasm plugin {}: {}",
+                caller.data().name,
+                String::from_utf8_lossy(&message)
+            );
+            Ok(())
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "tcp_connect",
+        |mut caller: Caller<'_, Sandbox>, ptr: u32, len: u32| {
+            let address = String::from_utf8_lossy(&read_guest(&mut caller, ptr, len)?).into_owned();
+            let sandbox = caller.data_mut();
+            let result = sandbox.capabilities.connect(&address);
+            Ok(sandbox.fallible(result).unwrap_or(-1))
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "tcp_read",
+        |mut caller: Caller<'_, Sandbox>, handle: i32, ptr: u32, len: u32| {
+            let sandbox = caller.data_mut();
+            let result = sandbox.capabilities.read(handle, len as usize);
+            let Some(data) = sandbox.fallible(result) else {
+                return Ok(-1);
+            };
+            write_guest(&mut caller, ptr, &data)?;
+            Ok(data.len() as i32)
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "tcp_write",
+        |mut caller: Caller<'_, Sandbox>, handle: i32, ptr: u32, len: u32| {
+            let data = read_guest(&mut caller, ptr, len)?;
+            let sandbox = caller.data_mut();
+            let result = sandbox.capabilities.write(handle, &data);
+            Ok(sandbox
+                .fallible(result)
+                .map_or(-1, |written| written as i32))
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "tcp_close",
+        |mut caller: Caller<'_, Sandbox>, handle: i32| {
+            caller.data_mut().capabilities.sockets.remove(&handle);
+        },
+    )?;
+
+    linker.func_wrap(
+        HOST_MODULE,
+        "last_error",
+        |mut caller: Caller<'_, Sandbox>, ptr: u32, len: u32| {
+            let message = caller.data().last_error.clone().into_bytes();
+            let copied = message.len().min(len as usize);
+            write_guest(&mut caller, ptr, &message[..copied])?;
+            Ok(message.len() as i32)
+        },
+    )?;
+
+    Ok(())
+}
+
+struct WasmPlugin {
+    config: WasmPluginConfig,
+    instance_pre: InstancePre<Sandbox>,
+}
+
+impl Debug for WasmPlugin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WasmPlugin")
+            .field("name", &self.config.name)
+            .field("module", &self.config.module)
+            .finish()
+    }
+}
+
+impl WasmPlugin {
+    fn timeout(&self) -> Duration {
+        self.config.timeout.unwrap_or(DEFAULT_TIMEOUT)
+    }
+
+    fn run(&self, input: &[u8]) -> Result<SurveyResult, Error> {
+        let output = MemoryOutputPipe::new(MAX_OUTPUT_BYTES);
+        let wasi = WasiCtxBuilder::new()
+            .stdout(output.clone())
+            .stderr(output.clone())
+            .build_p1();
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.config.max_memory.unwrap_or(DEFAULT_MAX_MEMORY))
+            .instances(1)
+            .build();
+        let timeout = self.timeout();
+        let mut store = Store::new(
+            self.instance_pre.module().engine(),
+            Sandbox {
+                wasi,
+                limits,
+                name: self.config.name.clone(),
+                capabilities: Capabilities {
+                    allowed_hosts: self.config.allowed_hosts.clone(),
+                    deadline: Instant::now() + timeout,
+                    sockets: HashMap::new(),
+                    next_socket: 1,
+                },
+                last_error: String::new(),
+            },
+        );
+        store.limiter(|sandbox| &mut sandbox.limits);
+        store.set_epoch_deadline((timeout.as_millis() / EPOCH_TICK.as_millis()) as u64 + 1);
+        store.epoch_deadline_trap();
+
+        let result = self.call(&mut store, input);
+        for line in String::from_utf8_lossy(&output.contents()).lines() {
+            log::info!("wasm plugin {}: {line}", self.config.name);
+        }
+
+        let result = match result {
+            Ok(result) => result,
+            Err(e) if e.downcast_ref::<Trap>() == Some(&Trap::Interrupt) => {
+                bail!("timed out after {timeout:?}")
+            }
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_slice::<SurveyResult>(&result)
+            .map_err(|e| anyhow!("module returned an invalid survey result: {e}"))
+    }
+
+    fn call(&self, store: &mut Store<Sandbox>, input: &[u8]) -> wasmtime::Result<Vec<u8>> {
+        let instance = self.instance_pre.instantiate(&mut *store)?;
+        // reactors built by rustc need their constructors run first
+        if let Ok(initialize) = instance.get_typed_func::<(), ()>(&mut *store, "_initialize") {
+            initialize.call(&mut *store, ())?;
+        }
+
+        let memory = instance
+            .get_memory(&mut *store, "memory")
+            .ok_or_else(|| wasmtime::format_err!("module doesn't export memory"))?;
+        let alloc = instance.get_typed_func::<u32, u32>(&mut *store, "whoopsie_alloc")?;
+        let survey = instance.get_typed_func::<(u32, u32), u64>(&mut *store, "whoopsie_survey")?;
+
+        let ptr = alloc.call(&mut *store, input.len() as u32)?;
+        memory.write(&mut *store, ptr as usize, input)?;
+        let packed = survey.call(&mut *store, (ptr, input.len() as u32))?;
+
+        let (ptr, len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+        if len > MAX_RESULT_BYTES {
+            wasmtime::bail!("survey result of {len} bytes is too large");
+        }
+        let mut result = vec![0u8; len];
+        memory.read(&*store, ptr, &mut result)?;
+        Ok(result)
+    }
+}
+
+#[derive(Clone)]
+struct WasmTask {
+    plugin: Arc<WasmPlugin>,
+    input: Arc<Vec<u8>>,
+}
+
+#[async_trait]
+impl MonitorTask for WasmTask {
+    async fn survey(&self) -> Result<MonitorStatus, Error> {
+        let task = self.clone();
+        match tokio::task::spawn_blocking(move || task.plugin.run(&task.input)).await? {
+            Ok(result) => Ok(result.into_status()),
+            Err(err) => Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: format!("{err:#}"),
+                details: None,
+            }),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct WasmTaskBuilder {
+    plugin: Arc<WasmPlugin>,
+}
+
+#[async_trait]
+impl TaskBuilder for WasmTaskBuilder {
+    fn get_api_version(&self) -> String {
+        self.plugin.config.api_version.clone()
+    }
+
+    fn get_kind(&self) -> String {
+        self.plugin.config.kind.clone()
+    }
+
+    async fn build(&self, monitor: Monitor) -> Result<TaskPtr, Error> {
+        let input = serde_json::to_vec(&serde_json::json!({
+            "name": monitor.name,
+            "labels": monitor.labels,
+            "spec": monitor.spec,
+        }))?;
+
+        Ok(Arc::new(WasmTask {
+            plugin: self.plugin.clone(),
+            input: Arc::new(input),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::tasks::test_monitor;
+    use serde_json::json;
+    use std::net::TcpListener;
+    use std::path::{Path, PathBuf};
+    use tempfile::TempDir;
+
+    fn plugin_config(module: PathBuf, allowed_hosts: Vec<String>) -> WasmPluginConfig {
+        WasmPluginConfig {
+            name: "test".to_string(),
+            module,
+            kind: "sandboxed".to_string(),
+            api_version: "v1alpha1".to_string(),
+            allowed_hosts,
+            timeout: Some(Duration::from_millis(300)),
+            max_memory: None,
+        }
+    }
+
+    fn wat_plugin(dir: &TempDir, survey_body: &str, imports: &str) -> WasmPluginConfig {
+        let result = r#"{"status":"up","details":{"answer":42}}"#;
+        let wat = format!(
+            r#"(module
+                {imports}
+                (memory (export "memory") 1)
+                (data (i32.const 1024) "{}")
+                (func (export "whoopsie_alloc") (param i32) (result i32) (i32.const 2048))
+                (func (export "whoopsie_survey") (param i32 i32) (result i64)
+                    {survey_body}
+                    (i64.or (i64.shl (i64.const 1024) (i64.const 32)) (i64.const {}))))"#,
+            result.replace('"', "\\\""),
+            result.len()
+        );
+        let path = dir.path().join("plugin.wat");
+        std::fs::write(&path, wat).unwrap();
+        plugin_config(path, vec![])
+    }
+
+    async fn survey(config: &WasmPluginConfig, spec: serde_json::Value) -> MonitorStatus {
+        let builders = get_builders(config).unwrap();
+        let monitor = test_monitor("sandboxed", "v1alpha1", spec);
+        let task = builders[0].build(monitor).await.unwrap();
+        task.survey().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_survey_and_timeout() {
+        let dir = TempDir::new().unwrap();
+
+        match survey(&wat_plugin(&dir, "", ""), json!({})).await {
+            MonitorStatus::Up { details, .. } => assert_eq!(details.unwrap()["answer"], 42),
+            other => panic!("expected Up, got {other:?}"),
+        }
+
+        let spinning = wat_plugin(&dir, "(loop $spin (br $spin))", "");
+        match survey(&spinning, json!({})).await {
+            MonitorStatus::Down { error_reason, .. } => {
+                assert_eq!(error_reason, "timed out after 300ms")
+            }
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_ungranted_imports() {
+        let dir = TempDir::new().unwrap();
+        let config = wat_plugin(
+            &dir,
+            "",
+            r#"(import "whoopsie" "exec" (func $exec (param i32 i32) (result i32)))"#,
+        );
+        let err = get_builders(&config).err().unwrap().to_string();
+        assert!(err.contains("unknown import: `whoopsie::exec`"), "{err}");
+    }
+
+    #[test]
+    fn test_allowed_hosts() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let allowed = listener.local_addr().unwrap().to_string();
+        let mut capabilities = Capabilities {
+            allowed_hosts: vec![allowed.clone()],
+            deadline: Instant::now() + Duration::from_secs(5),
+            sockets: HashMap::new(),
+            next_socket: 1,
+        };
+
+        assert_eq!(
+            capabilities.connect("127.0.0.1:9"),
+            Err("127.0.0.1:9 isn't in allowed_hosts".to_string())
+        );
+
+        let handle = capabilities.connect(&allowed).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        server.write_all(b"220 ready\r\n").unwrap();
+        assert_eq!(capabilities.read(handle, 1024).unwrap(), b"220 ready\r\n");
+        assert_eq!(capabilities.write(handle, b"QUIT\r\n").unwrap(), 6);
+    }
+
+    /// Builds plugins/examples/tcp-banner with the guest SDK.
+    #[tokio::test]
+    #[ignore = "needs the wasm32-wasip1 target (rustup target add wasm32-wasip1)"]
+    async fn test_example_plugin() {
+        let dir = TempDir::new().unwrap();
+        let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("../plugins/Cargo.toml");
+        let status = std::process::Command::new(std::env::var("CARGO").unwrap_or("cargo".into()))
+            .args([
+                "build",
+                "--release",
+                "--target",
+                "wasm32-wasip1",
+                "-p",
+                "tcp-banner",
+            ])
+            .arg("--manifest-path")
+            .arg(&manifest)
+            .arg("--target-dir")
+            .arg(dir.path())
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let module = dir.path().join("wasm32-wasip1/release/tcp_banner.wasm");
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(b"SSH-2.0-OpenSSH_9.6\r\n").unwrap();
+        });
+
+        let mut config = plugin_config(module, vec![address.clone()]);
+        config.timeout = Some(Duration::from_secs(5));
+
+        match survey(&config, json!({ "address": address, "expect": "OpenSSH" })).await {
+            MonitorStatus::Up { details, .. } => {
+                assert_eq!(details.unwrap()["banner"], "SSH-2.0-OpenSSH_9.6")
+            }
+            other => panic!("expected Up, got {other:?}"),
+        }
+
+        match survey(
+            &config,
+            json!({ "address": "127.0.0.1:9", "expect": "OpenSSH" }),
+        )
+        .await
+        {
+            MonitorStatus::Down { error_reason, .. } => assert_eq!(
+                error_reason,
+                "127.0.0.1:9: 127.0.0.1:9 isn't in allowed_hosts"
+            ),
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use crate::monitor::tasks::spec::{valid_regex, MonitorSpec};
+use crate::monitor::tasks::{MonitorTask, TaskPtr};
+use anyhow::{bail, Error};
+use app::types::{Monitor, MonitorStatus};
+use futures::{SinkExt, StreamExt};
+use macros::monitor_kind;
+use migration::async_trait::async_trait;
+use regex::Regex;
+use sea_orm::sqlx::types::chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::{HeaderName, HeaderValue};
+use tokio_tungstenite::tungstenite::Message;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct WebSocketMonitor {
+    uri: String,
+    headers: BTreeMap<String, String>,
+    send: Option<String>,
+    expect: Option<Regex>,
+    timeout: Duration,
+}
+
+struct Timings {
+    handshake: Duration,
+    round_trip: Option<Duration>,
+}
+
+impl WebSocketMonitor {
+    async fn check(&self) -> Result<Timings, Error> {
+        let mut request = self.uri.as_str().into_client_request()?;
+        for (name, value) in &self.headers {
+            request.headers_mut().insert(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+
+        let started = Instant::now();
+        let (mut socket, _) = tokio_tungstenite::connect_async(request).await?;
+        let handshake = started.elapsed();
+
+        let mut round_trip = None;
+        if let Some(message) = &self.send {
+            let sent = Instant::now();
+            socket.send(Message::text(message.as_str())).await?;
+
+            loop {
+                let reply = match socket.next().await {
+                    Some(reply) => reply?,
+                    None => bail!("connection closed before a matching reply"),
+                };
+                let text = match reply {
+                    Message::Text(text) => text.to_string(),
+                    Message::Binary(data) => String::from_utf8_lossy(&data).into_owned(),
+                    Message::Close(frame) => {
+                        bail!("connection closed before a matching reply: {frame:?}")
+                    }
+                    _ => continue,
+                };
+
+                if self.expect.as_ref().is_none_or(|r| r.is_match(&text)) {
+                    round_trip = Some(sent.elapsed());
+                    break;
+                }
+            }
+        }
+
+        // a failed close doesn't make the endpoint unhealthy
+        let _ = socket.close(None).await;
+
+        Ok(Timings {
+            handshake,
+            round_trip,
+        })
+    }
+}
+
+#[async_trait]
+impl MonitorTask for WebSocketMonitor {
+    async fn survey(&self) -> Result<MonitorStatus, Error> {
+        match tokio::time::timeout(self.timeout, self.check()).await {
+            Ok(Ok(timings)) => Ok(MonitorStatus::Up {
+                checked_at: Utc::now(),
+                details: Some(serde_json::json!({
+                    "handshake_ms": timings.handshake.as_secs_f64() * 1000.0,
+                    "round_trip_ms": timings.round_trip.map(|d| d.as_secs_f64() * 1000.0),
+                })),
+            }),
+            Ok(Err(err)) => Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: err.to_string(),
+                details: None,
+            }),
+            Err(_) => Ok(MonitorStatus::Down {
+                checked_at: Utc::now(),
+                error_reason: format!("timed out after {:?}", self.timeout),
+                details: None,
+            }),
+        }
+    }
+}
+
+#[monitor_kind(kind = "websocket", api_version = "v1alpha1")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct V1Alpha1WebSocketMonitorSpec {
+    /// `ws://` or `wss://`.
+    #[check(with = ws_uri)]
+    pub uri: String,
+    /// Extra headers sent with the upgrade request.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Text message sent once the connection is open.
+    pub send: Option<String>,
+    /// Regex a reply has to match; any reply is accepted if unset.
+    #[check(with = valid_regex)]
+    pub expect: Option<String>,
+    #[serde(default, with = "humantime_serde")]
+    pub timeout: Option<Duration>,
+}
+
+fn ws_uri(uri: &str) -> Result<(), String> {
+    match uri.starts_with("ws://") || uri.starts_with("wss://") {
+        true => Ok(()),
+        false => Err("must use ws or wss".to_string()),
+    }
+}
+
+#[async_trait]
+impl MonitorSpec for V1Alpha1WebSocketMonitorSpec {
+    async fn build(self, _monitor: Monitor) -> Result<TaskPtr, Error> {
+        if self.expect.is_some() && self.send.is_none() {
+            bail!("spec.expect: requires a message to send");
+        }
+
+        Ok(Arc::new(WebSocketMonitor {
+            uri: self.uri,
+            headers: self.headers,
+            send: self.send,
+            expect: self.expect.as_deref().map(Regex::new).transpose()?,
+            timeout: self.timeout.unwrap_or(DEFAULT_TIMEOUT),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::tasks::{test_monitor, TaskBuilder};
+    use axum::extract::ws::{Message as AxumMessage, WebSocket, WebSocketUpgrade};
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::response::{IntoResponse, Response};
+    use axum::routing::get;
+    use axum::Router;
+    use serde_json::json;
+    use std::net::SocketAddr;
+    use tokio::net::TcpListener;
+
+    async fn echo(headers: HeaderMap, ws: WebSocketUpgrade) -> Response {
+        if headers.get("x-api-key").is_none_or(|k| k != "letmein") {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+        ws.on_upgrade(|mut socket: WebSocket| async move {
+            while let Some(Ok(msg)) = socket.recv().await {
+                if let AxumMessage::Text(text) = msg {
+                    let _ = socket
+                        .send(AxumMessage::Text(format!("echo: {text}").into()))
+                        .await;
+                }
+            }
+        })
+    }
+
+    async fn spawn_echo_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/ws", get(echo));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        addr
+    }
+
+    async fn survey(spec: serde_json::Value) -> MonitorStatus {
+        let monitor = test_monitor("websocket", "v1alpha1", spec);
+        let task = V1Alpha1WebSocketMonitorBuilder {}
+            .build(monitor)
+            .await
+            .unwrap();
+        task.survey().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_echo_round_trip() {
+        let addr = spawn_echo_server().await;
+
+        let status = survey(json!({
+            "uri": format!("ws://{addr}/ws"),
+            "headers": { "X-Api-Key": "letmein" },
+            "send": "ping",
+            "expect": "^echo: ping$",
+        }))
+        .await;
+
+        match status {
+            MonitorStatus::Up { details, .. } => {
+                let details = details.unwrap();
+                assert!(details["handshake_ms"].is_number());
+                assert!(details["round_trip_ms"].is_number());
+            }
+            other => panic!("expected Up, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejected_handshake_is_down() {
+        let addr = spawn_echo_server().await;
+
+        let status = survey(json!({ "uri": format!("ws://{addr}/ws") })).await;
+
+        match status {
+            MonitorStatus::Down { error_reason, .. } => assert!(error_reason.contains("401")),
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unmatched_reply_times_out() {
+        let addr = spawn_echo_server().await;
+
+        let status = survey(json!({
+            "uri": format!("ws://{addr}/ws"),
+            "headers": { "X-Api-Key": "letmein" },
+            "send": "ping",
+            "expect": "pong",
+            "timeout": "200ms",
+        }))
+        .await;
+
+        match status {
+            MonitorStatus::Down { error_reason, .. } => assert!(error_reason.contains("timed out")),
+            other => panic!("expected Down, got {other:?}"),
+        }
+    }
+}